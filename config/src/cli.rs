@@ -0,0 +1,179 @@
+/*
+config/src/cli.rs
+10/18/26
+
+This file defines the command line interface.
+Options that can also come from the .env name their variable.
+*/
+use chrono::{ DateTime, Utc };
+use clap::{ Parser, Subcommand };
+use models::{ Bucket, InvoiceStatus, ReportWindow, UnreferencedSort, timestamp };
+
+// Command line structure
+#[derive(Debug, Parser)]
+#[command(name = "kobayashi-maru", about = "Detects valid deposits in listsinceblock data")]
+pub struct Cli {
+    /// Keep running, ingesting and reporting on an interval until SIGINT or SIGTERM
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Seconds to wait between polls in daemon mode or when following notices
+    #[arg(long, global = true, env = "POLL_INTERVAL_SECS", default_value_t = 60)]
+    pub interval: u64,
+
+    /// Re-ingest input files even if a file with the same content was already loaded
+    #[arg(long)]
+    pub force: bool,
+
+    /// Only report deposits confirmed at or after this date or RFC 3339 time
+    #[arg(long, global = true, value_parser = timestamp::parse)]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only report deposits confirmed before this date or RFC 3339 time.
+    /// Without --from the report gives the totals as of that time
+    #[arg(long, global = true, value_parser = timestamp::parse)]
+    pub to: Option<DateTime<Utc>>,
+
+    /// Break the report down per day, week or month
+    #[arg(long)]
+    pub bucket: Option<Bucket>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    // The time range and bucketing the reports are limited to
+    pub fn report_window(&self) -> ReportWindow {
+        ReportWindow { from: self.from, to: self.to, bucket: self.bucket }
+    }
+}
+
+// Commands run instead of ingesting and reporting
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print deposit statistics overall, per known customer and for unreferenced deposits
+    Stats {
+        /// Number of histogram bins
+        #[arg(long, default_value_t = 10)]
+        bins: u32,
+    },
+    /// List deposits to addresses of no known customer per address
+    Unreferenced {
+        /// Only deposits to this address
+        #[arg(long)]
+        address: Option<String>,
+        /// Only deposits whose label contains this text, ignoring case
+        #[arg(long)]
+        label: Option<String>,
+        /// Only deposits of at least this amount
+        #[arg(long)]
+        min_amount: Option<f64>,
+        /// Only deposits of at most this amount
+        #[arg(long)]
+        max_amount: Option<f64>,
+        /// Sort by address, amount, count, first or last deposit
+        #[arg(long, default_value_t = UnreferencedSort::Address)]
+        sort: UnreferencedSort,
+        /// Sort in descending order
+        #[arg(long)]
+        desc: bool,
+        /// List every deposit under its address
+        #[arg(long)]
+        deposits: bool,
+    },
+    /// List deposits below 6 confirmations, which are not creditable yet
+    Pending,
+    /// Write the provisional deposit notices as newline-delimited JSON
+    Notices {
+        /// Only notices recorded after this id
+        #[arg(long, default_value_t = 0)]
+        after: i32,
+        /// Keep polling for new notices until SIGINT or SIGTERM
+        #[arg(long)]
+        follow: bool,
+    },
+    /// List the sends out of the wallet per customer, with their fees
+    Withdrawals,
+    /// Print deposits less withdrawals and fees per customer
+    NetBalances,
+    /// Compare the stored deposits with what the wallet holds, per address and overall.
+    /// Without --unspent the snapshot is fetched from bitcoind
+    Reconcile {
+        /// File holding the result of listunspent
+        #[arg(long)]
+        unspent: Option<String>,
+        /// File holding the result of getbalances, the unspent sum is compared without it
+        #[arg(long, requires = "unspent")]
+        balances: Option<String>,
+        /// List addresses that reconcile too
+        #[arg(long)]
+        all: bool,
+    },
+    /// Count invoices per status and list them
+    Invoices {
+        /// Only invoices that are open, expired, exact, underpaid, overpaid or late
+        #[arg(long)]
+        status: Option<InvoiceStatus>,
+        /// List every deposit matched to an invoice under it
+        #[arg(long)]
+        payments: bool,
+    },
+    /// Show a stored transaction, its standing and the batch it came from
+    Transaction {
+        txid: String,
+    },
+    /// List the stored transactions of an address, oldest first
+    Address {
+        address: String,
+        /// Page to show, counting from 1
+        #[arg(long, default_value_t = 1)]
+        page: u32,
+    },
+    /// Show what the ledger credited to each customer and why
+    Ledger {
+        #[command(subcommand)]
+        action: LedgerAction,
+    },
+    /// Inspect, fix and re-submit entries rejected by validation
+    Quarantine {
+        #[command(subcommand)]
+        action: QuarantineAction,
+    },
+}
+
+// Ledger commands
+#[derive(Debug, Subcommand)]
+pub enum LedgerAction {
+    /// Print the balance of every account
+    Balances,
+    /// Print journal entries with their lines
+    Journal {
+        /// Only entries touching this account, a customer name, suspense or wallet
+        #[arg(long)]
+        account: Option<String>,
+        /// Only entries of this txid
+        #[arg(long)]
+        txid: Option<String>,
+    },
+}
+
+// Quarantine commands
+#[derive(Debug, Subcommand)]
+pub enum QuarantineAction {
+    /// List quarantined entries awaiting a fix
+    List {
+        /// Include entries that were already re-submitted
+        #[arg(long)]
+        all: bool,
+    },
+    /// Replace a quarantined entry with corrected JSON, read from stdin when given as -
+    Fix {
+        id: i32,
+        json: String,
+    },
+    /// Validate pending entries again and store those that pass, all of them when no id is given
+    Resubmit {
+        ids: Vec<i32>,
+    },
+}
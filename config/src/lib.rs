@@ -6,16 +6,61 @@ This file is used to parse the .env and make its content
 accessble throughout the codebase.
 */
 use dotenv::dotenv;
+use models::{ KnownCustomersArray, Network, from_file };
 use serde::Deserialize;
 use std::env;
 
+mod cli;
+
+pub use cli::{ Cli, Command, LedgerAction, QuarantineAction };
+
+// Credentials for the bitcoind JSON-RPC interface
+#[derive(Debug, Deserialize)]
+pub enum RpcAuth {
+    // Path to the .cookie file bitcoind writes on startup
+    Cookie(String),
+    // rpcuser and rpcpassword
+    UserPass(String, String),
+}
+
+// A deposit file and the chain tip height it was dumped at
+#[derive(Debug, Clone, Deserialize)]
+pub struct InputFile {
+    pub path: String,
+    pub snapshot_tip: Option<i32>,
+}
+
+// INPUT_DATA entries are either a bare path or a path with its snapshot tip
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InputEntry {
+    Path(String),
+    File(InputFile),
+}
+
+impl From<InputEntry> for InputFile {
+    fn from(entry: InputEntry) -> Self {
+        match entry {
+            InputEntry::Path(path) => InputFile { path, snapshot_tip: None },
+            InputEntry::File(file) => file,
+        }
+    }
+}
+
 // Configuration structure
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub db_connection_string: String,
     pub log_file: String,
     pub known_customers: String,
-    pub input_data: Vec<String>,
+    pub invoices: Option<String>,
+    pub input_data: Vec<InputFile>,
+    pub chain_tip_height: Option<i32>,
+    pub wallet_name: String,
+    pub bitcoind_rpc_url: Option<String>,
+    pub bitcoind_rpc_auth: Option<RpcAuth>,
+    pub ingest_batch_size: usize,
+    pub network: Network,
 }
 
 impl Config {
@@ -27,14 +72,62 @@ impl Config {
         let log_file = env::var("LOG_FILE")?;
         let known_customers = env::var("KNOWN_CUSTOMERS")?;
 
-        let input_data_str = env::var("INPUT_DATA")?;
-        let input_data: Vec<String> = serde_json::from_str(&input_data_str)?;
+        // Optional file of payment requests deposits are matched to
+        let invoices = env::var("INVOICES").ok();
+
+        // Optional when deposits are polled from bitcoind instead
+        let input_data: Vec<InputFile> = match env::var("INPUT_DATA") {
+            Ok(input_data_str) => serde_json::from_str::<Vec<InputEntry>>(&input_data_str)?
+                .into_iter().map(InputFile::from).collect(),
+            Err(_) => Vec::new(),
+        };
+
+        // Optional, the height the chain is at now, which matures stored deposits
+        let chain_tip_height = match env::var("CHAIN_TIP_HEIGHT") {
+            Ok(height) => Some(height.parse::<i32>()?),
+            Err(_) => None,
+        };
+
+        // Name the listsinceblock cursor is stored under
+        let wallet_name = env::var("WALLET_NAME").unwrap_or_else(|_| String::from("default"));
+
+        // Optional bitcoind JSON-RPC source, cookie auth takes precedence
+        let bitcoind_rpc_url = env::var("BITCOIND_RPC_URL").ok();
+        let bitcoind_rpc_auth = match (env::var("BITCOIND_RPC_COOKIE"),
+            env::var("BITCOIND_RPC_USER"), env::var("BITCOIND_RPC_PASSWORD")) {
+            (Ok(cookie), _, _) => Some(RpcAuth::Cookie(cookie)),
+            (_, Ok(user), Ok(password)) => Some(RpcAuth::UserPass(user, password)),
+            _ => None,
+        };
+
+        // Rows inserted per round trip, which also bounds rows held in memory
+        let ingest_batch_size = match env::var("INGEST_BATCH_SIZE") {
+            Ok(size) => size.parse::<usize>()?.max(1),
+            Err(_) => 1000,
+        };
+
+        // Network every address must belong to. Inferred from the known
+        // customer addresses when not set, and an error when they do not tell
+        let network = match env::var("BITCOIN_NETWORK") {
+            Ok(network) => network.parse::<Network>()?,
+            Err(_) => from_file::<KnownCustomersArray>(&known_customers)
+                .and_then(|customers| Ok(customers.infer_network()?))
+                .map_err(|e| format!("BITCOIN_NETWORK is not set and cannot be inferred from {}: {}",
+                    known_customers, e))?,
+        };
 
         Ok(Config {
             db_connection_string,
             log_file,
             known_customers,
+            invoices,
             input_data,
+            chain_tip_height,
+            wallet_name,
+            bitcoind_rpc_url,
+            bitcoind_rpc_auth,
+            ingest_batch_size,
+            network,
         })
     }
 }
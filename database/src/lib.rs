@@ -6,12 +6,28 @@ This file defines the database interface and provides
 an implementation of a postgres client
 */
 use tokio_postgres::{Client, NoTls, Row};
+use futures_util::future::try_join_all;
 use async_trait::async_trait;
-use models::{KnownCustomers, Transaction};
+use chrono::{DateTime, Utc};
+use models::{AddressReconciliation, Block, DepositBucket, DepositNotice, DepositScope, DepositStatistics, DepositStatus, DepositTransition,
+    HistogramBin, Invoice, InvoicePayment, InvoiceSummary, JournalLine, KnownCustomers, LabelRule, LedgerBalance, NetBalance, OutputDiscrepancy,
+    ProvisionalDeposit, QuarantinedTransaction, ReconciliationTotals, ReportWindow, StoredTransaction, Transaction,
+    UnreferencedAddress, UnreferencedDeposit, UnreferencedFilter, WalletSnapshot, Withdrawal};
 use rust_decimal::Decimal;
 use rust_decimal::prelude::*;
+use serde_json::Value;
 use std::error::Error;
 
+// Owned parameter list, for calls that outlive the statement building them
+macro_rules! params {
+    ($($param:expr),* $(,)?) => {
+        vec![$($param as &(dyn tokio_postgres::types::ToSql + Sync)),*]
+    };
+}
+
+// Transactions per page when listing the transactions of an address
+pub const TRANSACTIONS_PAGE_SIZE: u32 = 25;
+
 // This trait defines the programmatic interface with the database
 #[async_trait]
 pub trait DatabaseDriver {
@@ -21,10 +37,69 @@ pub trait DatabaseDriver {
     async fn known_wallet_transaction_count(&self, address: &str) -> Result<i32, Box<dyn Error>>;
     async fn unknown_wallet_deposit_amount(&self) -> Result<Option<f64>, Box<dyn Error>>;
     async fn unknown_wallet_transaction_count(&self) -> Result<Option<i32>, Box<dyn Error>>;
+    async fn total_deposit_amount(&self) -> Result<Option<f64>, Box<dyn Error>>;
     async fn get_smallest_confirmed_amount(&self) -> Result<Option<f64>, Box<dyn Error>>;
     async fn get_max_confirmed_amount(&self) -> Result<Option<f64>, Box<dyn Error>>;
+    async fn known_wallet_deposits_by_bucket(&self, address: &str, window: &ReportWindow)
+        -> Result<Vec<DepositBucket>, Box<dyn Error>>;
+    async fn unknown_wallet_deposits_by_bucket(&self, window: &ReportWindow) -> Result<Vec<DepositBucket>, Box<dyn Error>>;
+    async fn label_attributed_deposits_by_bucket(&self, name: &str, window: &ReportWindow)
+    -> Result<Vec<DepositBucket>, Box<dyn Error>>;
+    async fn deposit_statistics(&self, scope: &DepositScope, window: &ReportWindow)
+        -> Result<DepositStatistics, Box<dyn Error>>;
+    async fn deposit_histogram(&self, scope: &DepositScope, window: &ReportWindow, bins: i32)
+        -> Result<Vec<HistogramBin>, Box<dyn Error>>;
+    async fn unreferenced_deposits_by_address(&self, filter: &UnreferencedFilter, window: &ReportWindow)
+    -> Result<Vec<UnreferencedAddress>, Box<dyn Error>>;
+    async fn unreferenced_deposits(&self, filter: &UnreferencedFilter, window: &ReportWindow)
+    -> Result<Vec<UnreferencedDeposit>, Box<dyn Error>>;
+    async fn get_transaction(&self, txid: &str) -> Result<Option<StoredTransaction>, Box<dyn Error>>;
+    async fn list_transactions_by_address(&self, address: &str, page: u32) -> Result<Vec<StoredTransaction>, Box<dyn Error>>;
+    async fn clear_known_clients(&self) -> Result<(), Box<dyn Error>>;
     async fn insert_known_client(&self, known_customer: &KnownCustomers) -> Result<(), Box<dyn Error>>;
-    async fn insert_transaction(&self, transaction: &Transaction) -> Result<(), Box<dyn Error>>;
+    async fn set_label_rules(&self, label_rules: &[LabelRule]) -> Result<(), Box<dyn Error>>;
+    async fn set_payout_addresses(&self, known_customers: &[KnownCustomers]) -> Result<(), Box<dyn Error>>;
+    async fn set_payout_label_rules(&self, label_rules: &[LabelRule]) -> Result<(), Box<dyn Error>>;
+    async fn set_invoices(&self, invoices: &[Invoice]) -> Result<(), Box<dyn Error>>;
+    async fn invoice_statuses(&self) -> Result<Vec<InvoiceSummary>, Box<dyn Error>>;
+    async fn invoice_payments(&self) -> Result<Vec<InvoicePayment>, Box<dyn Error>>;
+    async fn insert_blocks(&self, blocks: &[Block], batch_id: i32) -> Result<(), Box<dyn Error>>;
+    async fn resolve_main_chain(&self, batch_id: i32, tip: Option<i32>) -> Result<(), Box<dyn Error>>;
+    async fn insert_transactions(&self, transactions: &[Transaction], batch_id: i32) -> Result<(), Box<dyn Error>>;
+    async fn set_chain_tip(&self, height: i32) -> Result<(), Box<dyn Error>>;
+    async fn remove_transaction(&self, transaction: &Transaction, tip: Option<i32>) -> Result<(), Box<dyn Error>>;
+    async fn get_last_block(&self, wallet: &str) -> Result<Option<String>, Box<dyn Error>>;
+    async fn set_last_block(&self, wallet: &str, lastblock: &str) -> Result<(), Box<dyn Error>>;
+    async fn start_ingest_batch(&self, source: &str, sha256: &str) -> Result<i32, Box<dyn Error>>;
+    async fn finish_ingest_batch(&self, batch_id: i32, row_count: i32, quarantined_count: i32, status: &str)
+        -> Result<(), Box<dyn Error>>;
+    async fn find_ingested_batch(&self, sha256: &str) -> Result<Option<i32>, Box<dyn Error>>;
+    async fn set_ingest_batch_tip(&self, batch_id: i32, height: i32) -> Result<(), Box<dyn Error>>;
+    async fn get_ingest_batch_tip(&self, batch_id: i32) -> Result<Option<i32>, Box<dyn Error>>;
+    async fn quarantine_transactions(&self, entries: &[(Value, String)], batch_id: i32) -> Result<(), Box<dyn Error>>;
+    async fn get_quarantined_transactions(&self, include_resolved: bool) -> Result<Vec<QuarantinedTransaction>, Box<dyn Error>>;
+    async fn fix_quarantined_transaction(&self, id: i32, raw: &Value) -> Result<bool, Box<dyn Error>>;
+    async fn resubmit_quarantined_transaction(&self, id: i32, resolved_batch_id: Option<i32>, reason: Option<&str>)
+        -> Result<(), Box<dyn Error>>;
+    async fn post_ledger_entries(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>>;
+    async fn ledger_balances(&self) -> Result<Vec<LedgerBalance>, Box<dyn Error>>;
+    async fn update_deposit_states(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>>;
+    async fn deposit_transitions(&self, txid: &str) -> Result<Vec<DepositTransition>, Box<dyn Error>>;
+    async fn provisional_deposits(&self) -> Result<Vec<ProvisionalDeposit>, Box<dyn Error>>;
+    async fn record_deposit_notices(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>>;
+    async fn deposit_notices(&self, after_id: i32, limit: i32) -> Result<Vec<DepositNotice>, Box<dyn Error>>;
+    async fn journal(&self, account: Option<&str>, txid: Option<&str>) -> Result<Vec<JournalLine>, Box<dyn Error>>;
+    async fn withdrawals(&self) -> Result<Vec<Withdrawal>, Box<dyn Error>>;
+    async fn net_balances(&self) -> Result<Vec<NetBalance>, Box<dyn Error>>;
+    async fn record_wallet_snapshot(&self, snapshot: &WalletSnapshot) -> Result<i32, Box<dyn Error>>;
+    async fn reconciliation_totals(&self, snapshot_id: i32) -> Result<ReconciliationTotals, Box<dyn Error>>;
+    async fn reconciliation_by_address(&self, snapshot_id: i32) -> Result<Vec<AddressReconciliation>, Box<dyn Error>>;
+    async fn reconciliation_discrepancies(&self, snapshot_id: i32) -> Result<Vec<OutputDiscrepancy>, Box<dyn Error>>;
+    async fn begin_transaction(&self) -> Result<(), Box<dyn Error>>;
+    async fn commit_transaction(&self) -> Result<(), Box<dyn Error>>;
+    async fn rollback_transaction(&self) -> Result<(), Box<dyn Error>>;
+    async fn begin_snapshot(&self) -> Result<(), Box<dyn Error>>;
+    async fn end_snapshot(&self) -> Result<(), Box<dyn Error>>;
     fn close(&mut self);
 }
 
@@ -79,7 +154,7 @@ impl DatabaseDriver for PostgresDriver {
             let count: i32 = row.get(0);
             Ok(count)
         } else {
-            Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "No database client available")))
+            Err(Box::new(std::io::Error::other("No database client available")))
         }
     }
 
@@ -107,6 +182,19 @@ impl DatabaseDriver for PostgresDriver {
         Ok(None)
     }
 
+    // Execute get_total_confirmed_amount_all_clients stored procedure
+    async fn total_deposit_amount(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        let procedure = "SELECT get_total_confirmed_amount_all_clients()";
+        if let Some(client) = &self.client {
+            let row: Row = client.query_one(procedure, &[]).await?;
+            let total_amount: Option<Decimal> = row.try_get(0)?;
+            let total_amount_f64 = total_amount.map(|d| d.to_f64()
+                .ok_or("Failed to convert Decimal to f64")).transpose()?;
+            return Ok(total_amount_f64);
+        }
+        Ok(None)
+    }
+
     // Execute get_smallest_confirmed_amount stored procedure
     async fn get_smallest_confirmed_amount(&self) -> Result<Option<f64>, Box<dyn Error>> {
         let procedure = "SELECT get_smallest_confirmed_amount()";
@@ -133,6 +221,177 @@ impl DatabaseDriver for PostgresDriver {
         Ok(None)
     }
 
+    // Execute get_confirmed_deposits_by_bucket stored procedure
+    async fn known_wallet_deposits_by_bucket(&self, address: &str, window: &ReportWindow)
+    -> Result<Vec<DepositBucket>, Box<dyn Error>> {
+        let bucket = window.bucket.map(|bucket| bucket.as_str());
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&address, &window.from, &window.to, &bucket];
+        let procedure = "SELECT * FROM get_confirmed_deposits_by_bucket($1, $2, $3, $4)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            return rows.iter().map(deposit_bucket).collect();
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_unknown_confirmed_deposits_by_bucket stored procedure
+    async fn unknown_wallet_deposits_by_bucket(&self, window: &ReportWindow) -> Result<Vec<DepositBucket>, Box<dyn Error>> {
+        let bucket = window.bucket.map(|bucket| bucket.as_str());
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&window.from, &window.to, &bucket];
+        let procedure = "SELECT * FROM get_unknown_confirmed_deposits_by_bucket($1, $2, $3)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            return rows.iter().map(deposit_bucket).collect();
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_label_attributed_deposits_by_bucket stored procedure
+    async fn label_attributed_deposits_by_bucket(&self, name: &str, window: &ReportWindow)
+    -> Result<Vec<DepositBucket>, Box<dyn Error>> {
+        let bucket = window.bucket.map(|bucket| bucket.as_str());
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&name, &window.from, &window.to, &bucket];
+        let procedure = "SELECT * FROM get_label_attributed_deposits_by_bucket($1, $2, $3, $4)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            return rows.iter().map(deposit_bucket).collect();
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_deposit_statistics stored procedure
+    async fn deposit_statistics(&self, scope: &DepositScope, window: &ReportWindow)
+    -> Result<DepositStatistics, Box<dyn Error>> {
+        let (address, label, unreferenced) = scope_filter(scope);
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] =
+            &[&address, &label, &unreferenced, &window.from, &window.to];
+        let procedure = "SELECT * FROM get_deposit_statistics($1, $2, $3, $4, $5)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, params).await?;
+            return Ok(DepositStatistics {
+                count: row.try_get(0)?,
+                sum: row.try_get(1)?,
+                mean: row.try_get(2)?,
+                median: row.try_get(3)?,
+                p90: row.try_get(4)?,
+                p99: row.try_get(5)?,
+                stddev: row.try_get(6)?,
+                min: row.try_get(7)?,
+                max: row.try_get(8)?,
+            });
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_deposit_histogram stored procedure
+    async fn deposit_histogram(&self, scope: &DepositScope, window: &ReportWindow, bins: i32)
+    -> Result<Vec<HistogramBin>, Box<dyn Error>> {
+        let (address, label, unreferenced) = scope_filter(scope);
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] =
+            &[&address, &label, &unreferenced, &window.from, &window.to, &bins];
+        let procedure = "SELECT lower_bound, upper_bound, transaction_count FROM get_deposit_histogram($1, $2, $3, $4, $5, $6)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            let mut histogram = Vec::with_capacity(rows.len());
+            for row in rows {
+                histogram.push(HistogramBin {
+                    lower: row.try_get(0)?,
+                    upper: row.try_get(1)?,
+                    count: row.try_get(2)?,
+                });
+            }
+            return Ok(histogram);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_unreferenced_deposits_by_address stored procedure
+    async fn unreferenced_deposits_by_address(&self, filter: &UnreferencedFilter, window: &ReportWindow)
+    -> Result<Vec<UnreferencedAddress>, Box<dyn Error>> {
+        let (min_amount, max_amount) = amount_bounds(filter)?;
+        let sort = filter.sort.as_str();
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&filter.address, &filter.label,
+            &min_amount, &max_amount, &window.from, &window.to, &sort, &filter.descending];
+        let procedure = "SELECT * FROM get_unreferenced_deposits_by_address($1, $2, $3, $4, $5, $6, $7, $8)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            let mut addresses = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(2)?;
+                addresses.push(UnreferencedAddress {
+                    address: row.try_get(0)?,
+                    count: row.try_get(1)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    first: row.try_get(3)?,
+                    last: row.try_get(4)?,
+                    labels: row.try_get(5)?,
+                });
+            }
+            return Ok(addresses);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_unreferenced_deposits stored procedure
+    async fn unreferenced_deposits(&self, filter: &UnreferencedFilter, window: &ReportWindow)
+    -> Result<Vec<UnreferencedDeposit>, Box<dyn Error>> {
+        let (min_amount, max_amount) = amount_bounds(filter)?;
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&filter.address, &filter.label,
+            &min_amount, &max_amount, &window.from, &window.to];
+        let procedure = "SELECT * FROM get_unreferenced_deposits($1, $2, $3, $4, $5, $6)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, params).await?;
+            let mut deposits = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(3)?;
+                deposits.push(UnreferencedDeposit {
+                    txid: row.try_get(0)?,
+                    vout: row.try_get(1)?,
+                    address: row.try_get(2)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    blocktime: row.try_get(4)?,
+                    label: row.try_get(5)?,
+                });
+            }
+            return Ok(deposits);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute find_transactions stored procedure for a single txid
+    async fn get_transaction(&self, txid: &str) -> Result<Option<StoredTransaction>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM find_transactions($1, NULL, 1, 0)";
+        if let Some(client) = &self.client {
+            return match client.query_opt(procedure, &[&txid]).await? {
+                Some(row) => Ok(Some(stored_transaction(&row)?)),
+                None => Ok(None),
+            };
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute find_transactions stored procedure for one page of an address.
+    // Pages are numbered from 1
+    async fn list_transactions_by_address(&self, address: &str, page: u32) -> Result<Vec<StoredTransaction>, Box<dyn Error>> {
+        let limit = i32::try_from(TRANSACTIONS_PAGE_SIZE)?;
+        let offset = i32::try_from(page.max(1) - 1)?.checked_mul(limit).ok_or("Page out of range")?;
+        let procedure = "SELECT * FROM find_transactions(NULL, $1, $2, $3)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&address, &limit, &offset]).await?;
+            return rows.iter().map(stored_transaction).collect();
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute clear_known_clients stored procedure
+    async fn clear_known_clients(&self) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL clear_known_clients()";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[]).await?;
+        }
+        Ok(())
+    }
+
     // Execute insert_known_client stored procedure
     async fn insert_known_client(&self, known_customer: &KnownCustomers) -> Result<(), Box<dyn Error>> {
         let procedure = "CALL insert_known_client($1, $2)";
@@ -142,35 +401,741 @@ impl DatabaseDriver for PostgresDriver {
         Ok(())
     }
 
-    // Execute insert_transaction stored procedure
-    async fn insert_transaction(&self, transaction: &Transaction) -> Result<(), Box<dyn Error>> {
-        let procedure = "CALL insert_transaction($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)";
+    // Execute set_label_rules stored procedure
+    async fn set_label_rules(&self, label_rules: &[LabelRule]) -> Result<(), Box<dyn Error>> {
+        let names: Vec<&str> = label_rules.iter().map(|rule| rule.name.as_str()).collect();
+        let patterns: Vec<String> = label_rules.iter().map(|rule| rule.like_pattern()).collect();
+        let procedure = "CALL set_label_rules($1, $2)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&names, &patterns]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute set_payout_addresses stored procedure with every payout address of every customer
+    async fn set_payout_addresses(&self, known_customers: &[KnownCustomers]) -> Result<(), Box<dyn Error>> {
+        let (names, addresses): (Vec<&str>, Vec<&str>) = known_customers.iter()
+            .flat_map(|customer| customer.payout_addresses.iter()
+                .map(|address| (customer.name.as_str(), address.as_str())))
+            .unzip();
+        let procedure = "CALL set_payout_addresses($1, $2)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&names, &addresses]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute set_payout_label_rules stored procedure
+    async fn set_payout_label_rules(&self, label_rules: &[LabelRule]) -> Result<(), Box<dyn Error>> {
+        let names: Vec<&str> = label_rules.iter().map(|rule| rule.name.as_str()).collect();
+        let patterns: Vec<String> = label_rules.iter().map(|rule| rule.like_pattern()).collect();
+        let procedure = "CALL set_payout_label_rules($1, $2)";
         if let Some(client) = &self.client {
-            let amount = Decimal::from_f64(transaction.amount).ok_or("Invalid amount")?;
-            client.execute(procedure, &[
-                &transaction.involves_watchonly,
-                &transaction.account,
-                &transaction.address,
-                &transaction.category,
-                &amount,
-                &transaction.label,
-                &transaction.confirmations,
-                &transaction.blockhash,
-                &transaction.blockindex,
-                &transaction.blocktime,
-                &transaction.txid,
-                &transaction.vout,
-                &transaction.walletconflicts,
-                &transaction.time,
-                &transaction.timereceived,
-                &transaction.bip125_replaceable,
-            ]).await?;
+            client.execute(procedure, &[&names, &patterns]).await?;
         }
         Ok(())
     }
 
+    // Execute set_invoices stored procedure
+    async fn set_invoices(&self, invoices: &[Invoice]) -> Result<(), Box<dyn Error>> {
+        let ids: Vec<&str> = invoices.iter().map(|invoice| invoice.id.as_str()).collect();
+        let customers: Vec<Option<&str>> = invoices.iter().map(|invoice| invoice.customer.as_deref()).collect();
+        let addresses: Vec<&str> = invoices.iter().map(|invoice| invoice.address.as_str()).collect();
+        let amounts = invoices.iter()
+            .map(|invoice| Decimal::from_f64(invoice.amount).ok_or("Invalid amount"))
+            .collect::<Result<Vec<Decimal>, _>>()?;
+        let created: Vec<DateTime<Utc>> = invoices.iter().map(|invoice| invoice.created).collect();
+        let expires: Vec<DateTime<Utc>> = invoices.iter().map(|invoice| invoice.expires).collect();
+        let procedure = "CALL set_invoices($1, $2, $3, $4, $5, $6)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&ids, &customers, &addresses, &amounts, &created, &expires]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute get_invoice_statuses stored procedure
+    async fn invoice_statuses(&self) -> Result<Vec<InvoiceSummary>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_invoice_statuses()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut invoices = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(3)?;
+                let status: String = row.try_get(6)?;
+                let paid: Decimal = row.try_get(7)?;
+                invoices.push(InvoiceSummary {
+                    id: row.try_get(0)?,
+                    customer: row.try_get(1)?,
+                    address: row.try_get(2)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    created: row.try_get(4)?,
+                    expires: row.try_get(5)?,
+                    status: status.parse()?,
+                    paid: paid.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    payments: row.try_get(8)?,
+                });
+            }
+            return Ok(invoices);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_invoice_payments stored procedure
+    async fn invoice_payments(&self) -> Result<Vec<InvoicePayment>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_invoice_payments()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut payments = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(3)?;
+                let kind: String = row.try_get(5)?;
+                payments.push(InvoicePayment {
+                    invoice_id: row.try_get(0)?,
+                    txid: row.try_get(1)?,
+                    vout: row.try_get(2)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    paid_at: row.try_get(4)?,
+                    kind: kind.parse()?,
+                });
+            }
+            return Ok(payments);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute insert_block stored procedure for every block.
+    // The calls are pipelined on the connection and run in order
+    async fn insert_blocks(&self, blocks: &[Block], batch_id: i32) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL insert_block($1, $2, $3, $4, $5)";
+        if let Some(client) = &self.client {
+            let statement = client.prepare(procedure).await?;
+            try_join_all(blocks.iter().map(|block|
+                client.execute_raw(&statement, params![&block.hash, &block.height, &block.time,
+                    &block.confirmations, &batch_id]))).await?;
+        }
+        Ok(())
+    }
+
+    // Execute resolve_main_chain stored procedure
+    async fn resolve_main_chain(&self, batch_id: i32, tip: Option<i32>) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL resolve_main_chain($1, $2)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&batch_id, &tip]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute insert_transaction stored procedure for every transaction.
+    // The calls are pipelined on the connection and run in order
+    async fn insert_transactions(&self, transactions: &[Transaction], batch_id: i32) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL insert_transaction($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)";
+        if let Some(client) = &self.client {
+            let statement = client.prepare(procedure).await?;
+
+            // Convert amounts up front so the pipelined calls can borrow them
+            let mut amounts = Vec::with_capacity(transactions.len());
+            for transaction in transactions {
+                let amount = Decimal::from_f64(transaction.amount).ok_or("Invalid amount")?;
+                let fee = transaction.fee.map(|fee| Decimal::from_f64(fee)
+                    .ok_or("Invalid fee")).transpose()?;
+                amounts.push((amount, fee));
+            }
+
+            try_join_all(transactions.iter().zip(amounts.iter()).map(|(transaction, (amount, fee))|
+                client.execute_raw(&statement, params![
+                    &transaction.involves_watchonly,
+                    &transaction.account,
+                    &transaction.address,
+                    &transaction.category,
+                    amount,
+                    &transaction.label,
+                    &transaction.confirmations,
+                    &transaction.blockhash,
+                    &transaction.blockindex,
+                    &transaction.txid,
+                    &transaction.vout,
+                    &transaction.walletconflicts,
+                    &transaction.time,
+                    &transaction.timereceived,
+                    &transaction.bip125_replaceable,
+                    &transaction.generated,
+                    &transaction.abandoned,
+                    fee,
+                    &transaction.comment,
+                    &transaction.to,
+                    &batch_id,
+                ]))).await?;
+        }
+        Ok(())
+    }
+
+    // Execute set_chain_tip stored procedure
+    async fn set_chain_tip(&self, height: i32) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL set_chain_tip($1)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&height]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute remove_transaction stored procedure
+    async fn remove_transaction(&self, transaction: &Transaction, tip: Option<i32>) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL remove_transaction($1, $2, $3)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&transaction.txid, &transaction.blockhash, &tip]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute get_last_block stored procedure
+    async fn get_last_block(&self, wallet: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&wallet];
+        let procedure = "SELECT get_last_block($1)";
+        if let Some(client) = &self.client {
+            let row: Row = client.query_one(procedure, params).await?;
+            let lastblock: Option<String> = row.try_get(0)?;
+            return Ok(lastblock);
+        }
+        Ok(None)
+    }
+
+    // Execute set_last_block stored procedure
+    async fn set_last_block(&self, wallet: &str, lastblock: &str) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL set_last_block($1, $2)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&wallet, &lastblock]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute start_ingest_batch stored procedure
+    async fn start_ingest_batch(&self, source: &str, sha256: &str) -> Result<i32, Box<dyn Error>> {
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&source, &sha256];
+        let procedure = "SELECT start_ingest_batch($1, $2)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, params).await?;
+            let batch_id: i32 = row.get(0);
+            Ok(batch_id)
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Execute finish_ingest_batch stored procedure
+    async fn finish_ingest_batch(&self, batch_id: i32, row_count: i32, quarantined_count: i32, status: &str)
+        -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL finish_ingest_batch($1, $2, $3, $4)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&batch_id, &row_count, &quarantined_count, &status]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute find_ingested_batch stored procedure
+    async fn find_ingested_batch(&self, sha256: &str) -> Result<Option<i32>, Box<dyn Error>> {
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&sha256];
+        let procedure = "SELECT find_ingested_batch($1)";
+        if let Some(client) = &self.client {
+            let row: Row = client.query_one(procedure, params).await?;
+            let batch_id: Option<i32> = row.try_get(0)?;
+            return Ok(batch_id);
+        }
+        Ok(None)
+    }
+
+    // Execute set_ingest_batch_tip stored procedure
+    async fn set_ingest_batch_tip(&self, batch_id: i32, height: i32) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL set_ingest_batch_tip($1, $2)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&batch_id, &height]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute get_ingest_batch_tip stored procedure
+    async fn get_ingest_batch_tip(&self, batch_id: i32) -> Result<Option<i32>, Box<dyn Error>> {
+        let procedure = "SELECT get_ingest_batch_tip($1)";
+        if let Some(client) = &self.client {
+            let row: Row = client.query_one(procedure, &[&batch_id]).await?;
+            let height: Option<i32> = row.try_get(0)?;
+            return Ok(height);
+        }
+        Ok(None)
+    }
+
+    // Execute quarantine_transaction stored procedure for each rejected entry
+    async fn quarantine_transactions(&self, entries: &[(Value, String)], batch_id: i32) -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL quarantine_transaction($1, $2, $3)";
+        if let Some(client) = &self.client {
+            let statement = client.prepare(procedure).await?;
+            try_join_all(entries.iter().map(|(raw, reason)|
+                client.execute_raw(&statement, params![&batch_id, raw, reason]))).await?;
+        }
+        Ok(())
+    }
+
+    // Execute get_quarantined_transactions stored procedure
+    async fn get_quarantined_transactions(&self, include_resolved: bool) -> Result<Vec<QuarantinedTransaction>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_quarantined_transactions($1)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&include_resolved]).await?;
+            let mut quarantined = Vec::with_capacity(rows.len());
+            for row in rows {
+                quarantined.push(QuarantinedTransaction {
+                    id: row.try_get(0)?,
+                    batch_id: row.try_get(1)?,
+                    raw: row.try_get(2)?,
+                    reason: row.try_get(3)?,
+                    resolved_batch_id: row.try_get(4)?,
+                });
+            }
+            Ok(quarantined)
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Execute fix_quarantined_transaction stored procedure
+    async fn fix_quarantined_transaction(&self, id: i32, raw: &Value) -> Result<bool, Box<dyn Error>> {
+        let params: &[&(dyn tokio_postgres::types::ToSql + Sync)] = &[&id, &raw];
+        let procedure = "SELECT fix_quarantined_transaction($1, $2)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, params).await?;
+            let fixed: bool = row.get(0);
+            Ok(fixed)
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Execute resubmit_quarantined_transaction stored procedure
+    async fn resubmit_quarantined_transaction(&self, id: i32, resolved_batch_id: Option<i32>, reason: Option<&str>)
+    -> Result<(), Box<dyn Error>> {
+        let procedure = "CALL resubmit_quarantined_transaction($1, $2, $3)";
+        if let Some(client) = &self.client {
+            client.execute(procedure, &[&id, &resolved_batch_id, &reason]).await?;
+        }
+        Ok(())
+    }
+
+    // Execute post_ledger_entries stored procedure
+    async fn post_ledger_entries(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>> {
+        let procedure = "SELECT post_ledger_entries($1)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, &[&batch_id]).await?;
+            return Ok(row.try_get(0)?);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_ledger_balances stored procedure
+    async fn ledger_balances(&self) -> Result<Vec<LedgerBalance>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_ledger_balances()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut balances = Vec::with_capacity(rows.len());
+            for row in rows {
+                let balance: Decimal = row.try_get(2)?;
+                balances.push(LedgerBalance {
+                    kind: row.try_get(0)?,
+                    name: row.try_get(1)?,
+                    balance: balance.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    entries: row.try_get(3)?,
+                });
+            }
+            return Ok(balances);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute update_deposit_states stored procedure
+    async fn update_deposit_states(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>> {
+        let procedure = "SELECT update_deposit_states($1)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, &[&batch_id]).await?;
+            return Ok(row.try_get(0)?);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_deposit_transitions stored procedure
+    async fn deposit_transitions(&self, txid: &str) -> Result<Vec<DepositTransition>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_deposit_transitions($1)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&txid]).await?;
+            let mut transitions = Vec::with_capacity(rows.len());
+            for row in rows {
+                let from: Option<String> = row.try_get(0)?;
+                let to: String = row.try_get(1)?;
+                transitions.push(DepositTransition {
+                    from: from.map(|from| from.parse::<DepositStatus>()).transpose()?,
+                    to: to.parse::<DepositStatus>()?,
+                    batch_id: row.try_get(2)?,
+                    at: row.try_get(3)?,
+                });
+            }
+            return Ok(transitions);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_provisional_deposits stored procedure
+    async fn provisional_deposits(&self) -> Result<Vec<ProvisionalDeposit>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_provisional_deposits()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut deposits = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(4)?;
+                deposits.push(ProvisionalDeposit {
+                    txid: row.try_get(0)?,
+                    vout: row.try_get(1)?,
+                    address: row.try_get(2)?,
+                    customer: row.try_get(3)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    confirmations: row.try_get(5)?,
+                    replaceable: row.try_get(6)?,
+                    first_seen: row.try_get(7)?,
+                    estimated_maturity: row.try_get(8)?,
+                });
+            }
+            return Ok(deposits);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute record_deposit_notices stored procedure
+    async fn record_deposit_notices(&self, batch_id: Option<i32>) -> Result<i32, Box<dyn Error>> {
+        let procedure = "SELECT record_deposit_notices($1)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure, &[&batch_id]).await?;
+            return Ok(row.try_get(0)?);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_deposit_notices stored procedure
+    async fn deposit_notices(&self, after_id: i32, limit: i32) -> Result<Vec<DepositNotice>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_deposit_notices($1, $2)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&after_id, &limit]).await?;
+            let mut notices = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(6)?;
+                notices.push(DepositNotice {
+                    id: row.try_get(0)?,
+                    kind: row.try_get(1)?,
+                    txid: row.try_get(2)?,
+                    vout: row.try_get(3)?,
+                    address: row.try_get(4)?,
+                    customer: row.try_get(5)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    confirmations: row.try_get(7)?,
+                    replaceable: row.try_get(8)?,
+                    estimated_maturity: row.try_get(9)?,
+                    batch_id: row.try_get(10)?,
+                    created_at: row.try_get(11)?,
+                });
+            }
+            return Ok(notices);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_withdrawals stored procedure
+    async fn withdrawals(&self) -> Result<Vec<Withdrawal>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_withdrawals()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut withdrawals = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(4)?;
+                let fee: Decimal = row.try_get(5)?;
+                withdrawals.push(Withdrawal {
+                    txid: row.try_get(0)?,
+                    vout: row.try_get(1)?,
+                    address: row.try_get(2)?,
+                    customer: row.try_get(3)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    fee: fee.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    confirmations: row.try_get(6)?,
+                    sent_at: row.try_get(7)?,
+                });
+            }
+            return Ok(withdrawals);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_customer_net_balances stored procedure
+    async fn net_balances(&self) -> Result<Vec<NetBalance>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_customer_net_balances()";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[]).await?;
+            let mut balances = Vec::with_capacity(rows.len());
+            for row in rows {
+                let deposited: Decimal = row.try_get(2)?;
+                let withdrawn: Decimal = row.try_get(4)?;
+                let fees: Decimal = row.try_get(5)?;
+                let net: Decimal = row.try_get(6)?;
+                balances.push(NetBalance {
+                    customer: row.try_get(0)?,
+                    deposits: row.try_get(1)?,
+                    deposited: deposited.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    withdrawals: row.try_get(3)?,
+                    withdrawn: withdrawn.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    fees: fees.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    net: net.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                });
+            }
+            return Ok(balances);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute record_wallet_snapshot stored procedure
+    async fn record_wallet_snapshot(&self, snapshot: &WalletSnapshot) -> Result<i32, Box<dyn Error>> {
+        let balance = snapshot.balances.as_ref()
+            .map(|balances| Decimal::from_f64(balances.total()).ok_or("Invalid balance")).transpose()?;
+        let txids: Vec<&str> = snapshot.unspent.iter().map(|output| output.txid.as_str()).collect();
+        let vouts: Vec<i32> = snapshot.unspent.iter().map(|output| output.vout).collect();
+        let addresses: Vec<Option<&str>> = snapshot.unspent.iter().map(|output| output.address.as_deref()).collect();
+        let amounts = snapshot.unspent.iter()
+            .map(|output| Decimal::from_f64(output.amount).ok_or("Invalid amount"))
+            .collect::<Result<Vec<Decimal>, _>>()?;
+        let confirmations: Vec<i32> = snapshot.unspent.iter().map(|output| output.confirmations).collect();
+        let procedure = "SELECT record_wallet_snapshot($1, $2, $3, $4, $5, $6, $7)";
+        if let Some(client) = &self.client {
+            let row = client.query_one(procedure,
+                &[&snapshot.source, &balance, &txids, &vouts, &addresses, &amounts, &confirmations]).await?;
+            return Ok(row.try_get(0)?);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_reconciliation_totals stored procedure
+    async fn reconciliation_totals(&self, snapshot_id: i32) -> Result<ReconciliationTotals, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_reconciliation_totals($1)";
+        if let Some(client) = &self.client {
+            let row = client.query_opt(procedure, &[&snapshot_id]).await?
+                .ok_or_else(|| format!("Wallet snapshot {} not found", snapshot_id))?;
+            let mut amounts = Vec::with_capacity(6);
+            for index in 0..6 {
+                let amount: Decimal = row.try_get(index)?;
+                amounts.push(amount.to_f64().ok_or("Failed to convert Decimal to f64")?);
+            }
+            return Ok(ReconciliationTotals {
+                deposited: amounts[0],
+                withdrawn: amounts[1],
+                fees: amounts[2],
+                engine_balance: amounts[3],
+                unspent: amounts[4],
+                wallet_balance: amounts[5],
+            });
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_reconciliation_by_address stored procedure
+    async fn reconciliation_by_address(&self, snapshot_id: i32) -> Result<Vec<AddressReconciliation>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_reconciliation_by_address($1)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&snapshot_id]).await?;
+            let mut addresses = Vec::with_capacity(rows.len());
+            for row in rows {
+                let engine_amount: Decimal = row.try_get(3)?;
+                let wallet_amount: Decimal = row.try_get(5)?;
+                addresses.push(AddressReconciliation {
+                    address: row.try_get(0)?,
+                    customer: row.try_get(1)?,
+                    engine_count: row.try_get(2)?,
+                    engine_amount: engine_amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                    wallet_count: row.try_get(4)?,
+                    wallet_amount: wallet_amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                });
+            }
+            return Ok(addresses);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_reconciliation_discrepancies stored procedure
+    async fn reconciliation_discrepancies(&self, snapshot_id: i32) -> Result<Vec<OutputDiscrepancy>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_reconciliation_discrepancies($1)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&snapshot_id]).await?;
+            let mut discrepancies = Vec::with_capacity(rows.len());
+            for row in rows {
+                let engine_amount: Option<Decimal> = row.try_get(4)?;
+                let wallet_amount: Option<Decimal> = row.try_get(5)?;
+                discrepancies.push(OutputDiscrepancy {
+                    txid: row.try_get(0)?,
+                    vout: row.try_get(1)?,
+                    address: row.try_get(2)?,
+                    kind: row.try_get(3)?,
+                    engine_amount: engine_amount.map(|amount| amount.to_f64()
+                        .ok_or("Failed to convert Decimal to f64")).transpose()?,
+                    wallet_amount: wallet_amount.map(|amount| amount.to_f64()
+                        .ok_or("Failed to convert Decimal to f64")).transpose()?,
+                });
+            }
+            return Ok(discrepancies);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Execute get_journal stored procedure
+    async fn journal(&self, account: Option<&str>, txid: Option<&str>) -> Result<Vec<JournalLine>, Box<dyn Error>> {
+        let procedure = "SELECT * FROM get_journal($1, $2)";
+        if let Some(client) = &self.client {
+            let rows = client.query(procedure, &[&account, &txid]).await?;
+            let mut lines = Vec::with_capacity(rows.len());
+            for row in rows {
+                let amount: Decimal = row.try_get(8)?;
+                lines.push(JournalLine {
+                    entry_id: row.try_get(0)?,
+                    txid: row.try_get(1)?,
+                    sequence: row.try_get(2)?,
+                    kind: row.try_get(3)?,
+                    batch_id: row.try_get(4)?,
+                    posted_at: row.try_get(5)?,
+                    account_kind: row.try_get(6)?,
+                    account: row.try_get(7)?,
+                    amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+                });
+            }
+            return Ok(lines);
+        }
+        Err(Box::new(std::io::Error::other("No database client available")))
+    }
+
+    // Open a transaction so a batch is stored all or nothing
+    async fn begin_transaction(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(client) = &self.client {
+            client.batch_execute("BEGIN").await?;
+            Ok(())
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Commit the transaction opened by begin_transaction
+    async fn commit_transaction(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(client) = &self.client {
+            client.batch_execute("COMMIT").await?;
+            Ok(())
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Discard the transaction opened by begin_transaction
+    async fn rollback_transaction(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(client) = &self.client {
+            client.batch_execute("ROLLBACK").await?;
+            Ok(())
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Open a read only REPEATABLE READ transaction so every query
+    // issued until end_snapshot sees the same committed data
+    async fn begin_snapshot(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(client) = &self.client {
+            client.batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY").await?;
+            Ok(())
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
+    // Release the snapshot opened by begin_snapshot
+    async fn end_snapshot(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(client) = &self.client {
+            client.batch_execute("COMMIT").await?;
+            Ok(())
+        } else {
+            Err(Box::new(std::io::Error::other("No database client available")))
+        }
+    }
+
     // Class deconstructor
     fn close(&mut self) {
         self.client = None;
     }
 }
+
+// Convert a bucket_start, transaction_count, total_amount row
+fn stored_transaction(row: &Row) -> Result<StoredTransaction, Box<dyn Error>> {
+    let amount: Decimal = row.try_get("amount")?;
+    let fee: Option<Decimal> = row.try_get("fee")?;
+    let walletconflicts: Option<Vec<String>> = row.try_get("walletconflicts")?;
+    let lifecycle_status: Option<String> = row.try_get("lifecycle_status")?;
+    let lifecycle_updated_at: Option<DateTime<Utc>> = row.try_get("lifecycle_updated_at")?;
+    let transaction = Transaction {
+        involves_watchonly: row.try_get("involves_watchonly")?,
+        account: row.try_get("account")?,
+        address: row.try_get("address")?,
+        category: row.try_get("category")?,
+        amount: amount.to_f64().ok_or("Failed to convert Decimal to f64")?,
+        label: row.try_get("label")?,
+        confirmations: row.try_get("confirmations")?,
+        generated: row.try_get("generated")?,
+        blockhash: row.try_get("blockhash")?,
+        blockindex: row.try_get("blockindex")?,
+        blocktime: row.try_get("blocktime")?,
+        txid: row.try_get("txid")?,
+        vout: row.try_get("vout")?,
+        walletconflicts: walletconflicts.unwrap_or_default(),
+        time: row.try_get("time")?,
+        timereceived: row.try_get("timereceived")?,
+        bip125_replaceable: row.try_get("bip125_replaceable")?,
+        blockheight: row.try_get("blockheight")?,
+        fee: fee.map(|fee| fee.to_f64().ok_or("Failed to convert Decimal to f64")).transpose()?,
+        abandoned: row.try_get("abandoned")?,
+        comment: row.try_get("comment")?,
+        to: row.try_get("comment_to")?,
+    };
+    Ok(StoredTransaction {
+        transaction,
+        removed: row.try_get("removed")?,
+        main_chain: row.try_get("is_main_chain")?,
+        confirmations: row.try_get("current_confirmations")?,
+        confirmed: row.try_get("is_confirmed")?,
+        conflicted: row.try_get("is_conflicted")?,
+        customer: row.try_get("customer")?,
+        attribution: row.try_get("attribution")?,
+        batch_id: row.try_get("batch_id")?,
+        batch_source: row.try_get("batch_source")?,
+        ingested_at: row.try_get("ingested_at")?,
+        lifecycle: match (lifecycle_status, lifecycle_updated_at) {
+            (Some(status), Some(updated_at)) => Some((status.parse::<DepositStatus>()?, updated_at)),
+            _ => None,
+        },
+    })
+}
+fn deposit_bucket(row: &Row) -> Result<DepositBucket, Box<dyn Error>> {
+    let amount: Option<Decimal> = row.try_get(2)?;
+    Ok(DepositBucket {
+        start: row.try_get(0)?,
+        count: row.try_get(1)?,
+        amount: amount.unwrap_or_default().to_f64().ok_or("Failed to convert Decimal to f64")?,
+    })
+}
+
+// Address, label customer and unreferenced flag that select a scope in the statistics functions
+fn scope_filter(scope: &DepositScope) -> (Option<&str>, Option<&str>, bool) {
+    match scope {
+        DepositScope::All => (None, None, false),
+        DepositScope::Address(address) => (Some(address.as_str()), None, false),
+        DepositScope::Label(name) => (None, Some(name.as_str()), false),
+        DepositScope::Unreferenced => (None, None, true),
+    }
+}
+fn amount_bounds(filter: &UnreferencedFilter) -> Result<(Option<Decimal>, Option<Decimal>), Box<dyn Error>> {
+    let to_decimal = |amount: Option<f64>| amount
+        .map(|amount| Decimal::from_f64(amount).ok_or("Failed to convert f64 to Decimal"))
+        .transpose();
+    Ok((to_decimal(filter.min_amount)?, to_decimal(filter.max_amount)?))
+}
@@ -0,0 +1,273 @@
+/*
+handlers/src/ingest.rs
+10/18/26
+
+This file defines the ingest pipeline.
+Transactions are inserted in chunks of INGEST_BATCH_SIZE. Files are
+parsed on a blocking thread that feeds a bounded channel, so the
+parser waits whenever the database falls behind and memory use
+stays flat regardless of file size.
+Files are scanned once for their hash, snapshot tip and removed
+entries before the second pass stores them, so block heights and
+reorgs are settled the same whatever the chunk size or row order.
+Both passes hash the bytes they parse, and a file that changed in
+between fails the batch.
+Every row is stamped with the id of the ingest batch it came from,
+and entries failing validation are quarantined instead of stored.
+Deposits the batch made valid are posted to the ledger with it, and
+every deposit it moved along its lifecycle records the transition.
+Provisional deposits it changed get a notice.
+*/
+use models::{ Network, ResponseTrailer, Transaction, stream_from_source };
+use config::Config;
+use database::DatabaseDriver;
+use sha2::{ Digest, Sha256 };
+use serde_json::Value;
+use sources::Batch;
+use std::error::Error;
+use std::fs::File;
+use std::io::{ self, Read };
+use tokio::sync::mpsc;
+use crate::{ utils, validate };
+
+// How the entries of a batch were handled
+#[derive(Debug, Default)]
+pub struct IngestCounts {
+    pub stored: usize,
+    pub quarantined: usize,
+    pub posted: i32,
+    pub transitions: i32,
+    pub notices: i32,
+}
+
+// What a batch holds before any of it is stored: the hex encoded
+// SHA-256 of its content, the snapshot tip its rows imply, and the
+// removed entries and lastblock it ends with
+pub struct BatchScan {
+    pub sha256: String,
+    implied_tip: Option<i32>,
+    removed: Vec<Transaction>,
+    lastblock: Option<String>,
+}
+
+// Reads the wrapped content through while hashing it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.hasher.update(&buffer[..read]);
+        Ok(read)
+    }
+}
+
+// Where a batch came from, as recorded in ingest_batches
+pub fn batch_source(batch: &Batch, source_name: &str) -> String {
+    match batch {
+        Batch::Response { .. } => source_name.to_string(),
+        Batch::File(file) => file.path.clone(),
+    }
+}
+
+// Scan a batch without storing anything. Responses are hashed as the
+// body bitcoind sent, files as the bytes the parser reads
+pub async fn scan_batch(config: &Config, batch: &mut Batch) -> Result<BatchScan, Box<dyn Error>> {
+    match batch {
+        Batch::Response { response, sha256, .. } => Ok(BatchScan {
+            sha256: sha256.clone(),
+            implied_tip: response.transactions.iter()
+                .filter_map(|entry| implied_tip(entry, config.network))
+                .max(),
+            removed: std::mem::take(&mut response.removed),
+            lastblock: response.lastblock.take(),
+        }),
+        Batch::File(file) => scan_file(config, file.path.clone()).await,
+    }
+}
+
+// This method uploads one scanned batch to the database and stores
+// its lastblock as the cursor of its source for the next call
+pub async fn ingest_batch<D: DatabaseDriver>(config: &Config, db_driver: &D, batch: Batch, scan: BatchScan,
+    cursor: &str, batch_id: i32) -> Result<IngestCounts, Box<dyn Error>> {
+    let mut counts = IngestCounts::default();
+
+    // Rows carrying a block height pin the snapshot tip down exactly,
+    // otherwise fall back to the one configured for the source or
+    // reported by the node
+    let tip = scan.implied_tip.or(batch.snapshot_tip());
+
+    // Reverse deposits dropped by a reorg before storing the rest
+    utils::remove_all_transactions(&scan.removed, tip, db_driver).await?;
+
+    match batch {
+        Batch::Response { response, .. } => {
+            let mut entries = response.transactions.into_iter();
+            loop {
+                let chunk: Vec<Value> = entries.by_ref().take(config.ingest_batch_size).collect();
+                if chunk.is_empty() {
+                    break;
+                }
+                load_chunk(config, db_driver, chunk, batch_id, &mut counts).await?;
+            }
+        },
+        Batch::File(file) => {
+            let sha256 = stream_file(config, db_driver, file.path.clone(), batch_id, &mut counts).await?;
+            if sha256 != scan.sha256 {
+                return Err(Box::from(format!("{}: changed while it was being loaded", file.path)));
+            }
+        },
+    }
+
+    // Settle the main chain from everything this snapshot saw
+    db_driver.resolve_main_chain(batch_id, tip).await?;
+
+    // Remember the snapshot tip so quarantined entries re-submitted later
+    // get their heights from it, and advance the chain tip to it
+    if let Some(height) = tip {
+        db_driver.set_ingest_batch_tip(batch_id, height).await?;
+        db_driver.set_chain_tip(height).await?;
+    }
+
+    // Persist the cursor for the next listsinceblock call
+    if let Some(lastblock) = &scan.lastblock {
+        db_driver.set_last_block(cursor, lastblock).await?;
+    }
+
+    // Credit what became valid and reverse what no longer is,
+    // then record where every deposit stands after that
+    counts.posted = db_driver.post_ledger_entries(Some(batch_id)).await?;
+    counts.transitions = db_driver.update_deposit_states(Some(batch_id)).await?;
+    counts.notices = db_driver.record_deposit_notices(Some(batch_id)).await?;
+
+    Ok(counts)
+}
+
+// This method uploads transactions that already passed validation,
+// such as quarantined entries re-submitted after a fix, posts them
+// to the ledger once they are valid and records their status.
+// The snapshot tip is the one of the batch the entries came from
+pub async fn store_transactions<D: DatabaseDriver>(config: &Config, db_driver: &D,
+    transactions: &[Transaction], snapshot_tip: Option<i32>, batch_id: i32) -> Result<(), Box<dyn Error>> {
+    let tip = transactions.iter().filter_map(utils::implied_tip_height).max().or(snapshot_tip);
+    for chunk in transactions.chunks(config.ingest_batch_size) {
+        insert_chunk(db_driver, chunk, batch_id).await?;
+    }
+    db_driver.resolve_main_chain(batch_id, tip).await?;
+    if let Some(height) = tip {
+        db_driver.set_chain_tip(height).await?;
+    }
+    db_driver.post_ledger_entries(Some(batch_id)).await?;
+    db_driver.update_deposit_states(Some(batch_id)).await?;
+    db_driver.record_deposit_notices(Some(batch_id)).await?;
+    Ok(())
+}
+
+// The snapshot tip implied by a raw entry, if it is valid and carries its block height
+fn implied_tip(entry: &Value, network: Network) -> Option<i32> {
+    validate::validate_transaction(entry, network).ok()
+        .and_then(|transaction| utils::implied_tip_height(&transaction))
+}
+
+// Parse a file on a blocking thread, handing every entry to the callback,
+// and return the trailer together with the hash of the bytes read
+fn hash_and_stream<F>(path: &str, on_transaction: F) -> Result<(String, ResponseTrailer<Value>), String>
+where
+    F: FnMut(Value) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut reader = HashingReader { inner: file, hasher: Sha256::new() };
+    let trailer = stream_from_source(path, &mut reader, on_transaction)
+        .and_then(|trailer| {
+            // Anything after the content is part of the file all the same
+            io::copy(&mut reader, &mut io::sink())?;
+            Ok(trailer)
+        })
+        .map_err(|e| format!("{}: {}", path, e))?;
+    Ok((format!("{:x}", reader.hasher.finalize()), trailer))
+}
+
+// Read a file through once on a blocking thread without storing anything
+async fn scan_file(config: &Config, path: String) -> Result<BatchScan, Box<dyn Error>> {
+    let network = config.network;
+    let (sha256, implied_tip, trailer) = tokio::task::spawn_blocking(move || {
+        let mut tip = None;
+        let (sha256, trailer) = hash_and_stream(&path, |entry: Value| {
+            tip = tip.max(implied_tip(&entry, network));
+            Ok(())
+        })?;
+        Ok::<_, String>((sha256, tip, trailer))
+    }).await??;
+
+    // Removed entries are reversed, never stored, so they must parse
+    let removed = trailer.removed.into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<Vec<Transaction>, _>>()?;
+    Ok(BatchScan { sha256, implied_tip, removed, lastblock: trailer.lastblock })
+}
+
+// Parse a file on a blocking thread and load what it yields in chunks.
+// Returns the hash of what was parsed
+async fn stream_file<D: DatabaseDriver>(config: &Config, db_driver: &D, path: String,
+    batch_id: i32, counts: &mut IngestCounts) -> Result<String, Box<dyn Error>> {
+    let (sender, mut receiver) = mpsc::channel::<Value>(config.ingest_batch_size);
+
+    // blocking_send parks the parser while the channel is full
+    let parser = tokio::task::spawn_blocking(move || {
+        hash_and_stream(&path, |entry: Value| {
+            sender.blocking_send(entry).map_err(|_| Box::from("Ingest pipeline closed"))
+        })
+    });
+
+    let mut chunk = Vec::with_capacity(config.ingest_batch_size);
+    while let Some(entry) = receiver.recv().await {
+        chunk.push(entry);
+        if chunk.len() == config.ingest_batch_size {
+            // Dropping the receiver on error stops the parser
+            load_chunk(config, db_driver, std::mem::take(&mut chunk), batch_id, counts).await?;
+        }
+    }
+    if !chunk.is_empty() {
+        load_chunk(config, db_driver, chunk, batch_id, counts).await?;
+    }
+
+    // The trailer was taken from the scan already
+    let (sha256, _) = parser.await??;
+    Ok(sha256)
+}
+
+// Validate one chunk of raw entries, upload those that pass
+// and quarantine the rest with the reason they were rejected
+async fn load_chunk<D: DatabaseDriver>(config: &Config, db_driver: &D, chunk: Vec<Value>,
+    batch_id: i32, counts: &mut IngestCounts) -> Result<(), Box<dyn Error>> {
+    let mut transactions = Vec::with_capacity(chunk.len());
+    let mut rejected = Vec::new();
+    for entry in chunk {
+        match validate::validate_transaction(&entry, config.network) {
+            Ok(transaction) => transactions.push(transaction),
+            Err(reason) => rejected.push((entry, reason)),
+        }
+    }
+
+    insert_chunk(db_driver, &transactions, batch_id).await?;
+    if !rejected.is_empty() {
+        db_driver.quarantine_transactions(&rejected, batch_id).await?;
+    }
+
+    counts.stored += transactions.len();
+    counts.quarantined += rejected.len();
+    Ok(())
+}
+
+// Upload one chunk of transactions together with their blocks.
+// Heights missing from the rows are derived once the batch is in
+async fn insert_chunk<D: DatabaseDriver>(db_driver: &D, chunk: &[Transaction], batch_id: i32)
+-> Result<(), Box<dyn Error>> {
+    // Upload Blocks to the db
+    utils::insert_all_blocks(chunk, batch_id, db_driver).await?;
+
+    // Upload Transactions to the db
+    utils::insert_all_transactions(chunk, batch_id, db_driver).await
+}
@@ -0,0 +1,50 @@
+/*
+handlers/src/invoices.rs
+10/18/26
+
+This file defines the invoice report.
+The configured invoices are loaded first, then counted per status, then listed one line each,
+optionally with every deposit matched to them and how it matched.
+*/
+use models::{ InvoicePayment, InvoiceStatus };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use crate::utils::{ self, format_amount };
+
+// This method prints how many invoices are in each status and lists
+// the invoices, only those in the given status when there is one
+pub async fn list_invoices(config: &Config, status: Option<InvoiceStatus>, payments: bool)
+-> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+    utils::load_invoices(config, &db_driver).await?;
+
+    // Statuses and payments must come from the same data
+    db_driver.begin_snapshot().await?;
+    let invoices = db_driver.invoice_statuses().await?;
+    let matched = if payments { db_driver.invoice_payments().await? } else { Vec::new() };
+    db_driver.end_snapshot().await?;
+
+    let counts: Vec<String> = InvoiceStatus::ALL.iter()
+        .map(|each| format!("{}={}", each, invoices.iter().filter(|invoice| invoice.status == *each).count()))
+        .collect();
+    println!("Invoices by status: {}", counts.join(" "));
+
+    for invoice in invoices.iter().filter(|invoice| status.is_none_or(|status| invoice.status == status)) {
+        println!("Invoice {0} for {1} at {2}: status={3} expected={4} paid={5} payments={6} created={7} expires={8}",
+            invoice.id, invoice.customer.as_deref().unwrap_or("no reference"), invoice.address, invoice.status,
+            format_amount(Some(invoice.amount)), format_amount(Some(invoice.paid)), invoice.payments,
+            invoice.created.to_rfc3339(), invoice.expires.to_rfc3339());
+        for payment in matched.iter().filter(|payment| payment.invoice_id == invoice.id) {
+            print_payment(payment);
+        }
+    }
+    Ok(())
+}
+
+// One deposit matched to an invoice
+fn print_payment(payment: &InvoicePayment) {
+    println!("    {0} {1}:{2} amount={3} paid={4}", payment.kind, payment.txid, payment.vout,
+        format_amount(Some(payment.amount)), payment.paid_at.to_rfc3339());
+}
@@ -0,0 +1,72 @@
+/*
+handlers/src/ledger.rs
+10/18/26
+
+This file defines the ledger reports.
+Balances are derived from journal lines only, never from the raw
+transactions, so they show what was actually credited to whom.
+*/
+use models::JournalLine;
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use crate::utils::{ self, format_amount };
+
+// This method prints the balance of every ledger account and
+// checks that the wallet holds what was credited to the others
+pub async fn ledger_balances(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let balances = db_driver.ledger_balances().await?;
+
+    let mut received = 0.0;
+    let mut credited = 0.0;
+    for account in &balances {
+        println!("Balance of {0} {1}: balance={2} lines={3}",
+            account.kind, account.name, format_amount(Some(account.balance)), account.entries);
+        if account.kind == "wallet" {
+            received += account.balance;
+        } else {
+            credited += account.balance;
+        }
+    }
+
+    // Every entry debits what it credits, so this only fails on a broken journal
+    if !utils::amounts_match(received, credited) {
+        return Err(Box::from(format!("Ledger does not balance: received={0} credited={1}", received, credited)));
+    }
+    println!("Ledger balances: received={0} credited={1}", format_amount(Some(received)), format_amount(Some(credited)));
+    Ok(())
+}
+
+// This method prints the journal entries touching an account or a
+// txid, every entry followed by its lines
+pub async fn ledger_journal(config: &Config, account: Option<&str>, txid: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let lines = db_driver.journal(account, txid).await?;
+    if lines.is_empty() {
+        println!("No journal entries");
+    }
+
+    let mut entry_id = None;
+    for line in &lines {
+        if entry_id != Some(line.entry_id) {
+            print_entry(line);
+            entry_id = Some(line.entry_id);
+        }
+        let side = if line.amount >= 0.0 { "debit" } else { "credit" };
+        println!("    {0} {1} {2}={3}", line.account_kind, line.account, side, format_amount(Some(line.amount.abs())));
+    }
+    Ok(())
+}
+
+// Header of a journal entry
+fn print_entry(line: &JournalLine) {
+    println!("Entry #{0} {1} {2} sequence={3} batch={4} posted={5}",
+        line.entry_id, line.kind, line.txid, line.sequence,
+        line.batch_id.map_or(String::from("none"), |batch_id| batch_id.to_string()),
+        line.posted_at.to_rfc3339());
+}
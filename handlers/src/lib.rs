@@ -6,16 +6,43 @@ This file defines the critical section of the codebase.
 These handlers are used to orchestrate calls to lower level
 crates and perform central logic.
 */
-use models::{ Transactions, KnownCustomersArray, from_file };
+use models::{ DepositBucket, KnownCustomersArray, ReportWindow, from_file };
 use config::Config;
 use database::{ DatabaseDriver, PostgresDriver };
+use logger::{ Logger, log };
+use sources::{ Batch, DepositSource };
 use std::error::Error;
+use std::sync::atomic::{ AtomicBool, Ordering };
 
+mod ingest;
+mod invoices;
+mod ledger;
+mod lookup;
+mod provisional;
+mod quarantine;
+mod reconcile;
+mod statistics;
+mod unreferenced;
 mod utils;
+mod validate;
+mod withdrawals;
 
-// This method creates a db connection, loads our input data,
-// and uploads it to the database
-pub async fn load_data(config: &Config) -> Result<(), Box<dyn Error>> {
+pub use invoices::list_invoices;
+pub use ledger::{ ledger_balances, ledger_journal };
+pub use lookup::{ list_address_transactions, show_transaction };
+pub use provisional::{ provisional_deposits, stream_notices };
+pub use quarantine::{ fix_quarantined, list_quarantined, resubmit_quarantined };
+pub use reconcile::reconcile_wallet;
+pub use statistics::deposit_statistics;
+pub use unreferenced::list_unreferenced;
+pub use withdrawals::{ list_withdrawals, net_balances };
+
+// This method creates a db connection, drains our deposit sources,
+// and uploads them to the database one batch at a time.
+// A shutdown request stops it between batches, never inside one.
+// Files already loaded with the same content are skipped unless forced
+pub async fn load_data(config: &Config, sources: &mut [Box<dyn DepositSource + Send>],
+    shutdown: &AtomicBool, force: bool, logger: &Logger) -> Result<(), Box<dyn Error>> {
     let mut db_driver = PostgresDriver::new();
     match db_driver.connect(&config.db_connection_string).await {
         Ok(()) => {},
@@ -28,45 +55,146 @@ pub async fn load_data(config: &Config) -> Result<(), Box<dyn Error>> {
         Err(e) => return Err(e)
     };
 
+    // Reject known customers whose address does not belong to the network
+    known_customers.validate(config.network)?;
+
     // Upload Known Customers to the db
-    if let Err(e) = utils::insert_all_known_clients(&known_customers, &db_driver).await {
-        return Err(e);
+    utils::insert_all_known_clients(&known_customers, &db_driver).await?;
+
+    // Replace the label rules with the configured ones
+    db_driver.set_label_rules(&known_customers.label_rules).await?;
+
+    // Replace what withdrawals are attributed by with the configured payouts
+    db_driver.set_payout_addresses(&known_customers.known_customers).await?;
+    db_driver.set_payout_label_rules(&known_customers.payout_label_rules).await?;
+
+    // Replace the invoices with the configured ones
+    utils::load_invoices(config, &db_driver).await?;
+
+    // Advance the chain tip so stored deposits mature without re-ingest
+    if let Some(height) = config.chain_tip_height {
+        db_driver.set_chain_tip(height).await?;
     }
 
-    // Load Transaction Data
-    let mut data = Transactions { transactions: Vec::new() };
-    for file in &config.input_data {
-        let mut input = match from_file::<Transactions>(&file) {
-            Ok(input) => input,
-            Err(e) => return Err(e)
-        };
-        data.transactions.append(&mut input.transactions);
+    // Drain every source in order, so later snapshots win
+    for source in sources.iter_mut() {
+        // Every source keeps a cursor of its own
+        let cursor = format!("{}:{}", config.wallet_name, source.name());
+        while !shutdown.load(Ordering::SeqCst) {
+            // Resume from the cursor stored by the previous batch
+            let lastblock = db_driver.get_last_block(&cursor).await?;
+            let mut batch = match source.next_batch(lastblock.as_deref()).await {
+                Ok(Some(batch)) => batch,
+                Ok(None) => break,
+                Err(e) => return Err(Box::from(format!("{}: {}", source.name(), e)))
+            };
+
+            // Record where the batch came from before loading it,
+            // so failed loads stay on record too
+            let origin = ingest::batch_source(&batch, &source.name());
+            let scan = ingest::scan_batch(config, &mut batch).await?;
+
+            // Skip a file whose exact content was loaded before
+            if !force && matches!(batch, Batch::File(_)) {
+                if let Some(previous) = db_driver.find_ingested_batch(&scan.sha256).await? {
+                    log!(logger, info, "Skipped {}: already ingested as batch {}", origin, previous);
+                    continue;
+                }
+            }
+
+            let batch_id = db_driver.start_ingest_batch(&origin, &scan.sha256).await?;
+
+            // The batch, its cursor and its completion are committed together, so
+            // a restart resumes after the last committed batch and never repeats one
+            db_driver.begin_transaction().await?;
+            let counts = match ingest::ingest_batch(config, &db_driver, batch, scan, &cursor, batch_id).await {
+                Ok(counts) => counts,
+                Err(e) => {
+                    db_driver.rollback_transaction().await?;
+                    db_driver.finish_ingest_batch(batch_id, 0, 0, "failed").await?;
+                    return Err(e);
+                }
+            };
+            db_driver.finish_ingest_batch(batch_id, i32::try_from(counts.stored)?,
+                i32::try_from(counts.quarantined)?, "completed").await?;
+            db_driver.commit_transaction().await?;
+            log!(logger, info, "Ingested {}: rows={} quarantined={} posted={} transitions={} notices={} batch={}",
+                origin, counts.stored, counts.quarantined, counts.posted, counts.transitions, counts.notices, batch_id);
+        }
     }
 
-    // Upload Transactions to the db
-    if let Err(e) = utils::insert_all_transactions(&data, &db_driver).await {
-        return Err(e);
+    // Deposits can also mature or fall behind a reorg without a new batch,
+    // when the configured chain tip moved. An error drops the connection,
+    // which rolls the postings back
+    db_driver.begin_transaction().await?;
+    let posted = db_driver.post_ledger_entries(None).await?;
+    let transitions = db_driver.update_deposit_states(None).await?;
+    let notices = db_driver.record_deposit_notices(None).await?;
+    db_driver.commit_transaction().await?;
+    if posted > 0 || transitions > 0 || notices > 0 {
+        log!(logger, info, "Posted {} ledger entries, {} transitions and {} notices outside of a batch",
+            posted, transitions, notices);
     }
 
     Ok(())
 }
 
-// This method creates a db connection, queries the transaction data
-// for each known customer, and prints the result
-pub async fn known_customer_deposits(config: &Config) -> Result<(), Box<dyn Error>> {
+// This method creates a single db connection and runs every report
+// inside one snapshot, so the known, unknown and range reports are
+// computed against the same data even if an ingest runs concurrently.
+// A bounded or bucketed window runs the windowed report instead
+pub async fn generate_reports(config: &Config, window: &ReportWindow) -> Result<(), Box<dyn Error>> {
     let mut db_driver = PostgresDriver::new();
     match db_driver.connect(&config.db_connection_string).await {
         Ok(()) => {},
         Err(e) => return Err(e)
     }
 
+    // Open Snapshot
+    db_driver.begin_snapshot().await?;
+
+    if !window.is_lifetime() {
+        windowed_deposits(config, &db_driver, window).await?;
+        return db_driver.end_snapshot().await;
+    }
+
+    let known_total = known_customer_deposits(config, &db_driver).await?;
+    let label_total = label_attributed_deposits(config, &db_driver).await?;
+    let unknown_total = unknown_customer_deposits(&db_driver).await?;
+    calculate_range(&db_driver).await?;
+
+    // Query Overall Balance
+    let total = match db_driver.total_deposit_amount().await {
+        Ok(Some(amount)) => amount,
+        Ok(None) => return Err(Box::from("No Deposits Found")),
+        Err(e) => return Err(e)
+    };
+
+    // Cross-check that known, label attributed and unknown deposits add up to the total
+    if !utils::amounts_match(known_total + label_total + unknown_total, total) {
+        return Err(Box::from(format!(
+            "Report totals do not add up: known={0} label={1} unknown={2} total={3}",
+            known_total, label_total, unknown_total, total)));
+    }
+
+    // Close Snapshot
+    db_driver.end_snapshot().await
+}
+
+// This method queries the transaction data for each known customer,
+// prints the result and returns the sum across all known customers
+async fn known_customer_deposits<D: DatabaseDriver>(config: &Config, db_driver: &D) -> Result<f64, Box<dyn Error>> {
     // Load Known Customers
     let known_customers = match from_file::<KnownCustomersArray>(&config.known_customers) {
         Ok(known_customers) => known_customers,
         Err(e) => return Err(e)
     };
 
+    // Reject known customers whose address does not belong to the network
+    known_customers.validate(config.network)?;
+
     // Iterate through Known Clients
+    let mut known_total = 0.0;
     for customer in known_customers.known_customers {
         // Query Balance
         let balance = match db_driver.known_wallet_deposit_amount(&customer.address).await {
@@ -83,20 +211,32 @@ pub async fn known_customer_deposits(config: &Config) -> Result<(), Box<dyn Erro
 
         // Log Output
         println!("Deposited for {0}: count={1} sum={2}", customer.name, txn_count, balance);
+        known_total += balance;
     }
 
-    Ok(())
+    Ok(known_total)
 }
 
-// This method creates a db connection, queries the transaction data
-// for each unknown customer, and prints the result
-pub async fn unknown_customer_deposits(config: &Config) -> Result<(), Box<dyn Error>> {
-    let mut db_driver = PostgresDriver::new();
-    match db_driver.connect(&config.db_connection_string).await {
-        Ok(()) => {},
-        Err(e) => return Err(e)
+// This method queries the deposits label rules attribute to each customer,
+// prints the result and returns the sum across all of them.
+// Nothing is printed when no label rules are configured
+async fn label_attributed_deposits<D: DatabaseDriver>(config: &Config, db_driver: &D) -> Result<f64, Box<dyn Error>> {
+    let known_customers = from_file::<KnownCustomersArray>(&config.known_customers)?;
+
+    let mut label_total = 0.0;
+    for name in known_customers.label_customers() {
+        let buckets = db_driver.label_attributed_deposits_by_bucket(name, &ReportWindow::default()).await?;
+        let (count, amount) = buckets.first().map_or((0, 0.0), |bucket| (bucket.count, bucket.amount));
+        println!("Attributed by label to {0}: count={1} sum={2}", name, count, amount);
+        label_total += amount;
     }
 
+    Ok(label_total)
+}
+
+// This method queries the transaction data for every unknown customer,
+// prints the result and returns the unknown sum
+async fn unknown_customer_deposits<D: DatabaseDriver>(db_driver: &D) -> Result<f64, Box<dyn Error>> {
     // Query Balance
     let balance = match db_driver.unknown_wallet_deposit_amount().await {
         Ok(Some(amount)) => amount,
@@ -114,18 +254,58 @@ pub async fn unknown_customer_deposits(config: &Config) -> Result<(), Box<dyn Er
     // Log Output
     println!("Deposited without reference: count={0} sum={1}", txn_count, balance);
 
-    Ok(())
+    Ok(balance)
 }
 
-// This method creates a db connection, queries the transaction data
-// for the smallest and largest deposits
-pub async fn calculate_range(config: &Config) -> Result<(), Box<dyn Error>> {
-    let mut db_driver = PostgresDriver::new();
-    match db_driver.connect(&config.db_connection_string).await {
-        Ok(()) => {},
+// This method queries the deposits of each known customer and of
+// unknown addresses within a time window, one line per bucket
+async fn windowed_deposits<D: DatabaseDriver>(config: &Config, db_driver: &D, window: &ReportWindow)
+-> Result<(), Box<dyn Error>> {
+    // Load Known Customers
+    let known_customers = match from_file::<KnownCustomersArray>(&config.known_customers) {
+        Ok(known_customers) => known_customers,
         Err(e) => return Err(e)
+    };
+
+    // Reject known customers whose address does not belong to the network
+    known_customers.validate(config.network)?;
+
+    println!("Deposits {}", window);
+
+    for customer in &known_customers.known_customers {
+        let buckets = db_driver.known_wallet_deposits_by_bucket(&customer.address, window).await?;
+        print_buckets(&format!("Deposited for {}", customer.name), window, &buckets);
+    }
+
+    for name in known_customers.label_customers() {
+        let buckets = db_driver.label_attributed_deposits_by_bucket(name, window).await?;
+        print_buckets(&format!("Attributed by label to {}", name), window, &buckets);
+    }
+
+    let buckets = db_driver.unknown_wallet_deposits_by_bucket(window).await?;
+    print_buckets("Deposited without reference", window, &buckets);
+
+    Ok(())
+}
+
+// Print one line per bucket. An unbucketed window always gets
+// a line, so customers without deposits show a zero count
+fn print_buckets(prefix: &str, window: &ReportWindow, buckets: &[DepositBucket]) {
+    if window.bucket.is_none() && buckets.is_empty() {
+        println!("{}: count=0 sum=0", prefix);
     }
+    for bucket in buckets {
+        match (window.bucket, bucket.start) {
+            (Some(size), Some(start)) => println!("{0} in {1}: count={2} sum={3}",
+                prefix, size.label(&start), bucket.count, bucket.amount),
+            _ => println!("{0}: count={1} sum={2}", prefix, bucket.count, bucket.amount),
+        }
+    }
+}
 
+// This method queries the transaction data
+// for the smallest and largest deposits
+async fn calculate_range<D: DatabaseDriver>(db_driver: &D) -> Result<(), Box<dyn Error>> {
     // Query Min Balance
     let min = match db_driver.get_smallest_confirmed_amount().await {
         Ok(Some(amount)) => amount,
@@ -0,0 +1,86 @@
+/*
+handlers/src/lookup.rs
+10/18/26
+
+This file defines lookups of single stored transactions.
+Next to the fields as they were ingested, a lookup shows what the
+engine makes of a transaction: whether it counts as a deposit,
+whether it is in conflict, which customer it is attributed to, how
+it moved along its lifecycle and which ingest batch it came from.
+*/
+use models::{ StoredTransaction, validate_address };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver, TRANSACTIONS_PAGE_SIZE };
+use std::error::Error;
+use crate::utils::format_amount;
+
+// This method prints a stored transaction in full
+pub async fn show_transaction(config: &Config, txid: &str) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let stored = match db_driver.get_transaction(txid).await? {
+        Some(stored) => stored,
+        None => return Err(Box::from(format!("Transaction {} not found", txid))),
+    };
+
+    println!("Transaction {}", stored.transaction.txid);
+    println!("{}", serde_json::to_string_pretty(&stored.transaction)?);
+    println!("Status: {0} confirmations={1} conflicted={2} main_chain={3}",
+        stored.status(), stored.confirmations, stored.conflicted,
+        stored.main_chain.map_or(String::from("n/a"), |main_chain| main_chain.to_string()));
+    println!("Customer: {}", customer(&stored));
+    println!("Batch: {0} source={1} ingested={2}",
+        stored.batch_id, stored.batch_source, stored.ingested_at.to_rfc3339());
+
+    // Every status change, oldest first
+    println!("Lifecycle: {}", lifecycle(&stored));
+    for transition in db_driver.deposit_transitions(&stored.transaction.txid).await? {
+        println!("    {0} -> {1} at {2} batch={3}",
+            transition.from.map_or(String::from("new"), |from| from.to_string()), transition.to,
+            transition.at.to_rfc3339(), transition.batch_id.map_or(String::from("none"), |batch_id| batch_id.to_string()));
+    }
+    Ok(())
+}
+
+// This method lists one page of the stored transactions of an address,
+// one line per transaction
+pub async fn list_address_transactions(config: &Config, address: &str, page: u32) -> Result<(), Box<dyn Error>> {
+    validate_address(address, config.network)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let page = page.max(1);
+    let transactions = db_driver.list_transactions_by_address(address, page).await?;
+
+    println!("Transactions of {0}, page {1} ({2} per page)", address, page, TRANSACTIONS_PAGE_SIZE);
+    for stored in &transactions {
+        println!("{0}:{1} category={2} amount={3} time={4} status={5} lifecycle={6} confirmations={7} conflicted={8} customer={9} batch={10}",
+            stored.transaction.txid, stored.transaction.vout, stored.transaction.category,
+            format_amount(Some(stored.transaction.amount)), stored.transaction.time.to_rfc3339(),
+            stored.status(), lifecycle(stored), stored.confirmations, stored.conflicted, customer(stored), stored.batch_id);
+    }
+    if transactions.is_empty() {
+        println!("No transactions");
+    }
+    Ok(())
+}
+
+// The recorded lifecycle status and since when
+fn lifecycle(stored: &StoredTransaction) -> String {
+    match &stored.lifecycle {
+        Some((status, since)) => format!("{} since {}", status, since.to_rfc3339()),
+        None => String::from("none"),
+    }
+}
+
+// The customer a transaction is attributed to and how
+fn customer(stored: &StoredTransaction) -> String {
+    match (&stored.customer, &stored.attribution) {
+        (Some(name), Some(attribution)) => format!("{} (by {})", name, attribution),
+        (Some(name), None) => name.clone(),
+        _ => String::from("none"),
+    }
+}
@@ -0,0 +1,83 @@
+/*
+handlers/src/provisional.rs
+10/18/26
+
+This file defines the provisional deposit report and notice stream.
+Provisional deposits have fewer than 6 confirmations. They are never
+part of the creditable totals and are reported on their own, so
+customers can be told a deposit is on its way before it counts.
+*/
+use models::ProvisionalDeposit;
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+use crate::utils::format_amount;
+
+// Confirmations a deposit needs before it is creditable
+const REQUIRED_CONFIRMATIONS: i32 = 6;
+
+// Notices fetched per round trip
+const NOTICE_PAGE_SIZE: i32 = 500;
+
+// This method prints every provisional deposit, grouped by the customer
+// it would be credited to, followed by those without reference
+pub async fn provisional_deposits(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let deposits = db_driver.provisional_deposits().await?;
+
+    println!("Provisional deposits, below {} confirmations and not creditable yet", REQUIRED_CONFIRMATIONS);
+    for deposit in &deposits {
+        let prefix = match &deposit.customer {
+            Some(customer) => format!("Provisional for {}", customer),
+            None => String::from("Provisional without reference"),
+        };
+        print_deposit(&prefix, deposit);
+    }
+
+    let amount: f64 = deposits.iter().map(|deposit| deposit.amount).sum();
+    println!("Provisional total: count={0} sum={1}", deposits.len(), format_amount(Some(amount)));
+    Ok(())
+}
+
+// One line per provisional deposit
+fn print_deposit(prefix: &str, deposit: &ProvisionalDeposit) {
+    println!("{0}: {1}:{2} amount={3} confirmations={4}/{5} replaceable={6} seen={7} eta={8}",
+        prefix, deposit.txid, deposit.vout, format_amount(Some(deposit.amount)),
+        deposit.confirmations, REQUIRED_CONFIRMATIONS, deposit.replaceable,
+        deposit.first_seen.to_rfc3339(), deposit.estimated_maturity.to_rfc3339());
+}
+
+// This method writes the notices recorded after the given id as
+// newline-delimited JSON. When following, it keeps polling for new
+// notices every interval until SIGINT or SIGTERM
+pub async fn stream_notices(config: &Config, after: i32, follow: bool, interval: u64, shutdown: &AtomicBool)
+-> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let mut cursor = after;
+    while !shutdown.load(Ordering::SeqCst) {
+        let notices = db_driver.deposit_notices(cursor, NOTICE_PAGE_SIZE).await?;
+        for notice in &notices {
+            println!("{}", serde_json::to_string(notice)?);
+            cursor = notice.id;
+        }
+
+        // Drain full pages right away, wait only once caught up
+        if notices.len() == NOTICE_PAGE_SIZE as usize {
+            continue;
+        }
+        if !follow {
+            break;
+        }
+        let sleep_until = Instant::now() + Duration::from_secs(interval);
+        while !shutdown.load(Ordering::SeqCst) && Instant::now() < sleep_until {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+/*
+handlers/src/quarantine.rs
+10/18/26
+
+This file defines the handlers behind the quarantine commands.
+Rejected entries are listed, corrected in place and re-submitted.
+Re-submitted entries are stored in an ingest batch of their own,
+so their provenance shows they were repaired by hand.
+*/
+use models::QuarantinedTransaction;
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use sha2::{ Digest, Sha256 };
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{ self, Read };
+use crate::{ ingest, validate };
+
+// Source recorded in ingest_batches for re-submitted entries
+const QUARANTINE_SOURCE: &str = "quarantine";
+
+// Print the quarantined entries, pending ones unless all are asked for
+pub async fn list_quarantined(config: &Config, include_resolved: bool) -> Result<(), Box<dyn Error>> {
+    let db_driver = connect(config).await?;
+
+    let quarantined = db_driver.get_quarantined_transactions(include_resolved).await?;
+    for entry in &quarantined {
+        let status = match entry.resolved_batch_id {
+            Some(batch_id) => format!("resolved in batch {}", batch_id),
+            None => String::from("pending"),
+        };
+        println!("#{0} batch={1} {2}: {3}", entry.id, entry.batch_id, status, entry.reason);
+        println!("    {}", entry.raw);
+    }
+    println!("Quarantined entries: {}", quarantined.len());
+
+    Ok(())
+}
+
+// Replace a pending quarantined entry with corrected JSON
+pub async fn fix_quarantined(config: &Config, id: i32, json: &str) -> Result<(), Box<dyn Error>> {
+    let db_driver = connect(config).await?;
+
+    // Read the entry from stdin when given as -
+    let json = if json == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        json.to_string()
+    };
+    let raw: Value = serde_json::from_str(&json)?;
+
+    if !db_driver.fix_quarantined_transaction(id, &raw).await? {
+        return Err(Box::from(format!("No pending quarantined entry #{}", id)));
+    }
+
+    // Show right away whether the fix will pass
+    match validate::validate_transaction(&raw, config.network) {
+        Ok(_) => println!("Fixed #{}, ready to re-submit", id),
+        Err(reason) => println!("Fixed #{}, still invalid: {}", id, reason),
+    }
+
+    Ok(())
+}
+
+// Validate pending entries again, store those that pass and keep the
+// new reason on those that do not. Runs on every pending entry when no id is given
+pub async fn resubmit_quarantined(config: &Config, ids: &[i32]) -> Result<(), Box<dyn Error>> {
+    let db_driver = connect(config).await?;
+
+    let mut pending = db_driver.get_quarantined_transactions(false).await?;
+    if !ids.is_empty() {
+        if let Some(id) = ids.iter().find(|id| !pending.iter().any(|entry| entry.id == **id)) {
+            return Err(Box::from(format!("No pending quarantined entry #{}", id)));
+        }
+        pending.retain(|entry| ids.contains(&entry.id));
+    }
+    if pending.is_empty() {
+        println!("Nothing to re-submit");
+        return Ok(());
+    }
+
+    // Record the re-submission as a batch of its own
+    let raw: Vec<&Value> = pending.iter().map(|entry| &entry.raw).collect();
+    let sha256 = format!("{:x}", Sha256::digest(serde_json::to_vec(&raw)?));
+    let batch_id = db_driver.start_ingest_batch(QUARANTINE_SOURCE, &sha256).await?;
+
+    db_driver.begin_transaction().await?;
+    match store_pending(config, &db_driver, &pending, batch_id).await {
+        Ok((stored, rejected)) => {
+            db_driver.finish_ingest_batch(batch_id, i32::try_from(stored)?, i32::try_from(rejected)?, "completed").await?;
+            db_driver.commit_transaction().await?;
+            println!("Re-submitted in batch {0}: stored={1} still quarantined={2}", batch_id, stored, rejected);
+            Ok(())
+        },
+        Err(e) => {
+            db_driver.rollback_transaction().await?;
+            db_driver.finish_ingest_batch(batch_id, 0, 0, "failed").await?;
+            Err(e)
+        }
+    }
+}
+
+// Store the entries that pass validation now and resolve them.
+// Returns how many were stored and how many were rejected again
+async fn store_pending<D: DatabaseDriver>(config: &Config, db_driver: &D,
+    pending: &[QuarantinedTransaction], batch_id: i32) -> Result<(usize, usize), Box<dyn Error>> {
+    // Entries get their block heights from the snapshot they came from
+    let mut by_batch: BTreeMap<i32, Vec<&QuarantinedTransaction>> = BTreeMap::new();
+    for entry in pending {
+        by_batch.entry(entry.batch_id).or_default().push(entry);
+    }
+
+    let mut stored = 0;
+    let mut rejected = 0;
+    for (origin_batch_id, entries) in by_batch {
+        let mut transactions = Vec::new();
+        let mut resolved = Vec::new();
+        for entry in entries {
+            match validate::validate_transaction(&entry.raw, config.network) {
+                Ok(transaction) => {
+                    transactions.push(transaction);
+                    resolved.push(entry.id);
+                },
+                Err(reason) => {
+                    db_driver.resubmit_quarantined_transaction(entry.id, None, Some(&reason)).await?;
+                    rejected += 1;
+                }
+            }
+        }
+
+        let snapshot_tip = db_driver.get_ingest_batch_tip(origin_batch_id).await?;
+        ingest::store_transactions(config, db_driver, &transactions, snapshot_tip, batch_id).await?;
+        for id in resolved {
+            db_driver.resubmit_quarantined_transaction(id, Some(batch_id), None).await?;
+        }
+        stored += transactions.len();
+    }
+
+    Ok((stored, rejected))
+}
+
+// Open a db connection for a command
+async fn connect(config: &Config) -> Result<PostgresDriver, Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+    Ok(db_driver)
+}
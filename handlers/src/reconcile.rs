@@ -0,0 +1,105 @@
+/*
+handlers/src/reconcile.rs
+10/18/26
+
+This file defines the reconciliation against the wallet.
+A listunspent snapshot, read from a file or fetched from bitcoind,
+is stored and compared per address and per output with the deposits
+the engine holds, and its getbalances total with the net of deposits,
+withdrawals and fees. Deposits swept or spent since show up as not in
+the wallet and the change of sends as change, so only the balance and
+outputs nothing explains make a reconciliation fail.
+*/
+use models::{ OutputDiscrepancy, UnspentOutput, WalletBalances, WalletSnapshot, from_file };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use sources::BitcoindRpcSource;
+use std::error::Error;
+use crate::utils::{ self, format_amount };
+
+// This method reconciles the engine against a wallet snapshot, read from
+// the given files or, without them, fetched from the configured bitcoind.
+// Addresses that match are only listed with all. Fails when the balances
+// differ or the wallet holds outputs that are neither deposits nor change
+pub async fn reconcile_wallet(config: &Config, unspent: Option<&str>, balances: Option<&str>, all: bool)
+-> Result<(), Box<dyn Error>> {
+    let snapshot = load_snapshot(config, unspent, balances).await?;
+
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+    let snapshot_id = db_driver.record_wallet_snapshot(&snapshot).await?;
+
+    // Compare against one consistent view of the stored deposits
+    db_driver.begin_snapshot().await?;
+    let addresses = db_driver.reconciliation_by_address(snapshot_id).await?;
+    let discrepancies = db_driver.reconciliation_discrepancies(snapshot_id).await?;
+    let totals = db_driver.reconciliation_totals(snapshot_id).await?;
+    db_driver.end_snapshot().await?;
+
+    println!("Reconciliation against wallet snapshot #{0} from {1}", snapshot_id, snapshot.source);
+
+    let mut differing = 0;
+    for address in &addresses {
+        let outputs: Vec<&OutputDiscrepancy> = discrepancies.iter()
+            .filter(|discrepancy| discrepancy.address == address.address)
+            .collect();
+        let matches = address.engine_count == address.wallet_count
+            && utils::amounts_match(address.engine_amount, address.wallet_amount)
+            && outputs.is_empty();
+        if !matches {
+            differing += 1;
+        }
+        if matches && !all {
+            continue;
+        }
+
+        println!("Address {0} of {1}: engine count={2} sum={3} wallet count={4} sum={5} difference={6}",
+            address.address.as_deref().unwrap_or("n/a"),
+            address.customer.as_deref().unwrap_or("no reference"),
+            address.engine_count, format_amount(Some(address.engine_amount)),
+            address.wallet_count, format_amount(Some(address.wallet_amount)),
+            format_amount(Some(address.engine_amount - address.wallet_amount)));
+        for output in outputs {
+            println!("    {0} {1}:{2} engine={3} wallet={4}", output.kind, output.txid, output.vout,
+                format_amount(output.engine_amount), format_amount(output.wallet_amount));
+        }
+    }
+
+    println!("Engine holds: deposited={0} withdrawn={1} fees={2} balance={3}",
+        format_amount(Some(totals.deposited)), format_amount(Some(totals.withdrawn)),
+        format_amount(Some(totals.fees)), format_amount(Some(totals.engine_balance)));
+    println!("Wallet holds: unspent={0} balance={1}",
+        format_amount(Some(totals.unspent)), format_amount(Some(totals.wallet_balance)));
+
+    let count = |kind: &str| discrepancies.iter().filter(|discrepancy| discrepancy.kind == kind).count();
+    let unexplained = count("not_in_engine");
+    println!("Reconciled {0} addresses: {1} match, {2} differ, balance difference={3}",
+        addresses.len(), addresses.len() - differing, differing,
+        format_amount(Some(totals.engine_balance - totals.wallet_balance)));
+    println!("Outputs: not in wallet={0} change={1} mismatch={2} not in engine={3}",
+        count("not_in_wallet"), count("change"), count("mismatch"), unexplained);
+
+    let balanced = utils::amounts_match(totals.engine_balance, totals.wallet_balance);
+    if !balanced || unexplained > 0 {
+        return Err(Box::from(format!("Wallet snapshot #{0} does not reconcile: balance difference={1}, {2} outputs not in the engine",
+            snapshot_id, format_amount(Some(totals.engine_balance - totals.wallet_balance)), unexplained)));
+    }
+    Ok(())
+}
+
+// Read the snapshot from listunspent and getbalances results saved to
+// files, or fetch it from bitcoind when no file is given
+async fn load_snapshot(config: &Config, unspent: Option<&str>, balances: Option<&str>)
+-> Result<WalletSnapshot, Box<dyn Error>> {
+    match unspent {
+        Some(unspent) => Ok(WalletSnapshot {
+            source: String::from(unspent),
+            unspent: from_file::<Vec<UnspentOutput>>(unspent)?,
+            balances: balances.map(from_file::<WalletBalances>).transpose()?,
+        }),
+        None => match &config.bitcoind_rpc_url {
+            Some(url) => BitcoindRpcSource::new(url, config.bitcoind_rpc_auth.as_ref())?.wallet_snapshot().await,
+            None => Err(Box::from("No wallet snapshot, pass --unspent or set BITCOIND_RPC_URL")),
+        },
+    }
+}
@@ -0,0 +1,64 @@
+/*
+handlers/src/statistics.rs
+10/18/26
+
+This file defines the deposit statistics report.
+Statistics and histograms are computed in the database, overall,
+per known customer, per customer label rules attribute deposits to
+and for unreferenced deposits, all inside one snapshot so the
+sections agree with each other and the per-customer counts add up.
+*/
+use models::{ DepositScope, DepositStatistics, HistogramBin, KnownCustomersArray, ReportWindow, from_file };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use crate::utils::format_amount;
+
+// Width of the longest histogram bar
+const HISTOGRAM_WIDTH: i32 = 40;
+
+// This method prints count, sum, mean, median, percentiles, standard
+// deviation, range and a histogram of deposit sizes for every scope
+pub async fn deposit_statistics(config: &Config, window: &ReportWindow, bins: u32) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    // Load Known Customers
+    let known_customers = from_file::<KnownCustomersArray>(&config.known_customers)?;
+    known_customers.validate(config.network)?;
+
+    let mut scopes = vec![(String::from("all deposits"), DepositScope::All)];
+    for customer in &known_customers.known_customers {
+        scopes.push((customer.name.clone(), DepositScope::Address(customer.address.clone())));
+    }
+    for name in known_customers.label_customers() {
+        scopes.push((format!("deposits attributed by label to {}", name), DepositScope::Label(name.to_string())));
+    }
+    scopes.push((String::from("deposits without reference"), DepositScope::Unreferenced));
+
+    let bins = i32::try_from(bins.max(1))?;
+    db_driver.begin_snapshot().await?;
+    println!("Deposit statistics {}", window);
+    for (name, scope) in &scopes {
+        let statistics = db_driver.deposit_statistics(scope, window).await?;
+        let histogram = db_driver.deposit_histogram(scope, window, bins).await?;
+        print_statistics(name, &statistics, &histogram);
+    }
+    db_driver.end_snapshot().await
+}
+
+// Print the statistics of one scope followed by its histogram
+fn print_statistics(name: &str, statistics: &DepositStatistics, histogram: &[HistogramBin]) {
+    println!("Statistics for {0}: count={1} sum={2} mean={3} median={4} p90={5} p99={6} stddev={7} min={8} max={9}",
+        name, statistics.count, format_amount(statistics.sum), format_amount(statistics.mean), format_amount(statistics.median),
+        format_amount(statistics.p90), format_amount(statistics.p99), format_amount(statistics.stddev),
+        format_amount(statistics.min), format_amount(statistics.max));
+
+    let largest = histogram.iter().map(|bin| bin.count).max().unwrap_or(0).max(1);
+    for (index, bin) in histogram.iter().enumerate() {
+        // The last bin includes the largest deposit
+        let close = if index + 1 == histogram.len() { ']' } else { ')' };
+        let bar = "#".repeat((bin.count * HISTOGRAM_WIDTH / largest) as usize);
+        println!("    [{0}, {1}{2} {3:>6} {4}", format_amount(Some(bin.lower)), format_amount(Some(bin.upper)), close, bin.count, bar);
+    }
+}
@@ -0,0 +1,70 @@
+/*
+handlers/src/unreferenced.rs
+10/18/26
+
+This file defines the breakdown of unreferenced deposits by address.
+Support staff use it to match deposits to addresses of no known
+customer, for instance a stale address a customer kept using, by
+what was paid, when and under which label.
+*/
+use models::{ ReportWindow, UnreferencedAddress, UnreferencedDeposit, UnreferencedFilter, validate_address };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use crate::utils::format_amount;
+
+// This method lists unreferenced deposits per address, optionally
+// followed by every single deposit to the address
+pub async fn list_unreferenced(config: &Config, window: &ReportWindow, filter: &UnreferencedFilter, deposits: bool)
+-> Result<(), Box<dyn Error>> {
+    if let Some(address) = &filter.address {
+        validate_address(address, config.network)
+            .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+    }
+
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    db_driver.begin_snapshot().await?;
+    let addresses = db_driver.unreferenced_deposits_by_address(filter, window).await?;
+    let details = if deposits {
+        db_driver.unreferenced_deposits(filter, window).await?
+    } else {
+        Vec::new()
+    };
+    db_driver.end_snapshot().await?;
+
+    println!("Deposits without reference {} by {}{}", window, filter.sort,
+        if filter.descending { " descending" } else { "" });
+    for address in &addresses {
+        print_address(address);
+        // Deposits come ordered by address, then by time
+        for deposit in details.iter().filter(|deposit| deposit.address == address.address) {
+            print_deposit(deposit);
+        }
+    }
+
+    let count: i32 = addresses.iter().map(|address| address.count).sum();
+    let amount: f64 = addresses.iter().map(|address| address.amount).sum();
+    println!("Total: addresses={0} count={1} sum={2}", addresses.len(), count, format_amount(Some(amount)));
+    Ok(())
+}
+
+// One line per address
+fn print_address(address: &UnreferencedAddress) {
+    let labels = if address.labels.is_empty() {
+        String::from("none")
+    } else {
+        address.labels.join(", ")
+    };
+    println!("Address {0}: count={1} sum={2} first={3} last={4} labels={5}",
+        address.address, address.count, format_amount(Some(address.amount)),
+        address.first.to_rfc3339(), address.last.to_rfc3339(), labels);
+}
+
+// One indented line per deposit
+fn print_deposit(deposit: &UnreferencedDeposit) {
+    println!("    {0}:{1} amount={2} time={3} label={4}",
+        deposit.txid, deposit.vout, format_amount(Some(deposit.amount)), deposit.blocktime.to_rfc3339(),
+        deposit.label.as_deref().filter(|label| !label.is_empty()).unwrap_or("none"));
+}
@@ -2,24 +2,88 @@
 handlers/src/utils.rs
 7/2/24
 */
-use models::{ Transactions, KnownCustomersArray };
+use models::{ Block, InvoicesArray, Transaction, KnownCustomersArray, from_file };
+use config::Config;
 use database::DatabaseDriver;
+use std::collections::HashSet;
 use std::error::Error;
 
-// Delegate call to upload known clients
+// Amounts are stored with 8 decimal places, so sums that differ by
+// less than half a satoshi are the same amount
+const AMOUNT_TOLERANCE: f64 = 0.000000005;
+
+// Amounts rounded to the satoshi, n/a when there is nothing to show
+pub fn format_amount(value: Option<f64>) -> String {
+    match value {
+        Some(value) => {
+            // Adding zero turns the -0 of an empty f64 sum into 0
+            let rounded = format!("{:.8}", value + 0.0);
+            rounded.trim_end_matches('0').trim_end_matches('.').to_string()
+        },
+        None => String::from("n/a"),
+    }
+}
+
+// Replace the stored invoices with the configured ones. The migrations
+// recreate the table on every start, so each command reading invoices loads them
+pub async fn load_invoices<D: DatabaseDriver>(config: &Config, db_driver: &D) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &config.invoices {
+        let invoices = from_file::<InvoicesArray>(path)?;
+        invoices.validate(config.network)?;
+        db_driver.set_invoices(&invoices.invoices).await?;
+    }
+    Ok(())
+}
+
+// Delegate call to replace the known clients with the configured ones,
+// so every daemon cycle picks up the current file
 pub async fn insert_all_known_clients<D: DatabaseDriver>(known_customers: &KnownCustomersArray, db_driver: &D)
 -> Result<(), Box<dyn Error>> {
+    db_driver.begin_transaction().await?;
+    db_driver.clear_known_clients().await?;
     for customer in &known_customers.known_customers {
-        db_driver.insert_known_client(&customer).await?;
+        db_driver.insert_known_client(customer).await?;
+    }
+    db_driver.commit_transaction().await
+}
+
+// Delegate call to reverse transactions dropped by a reorg
+pub async fn remove_all_transactions<D: DatabaseDriver>(removed: &[Transaction], tip: Option<i32>, db_driver: &D)
+-> Result<(), Box<dyn Error>> {
+    for transaction in removed {
+        db_driver.remove_transaction(transaction, tip).await?;
     }
     Ok(())
 }
 
+// Delegate call to upload the blocks referenced by transactions,
+// recording that the batch saw them
+pub async fn insert_all_blocks<D: DatabaseDriver>(transactions: &[Transaction], batch_id: i32, db_driver: &D)
+-> Result<(), Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let blocks: Vec<Block> = transactions.iter()
+        .filter_map(|transaction| transaction.block())
+        .filter(|block| seen.insert(block.hash.clone()))
+        .collect();
+    db_driver.insert_blocks(&blocks, batch_id).await
+}
+
 // Delegate call to upload transactions
-pub async fn insert_all_transactions<D: DatabaseDriver>(transactions: &Transactions, db_driver: &D)
+pub async fn insert_all_transactions<D: DatabaseDriver>(transactions: &[Transaction], batch_id: i32, db_driver: &D)
 -> Result<(), Box<dyn Error>> {
-    for transaction in &transactions.transactions {
-        db_driver.insert_transaction(&transaction).await?;
+    db_driver.insert_transactions(transactions, batch_id).await
+}
+
+// Compare two summed amounts, ignoring floating point noise
+pub fn amounts_match(left: f64, right: f64) -> bool {
+    (left - right).abs() < AMOUNT_TOLERANCE
+}
+
+// The chain tip a listsinceblock snapshot was taken at,
+// as implied by a confirmed row that carries its block height
+pub fn implied_tip_height(transaction: &Transaction) -> Option<i32> {
+    match transaction.blockheight {
+        Some(height) if transaction.confirmations > 0 => Some(height + transaction.confirmations - 1),
+        _ => None,
     }
-    Ok(())
 }
@@ -0,0 +1,179 @@
+/*
+handlers/src/validate.rs
+10/18/26
+
+This file defines the checks every entry passes before it is stored.
+Entries that fail are quarantined with the reason instead of failing
+the whole batch.
+*/
+use chrono::{ Duration, Utc };
+use models::{ Network, Transaction, validate_address };
+use serde::Deserialize;
+use serde_json::Value;
+
+// Categories listsinceblock reports
+const CATEGORIES: &[&str] = &["send", "receive", "generate", "immature", "orphan"];
+
+// No amount can exceed the total supply
+const MAX_AMOUNT: f64 = 21_000_000.0;
+
+// Block timestamps may run up to two hours ahead of the network clock,
+// so clock checks allow for that much drift
+const TIME_TOLERANCE_SECS: i64 = 2 * 60 * 60;
+
+// Parse and check a raw entry, returning the reason it was rejected
+pub fn validate_transaction(raw: &Value, network: Network) -> Result<Transaction, String> {
+    let transaction = Transaction::deserialize(raw).map_err(|e| e.to_string())?;
+
+    if !is_hash(&transaction.txid) {
+        return Err(format!("txid is not a 64 character hex hash: {}", transaction.txid));
+    }
+    if let Some(blockhash) = &transaction.blockhash {
+        if !is_hash(blockhash) {
+            return Err(format!("blockhash is not a 64 character hex hash: {}", blockhash));
+        }
+    }
+    if !CATEGORIES.contains(&transaction.category.as_str()) {
+        return Err(format!("unknown category: {}", transaction.category));
+    }
+    if !transaction.amount.is_finite() || transaction.amount.abs() > MAX_AMOUNT {
+        return Err(format!("amount out of range: {}", transaction.amount));
+    }
+    if transaction.vout < 0 {
+        return Err(format!("negative vout: {}", transaction.vout));
+    }
+    validate_times(&transaction)?;
+    if let Some(address) = &transaction.address {
+        validate_address(address, network).map_err(|e| format!("address {}: {}", address, e))?;
+    }
+
+    Ok(transaction)
+}
+
+// No timestamp may lie in the future, and a transaction
+// cannot have been mined before the wallet saw it
+fn validate_times(transaction: &Transaction) -> Result<(), String> {
+    let tolerance = Duration::seconds(TIME_TOLERANCE_SECS);
+    let latest = Utc::now() + tolerance;
+
+    let times = [("time", Some(transaction.time)), ("timereceived", Some(transaction.timereceived)),
+        ("blocktime", transaction.blocktime)];
+    for (field, time) in times {
+        if let Some(time) = time.filter(|time| *time > latest) {
+            return Err(format!("{} is in the future: {}", field, time.to_rfc3339()));
+        }
+    }
+
+    if let Some(blocktime) = transaction.blocktime {
+        if blocktime < transaction.time - tolerance {
+            return Err(format!("blocktime {} is before time {}",
+                blocktime.to_rfc3339(), transaction.time.to_rfc3339()));
+        }
+    }
+    Ok(())
+}
+
+// Transaction and block hashes are 32 bytes of hex
+fn is_hash(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // A confirmed receive from the sample data
+    fn entry() -> Value {
+        json!({
+            "involvesWatchonly": true, "account": "", "address": "myAre6hq8uSDAzhmNit1fjkTeajebBzrKZ",
+            "category": "receive", "amount": 36.9759613, "label": "", "confirmations": 42,
+            "blockhash": "ceea46e555518b0c7e858476ca2259b1ca91832ea6b35a8e135ac30d9ab7360b",
+            "blockindex": 59, "blocktime": 1627633348873_i64,
+            "txid": "dd23e0dfcc3df0e086ffc0f3662f3727fff6e10021bf0d396a7eb7c1f87dc284", "vout": 5,
+            "walletconflicts": [], "time": 1627633337048_i64, "timereceived": 1627633337048_i64,
+            "bip125-replaceable": "no"
+        })
+    }
+
+    // The reason an entry with one field replaced is rejected
+    fn rejected(field: &str, value: Value) -> String {
+        let mut raw = entry();
+        raw[field] = value;
+        validate_transaction(&raw, Network::Testnet).unwrap_err()
+    }
+
+    #[test]
+    fn accepts_a_valid_entry() {
+        let transaction = validate_transaction(&entry(), Network::Testnet).unwrap();
+        assert_eq!(transaction.vout, 5);
+    }
+
+    #[test]
+    fn rejects_entries_that_do_not_parse() {
+        let mut raw = entry();
+        raw.as_object_mut().unwrap().remove("txid");
+        assert!(validate_transaction(&raw, Network::Testnet).unwrap_err().contains("txid"));
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        assert!(rejected("txid", json!("dd23")).starts_with("txid"));
+        assert!(rejected("txid", json!("z".repeat(64))).starts_with("txid"));
+        assert!(rejected("blockhash", json!("ceea")).starts_with("blockhash"));
+    }
+
+    #[test]
+    fn rejects_unknown_categories() {
+        assert!(rejected("category", json!("deposit")).starts_with("unknown category"));
+        for category in CATEGORIES {
+            let mut raw = entry();
+            raw["category"] = json!(category);
+            assert!(validate_transaction(&raw, Network::Testnet).is_ok(), "{}", category);
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_beyond_the_supply() {
+        assert!(rejected("amount", json!(21_000_000.1)).starts_with("amount out of range"));
+        assert!(rejected("amount", json!(-21_000_000.1)).starts_with("amount out of range"));
+
+        let mut raw = entry();
+        raw["amount"] = json!(-21_000_000.0);
+        assert!(validate_transaction(&raw, Network::Testnet).is_ok());
+    }
+
+    #[test]
+    fn rejects_negative_vouts() {
+        assert!(rejected("vout", json!(-1)).starts_with("negative vout"));
+    }
+
+    #[test]
+    fn rejects_times_in_the_future() {
+        let tomorrow = (Utc::now() + Duration::days(1)).timestamp();
+        assert!(rejected("time", json!(tomorrow)).starts_with("time is in the future"));
+        assert!(rejected("timereceived", json!(tomorrow)).starts_with("timereceived is in the future"));
+        assert!(rejected("blocktime", json!(tomorrow)).starts_with("blocktime is in the future"));
+
+        // Drift within the tolerance is accepted
+        let mut raw = entry();
+        raw["blocktime"] = json!((Utc::now() + Duration::minutes(30)).timestamp());
+        assert!(validate_transaction(&raw, Network::Testnet).is_ok());
+    }
+
+    #[test]
+    fn rejects_blocks_mined_before_the_transaction_was_seen() {
+        // Three hours before time, beyond the two hours of drift allowed
+        assert!(rejected("blocktime", json!(1627633337048_i64 - 3 * 60 * 60 * 1000)).starts_with("blocktime"));
+
+        let mut raw = entry();
+        raw["blocktime"] = json!(1627633337048_i64 - 60 * 60 * 1000);
+        assert!(validate_transaction(&raw, Network::Testnet).is_ok());
+    }
+
+    #[test]
+    fn rejects_addresses_of_another_network() {
+        assert!(validate_transaction(&entry(), Network::Mainnet).unwrap_err().starts_with("address"));
+        assert!(rejected("address", json!("myAre6hq8uSDAzhmNit1fjkTeajebBzrKz")).starts_with("address"));
+    }
+}
@@ -0,0 +1,78 @@
+/*
+handlers/src/withdrawals.rs
+10/18/26
+
+This file defines the withdrawal and net balance reports.
+Withdrawals are attributed to customers by the payout address they
+paid or the label of the send, never by where the customer deposits
+from, so the net balance of a customer is what they deposited less
+what was paid out to them and its fees.
+*/
+use models::{ KnownCustomersArray, NetBalance, from_file };
+use config::Config;
+use database::{ DatabaseDriver, PostgresDriver };
+use std::error::Error;
+use crate::utils::format_amount;
+
+// This method lists every withdrawal that still stands,
+// grouped per customer, unattributed ones last
+pub async fn list_withdrawals(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let withdrawals = db_driver.withdrawals().await?;
+    if withdrawals.is_empty() {
+        println!("No withdrawals");
+    }
+
+    for withdrawal in &withdrawals {
+        let customer = match &withdrawal.customer {
+            Some(customer) => format!("for {}", customer),
+            None => String::from("unattributed"),
+        };
+        println!("Withdrawal {0}:{1} to {2} {3}: amount={4} fee={5} confirmations={6} sent={7}",
+            withdrawal.txid, withdrawal.vout,
+            withdrawal.address.as_deref().unwrap_or("n/a"),
+            customer,
+            format_amount(Some(withdrawal.amount)), format_amount(Some(withdrawal.fee)),
+            withdrawal.confirmations, withdrawal.sent_at.to_rfc3339());
+    }
+    Ok(())
+}
+
+// This method prints the net balance of every known customer, every
+// customer of a label or payout label rule and of everything attributed
+// to no customer. Customers without deposits or withdrawals get a zero line
+pub async fn net_balances(config: &Config) -> Result<(), Box<dyn Error>> {
+    let known_customers = from_file::<KnownCustomersArray>(&config.known_customers)?;
+    known_customers.validate(config.network)?;
+
+    let mut db_driver = PostgresDriver::new();
+    db_driver.connect(&config.db_connection_string).await?;
+
+    let balances = db_driver.net_balances().await?;
+    let balance_of = |customer: Option<&str>| balances.iter().find(|balance| balance.customer.as_deref() == customer);
+
+    let mut names: Vec<&str> = known_customers.known_customers.iter().map(|customer| customer.name.as_str()).collect();
+    for name in known_customers.label_customers().into_iter().chain(known_customers.payout_label_customers()) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    for name in names {
+        print_balance(&format!("Net balance of {}", name), balance_of(Some(name)));
+    }
+    print_balance("Net balance without reference or attribution", balance_of(None));
+    Ok(())
+}
+
+// One line of the net balance report
+fn print_balance(prefix: &str, balance: Option<&NetBalance>) {
+    match balance {
+        Some(balance) => println!("{0}: deposits={1} deposited={2} withdrawals={3} withdrawn={4} fees={5} net={6}",
+            prefix, balance.deposits, format_amount(Some(balance.deposited)), balance.withdrawals,
+            format_amount(Some(balance.withdrawn)), format_amount(Some(balance.fees)), format_amount(Some(balance.net))),
+        None => println!("{}: deposits=0 deposited=0 withdrawals=0 withdrawn=0 fees=0 net=0", prefix),
+    }
+}
@@ -0,0 +1,142 @@
+/*
+handlers/tests/common/mod.rs
+10/18/26
+
+This file defines the helpers shared by the database tests.
+They need a scratch Postgres database, given in
+TEST_DB_CONNECTION_STRING, whose public schema is wiped and
+migrated before every test. Without it the tests are skipped.
+*/
+#![allow(dead_code)]
+
+use config::{ Config, InputFile };
+use logger::{ LogLevel, Logger };
+use models::Network;
+use serde_json::{ json, Value };
+use std::sync::atomic::AtomicBool;
+use tokio::sync::{ Mutex, MutexGuard };
+use tokio_postgres::{ Client, NoTls };
+
+// Testnet addresses of known customers in known-customers.json
+pub const WESLEY: &str = "mvd6qFeVkqH6MNAS2Y2cLifbdaX5XUkbZJ";
+pub const MCCOY: &str = "mmFFG4jqAtw9MoCC88hw5FNfreQWuEHADp";
+
+// Tests share one database, so they run one at a time
+static DATABASE: Mutex<()> = Mutex::const_new(());
+
+// A migrated, empty database and the lock held while a test uses it
+pub struct TestDatabase {
+    pub client: Client,
+    pub connection_string: String,
+    _guard: MutexGuard<'static, ()>,
+}
+
+// Wipe and migrate the test database, or None when none is configured
+pub async fn database() -> Option<TestDatabase> {
+    let connection_string = match std::env::var("TEST_DB_CONNECTION_STRING") {
+        Ok(connection_string) => connection_string,
+        Err(_) => {
+            eprintln!("TEST_DB_CONNECTION_STRING is not set, skipping");
+            return None;
+        }
+    };
+    let guard = DATABASE.lock().await;
+
+    let (client, connection) = tokio_postgres::connect(&connection_string, NoTls).await
+        .expect("test database is reachable");
+    tokio::spawn(connection);
+    client.batch_execute("DROP SCHEMA public CASCADE; CREATE SCHEMA public;").await
+        .expect("test database is wiped");
+    let database = TestDatabase { client, connection_string, _guard: guard };
+    migrate(&database).await;
+    Some(database)
+}
+
+// Run the startup migrations, as every start of the engine does
+pub async fn migrate(database: &TestDatabase) {
+    database.client.batch_execute(include_str!("../../../migrations/0.sql")).await
+        .expect("migrations run");
+}
+
+// Path of a file at the repository root
+pub fn repo_file(name: &str) -> String {
+    format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// Configuration loading the given files into the test database
+pub fn config(database: &TestDatabase, input_data: Vec<InputFile>) -> Config {
+    Config {
+        db_connection_string: database.connection_string.clone(),
+        log_file: scratch_file("log.txt"),
+        known_customers: repo_file("known-customers.json"),
+        invoices: None,
+        input_data,
+        chain_tip_height: None,
+        wallet_name: String::from("default"),
+        bitcoind_rpc_url: None,
+        bitcoind_rpc_auth: None,
+        ingest_batch_size: 1000,
+        network: Network::Testnet,
+    }
+}
+
+// An input file without a snapshot tip
+pub fn input(path: &str) -> InputFile {
+    InputFile { path: path.to_string(), snapshot_tip: None }
+}
+
+// Drain every configured source into the test database
+pub async fn load(config: &Config, force: bool) {
+    let mut sources = sources::from_config(config).expect("sources are configured");
+    let logger = Logger::new(LogLevel::Error, &config.log_file);
+    handlers::load_data(config, &mut sources, &AtomicBool::new(false), force, &logger).await
+        .expect("data loads");
+}
+
+// A path under a scratch directory unique to this test process
+pub fn scratch_file(name: &str) -> String {
+    let mut path = std::env::temp_dir();
+    path.push(format!("kobayashi-maru-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&path).expect("scratch directory is created");
+    path.push(name);
+    path.to_string_lossy().into_owned()
+}
+
+// Write a listsinceblock response to a scratch file and return its path
+pub fn write_snapshot(name: &str, transactions: Vec<Value>, removed: Vec<Value>, lastblock: &str) -> String {
+    let path = scratch_file(name);
+    let response = json!({ "transactions": transactions, "removed": removed, "lastblock": lastblock });
+    std::fs::write(&path, serde_json::to_vec(&response).expect("snapshot serializes"))
+        .expect("snapshot is written");
+    path
+}
+
+// A receive entry as listsinceblock reports it
+pub fn receive(txid: &str, address: &str, amount: f64, confirmations: i32, blockhash: Option<&str>) -> Value {
+    let mut entry = json!({
+        "involvesWatchonly": true,
+        "account": "",
+        "address": address,
+        "category": "receive",
+        "amount": amount,
+        "label": "",
+        "confirmations": confirmations,
+        "txid": txid,
+        "vout": 0,
+        "walletconflicts": [],
+        "time": 1627607521,
+        "timereceived": 1627607521,
+        "bip125-replaceable": "no"
+    });
+    if let Some(blockhash) = blockhash {
+        entry["blockhash"] = json!(blockhash);
+        entry["blockindex"] = json!(1);
+        entry["blocktime"] = json!(1627607548);
+    }
+    entry
+}
+
+// A 64 character hex hash made of one repeated digit
+pub fn hash(digit: char) -> String {
+    std::iter::repeat_n(digit, 64).collect()
+}
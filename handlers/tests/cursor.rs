@@ -0,0 +1,62 @@
+/*
+handlers/tests/cursor.rs
+10/18/26
+
+This file tests that every deposit source resumes from its own cursor.
+*/
+mod common;
+
+use serde_json::Value;
+use sources::mock::MockBitcoind;
+use tokio::net::TcpListener;
+
+// Parse a listsinceblock dump from the repository
+fn dump(name: &str) -> Value {
+    serde_json::from_slice(&std::fs::read(common::repo_file(name)).expect("dump is readable"))
+        .expect("dump parses")
+}
+
+// Stored cursors, by key
+async fn cursors(database: &common::TestDatabase) -> Vec<(String, String)> {
+    database.client.query("SELECT wallet, lastblock FROM sync_cursors ORDER BY wallet", &[]).await
+        .expect("cursors are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn file_and_rpc_sources_keep_separate_cursors() {
+    let Some(database) = common::database().await else { return };
+
+    let file = dump("transactions-1.json");
+    let first = dump("transactions-2.json");
+    let second = dump("fixtures/bitcoind-24.0-listsinceblock.json");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("port is free");
+    let url = format!("http://{}", listener.local_addr().expect("listener has an address"));
+    let mock = MockBitcoind::new(vec![first.clone(), second.clone()], None);
+    tokio::spawn(async move {
+        let _ = mock.serve(listener).await;
+    });
+
+    let mut config = common::config(&database, vec![common::input(&common::repo_file("transactions-1.json"))]);
+    config.bitcoind_rpc_url = Some(url.clone());
+
+    // The file's lastblock is not where the node source resumes from,
+    // which the mock would reject as an unknown block
+    common::load(&config, false).await;
+    let files = (String::from("default:files"), file["lastblock"].as_str().unwrap().to_string());
+    let node = format!("default:bitcoind {}", url);
+    assert_eq!(cursors(&database).await, vec![
+        (node.clone(), first["lastblock"].as_str().unwrap().to_string()),
+        files.clone(),
+    ]);
+
+    // The next poll resumes from the first response and gets the second
+    common::load(&config, false).await;
+    assert_eq!(cursors(&database).await, vec![
+        (node, second["lastblock"].as_str().unwrap().to_string()),
+        files,
+    ]);
+}
@@ -0,0 +1,170 @@
+/*
+handlers/tests/ingest.rs
+10/18/26
+
+This file tests that a batch is stored the same whatever its
+chunk size and row order, and which batches a restart skips.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::{ Value, json };
+use sha2::{ Digest, Sha256 };
+
+// Height of every stored block, by hash
+async fn heights(database: &common::TestDatabase) -> Vec<(String, Option<i32>)> {
+    database.client.query("SELECT hash, height FROM blocks ORDER BY hash", &[]).await
+        .expect("blocks are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+// Only the last row carries its block height, which implies tip 500
+fn rows() -> Vec<Value> {
+    let mut pinned = receive(&hash('7'), MCCOY, 1.0, 1, Some(&hash('c')));
+    pinned["blockheight"] = json!(500);
+    vec![
+        receive(&hash('5'), WESLEY, 1.0, 10, Some(&hash('a'))),
+        receive(&hash('6'), WESLEY, 1.0, 3, Some(&hash('b'))),
+        pinned,
+    ]
+}
+
+#[tokio::test]
+async fn heights_do_not_depend_on_chunk_size_or_order() {
+    let expected = vec![
+        (hash('a'), Some(491)),
+        (hash('b'), Some(498)),
+        (hash('c'), Some(500)),
+    ];
+
+    for (name, batch_size, reversed) in [("tip-1.json", 1, false), ("tip-2.json", 1000, false), ("tip-3.json", 1, true)] {
+        let Some(database) = common::database().await else { return };
+        let mut rows = rows();
+        if reversed {
+            rows.reverse();
+        }
+        let path = write_snapshot(name, rows, vec![], &hash('c'));
+        let mut config = common::config(&database, vec![common::input(&path)]);
+        config.ingest_batch_size = batch_size;
+        common::load(&config, false).await;
+        assert_eq!(heights(&database).await, expected, "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn deposit_mined_again_after_a_reorg_stays_standing() {
+    let Some(database) = common::database().await else { return };
+
+    let first = write_snapshot("mined.json",
+        vec![receive(&hash('8'), WESLEY, 1.0, 1, Some(&hash('d')))], vec![], &hash('d'));
+    // The deposit was reorged out of block d and mined again in block e
+    let second = write_snapshot("remined.json",
+        vec![receive(&hash('8'), WESLEY, 1.0, 1, Some(&hash('e')))],
+        vec![receive(&hash('8'), WESLEY, 1.0, -1, Some(&hash('d')))], &hash('e'));
+
+    let config = common::config(&database, vec![common::input(&first), common::input(&second)]);
+    common::load(&config, false).await;
+
+    let row = database.client.query_one("SELECT blockhash, removed FROM transactions WHERE txid = $1", &[&hash('8')])
+        .await.expect("the deposit is stored");
+    assert_eq!((row.get::<_, String>(0), row.get::<_, bool>(1)), (hash('e'), false));
+}
+
+#[tokio::test]
+async fn deposit_seen_again_in_its_block_gains_confirmations() {
+    let Some(database) = common::database().await else { return };
+
+    // Snapshots without block heights, taken one and seven blocks after it was mined
+    let pending = write_snapshot("pending.json",
+        vec![receive(&hash('9'), WESLEY, 1.0, 1, Some(&hash('d')))], vec![], &hash('d'));
+    let matured = write_snapshot("matured.json",
+        vec![receive(&hash('9'), WESLEY, 1.0, 7, Some(&hash('d')))], vec![], &hash('f'));
+
+    let config = common::config(&database, vec![common::input(&pending), common::input(&matured)]);
+    common::load(&config, false).await;
+
+    let confirmations: i32 = database.client
+        .query_one("SELECT confirmations FROM transactions WHERE txid = $1", &[&hash('9')]).await
+        .expect("the deposit is stored").get(0);
+    assert_eq!(confirmations, 7);
+    let confirmed: f64 = database.client
+        .query_one("SELECT get_total_confirmed_amount($1)::FLOAT8", &[&WESLEY]).await
+        .expect("the total is queried").get(0);
+    assert_eq!(confirmed, 1.0);
+    let status: String = database.client
+        .query_one("SELECT status FROM deposit_states WHERE txid = $1", &[&hash('9')]).await
+        .expect("the deposit has a state").get(0);
+    assert_eq!(status, "credited");
+
+    // An older snapshot loaded afterwards does not take confirmations away
+    let stale = write_snapshot("stale.json",
+        vec![receive(&hash('9'), WESLEY, 1.0, 2, Some(&hash('d')))], vec![], &hash('e'));
+    common::load(&common::config(&database, vec![common::input(&stale)]), false).await;
+    let confirmations: i32 = database.client
+        .query_one("SELECT confirmations FROM transactions WHERE txid = $1", &[&hash('9')]).await
+        .expect("the deposit is stored").get(0);
+    assert_eq!(confirmations, 7);
+}
+
+#[tokio::test]
+async fn deposit_seen_again_keeps_the_batch_that_first_stored_it() {
+    let Some(database) = common::database().await else { return };
+
+    let first = write_snapshot("first-seen.json",
+        vec![receive(&hash('7'), WESLEY, 1.0, 0, None)], vec![], &hash('c'));
+    // Mined since, and another output of the transaction listed in another block
+    let mut other_output = receive(&hash('7'), MCCOY, 2.0, 1, Some(&hash('e')));
+    other_output["vout"] = json!(1);
+    let second = write_snapshot("seen-again.json",
+        vec![receive(&hash('7'), WESLEY, 1.0, 1, Some(&hash('d'))), other_output], vec![], &hash('e'));
+
+    let config = common::config(&database, vec![common::input(&first), common::input(&second)]);
+    common::load(&config, false).await;
+
+    let row = database.client
+        .query_one("SELECT blockhash, batch_id, last_seen_batch_id FROM transactions WHERE txid = $1 AND vout = 0",
+            &[&hash('7')]).await
+        .expect("the deposit is stored");
+    assert_eq!((row.get::<_, String>(0), row.get::<_, i32>(1), row.get::<_, Option<i32>>(2)), (hash('d'), 1, Some(2)));
+}
+
+#[tokio::test]
+async fn restart_keeps_provenance_and_skips_loaded_files() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("restart.json", rows(), vec![], &hash('c'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    // Starting again runs the migrations before loading the same file
+    common::migrate(&database).await;
+    common::load(&config, false).await;
+
+    let batches = database.client.query("SELECT source, sha256, row_count FROM ingest_batches", &[]).await
+        .expect("batches are queried");
+    assert_eq!(batches.len(), 1);
+    let sha256: String = batches[0].get(1);
+    let expected = format!("{:x}", Sha256::digest(std::fs::read(&path).expect("snapshot is readable")));
+    assert_eq!((batches[0].get::<_, String>(0), sha256, batches[0].get::<_, i32>(2)), (path, expected, 3));
+
+    let stored: i64 = database.client.query_one("SELECT COUNT(*) FROM transactions", &[]).await
+        .expect("transactions are counted").get(0);
+    assert_eq!(stored, 3);
+}
+
+#[tokio::test]
+async fn force_loads_an_ingested_file_again() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("forced.json", rows(), vec![], &hash('c'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+    common::migrate(&database).await;
+    common::load(&config, true).await;
+
+    let batches: i64 = database.client.query_one("SELECT COUNT(*) FROM ingest_batches WHERE status = 'completed'", &[]).await
+        .expect("batches are counted").get(0);
+    assert_eq!(batches, 2);
+}
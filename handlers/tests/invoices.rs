@@ -0,0 +1,131 @@
+/*
+handlers/tests/invoices.rs
+10/18/26
+
+This file tests how deposits settle invoices and that
+the invoice report loads the configured invoices.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::json;
+
+const ARCHER: &str = "mzzg8fvHXydKs8j9D2a8t7KpSXpGgAnk4n";
+const DAX: &str = "2N1SP7r92ZZJvYKG2oNtzPwYnzw62up7mTo";
+const SCOTT: &str = "mutrAf4usv3HKNdpLwVD4ow2oLArL6Rez8";
+const KIRK: &str = "miTHhiX3iFhVnAEecLjybxvV5g8mKYTtnM";
+const SPOCK: &str = "mvcyJMiAcSXKAEsQxbW9TYZ369rsMG6rVV";
+
+// Every deposit is seen at 1627607521, between these times
+const CREATED: i64 = 1627600000;
+const EXPIRED: i64 = 1627605000;
+const EXPIRES: i64 = 1627610000;
+
+// Write one invoice per address and load them with the deposits paying them
+async fn load(database: &common::TestDatabase) -> config::Config {
+    let invoices = common::scratch_file("invoices.json");
+    std::fs::write(&invoices, serde_json::to_vec(&json!({ "invoices": [
+        { "id": "EXACT", "address": WESLEY, "amount": 1.0, "created": CREATED, "expires": EXPIRES },
+        { "id": "UNDERPAID", "address": MCCOY, "amount": 2.0, "created": CREATED, "expires": EXPIRES },
+        { "id": "OVERPAID", "address": ARCHER, "amount": 1.0, "created": CREATED, "expires": EXPIRES },
+        { "id": "DUPLICATE", "address": KIRK, "amount": 1.0, "created": CREATED, "expires": EXPIRES },
+        { "id": "LATE", "address": SCOTT, "amount": 1.0, "created": CREATED, "expires": EXPIRED },
+        { "id": "EXPIRED", "address": DAX, "amount": 1.0, "created": CREATED, "expires": EXPIRED },
+        { "id": "OPEN", "address": SPOCK, "amount": 1.0, "created": CREATED, "expires": 4102444800_i64 }
+    ] })).expect("invoices serialize")).expect("invoices are written");
+
+    let block = Some(hash('a'));
+    let path = write_snapshot("payments.json", vec![
+        receive(&hash('1'), WESLEY, 1.0, 6, block.as_deref()),
+        receive(&hash('2'), MCCOY, 0.5, 6, block.as_deref()),
+        receive(&hash('3'), MCCOY, 1.0, 6, block.as_deref()),
+        receive(&hash('4'), ARCHER, 0.6, 6, block.as_deref()),
+        receive(&hash('5'), ARCHER, 0.6, 6, block.as_deref()),
+        receive(&hash('6'), KIRK, 1.0, 6, block.as_deref()),
+        receive(&hash('7'), KIRK, 1.0, 6, block.as_deref()),
+        receive(&hash('8'), SCOTT, 1.0, 6, block.as_deref()),
+    ], vec![], &hash('a'));
+    let mut config = common::config(database, vec![common::input(&path)]);
+    config.invoices = Some(invoices);
+    common::load(&config, false).await;
+    config
+}
+
+// Status, paid amount and payment count of every invoice
+async fn statuses(database: &common::TestDatabase) -> Vec<(String, String, f64, i32)> {
+    database.client
+        .query("SELECT id, status, paid_amount::FLOAT8, payment_count FROM get_invoice_statuses() ORDER BY id", &[]).await
+        .expect("statuses are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+        .collect()
+}
+
+// Number of invoices stored
+async fn stored(database: &common::TestDatabase) -> i64 {
+    database.client.query_one("SELECT COUNT(*) FROM invoices", &[]).await
+        .expect("invoices are counted").get(0)
+}
+
+#[tokio::test]
+async fn payments_before_expiry_settle_the_invoice() {
+    let Some(database) = common::database().await else { return };
+    load(&database).await;
+
+    let expected = [
+        ("DUPLICATE", "exact", 1.0, 2),
+        ("EXACT", "exact", 1.0, 1),
+        ("EXPIRED", "expired", 0.0, 0),
+        ("LATE", "late", 0.0, 1),
+        ("OPEN", "open", 0.0, 0),
+        ("OVERPAID", "overpaid", 1.2, 2),
+        ("UNDERPAID", "underpaid", 1.5, 2),
+    ];
+    let statuses = statuses(&database).await;
+    assert_eq!(statuses.len(), expected.len());
+    for ((id, status, paid, count), (expected_id, expected_status, expected_paid, expected_count)) in statuses.iter().zip(expected) {
+        assert_eq!((id.as_str(), status.as_str(), *count), (expected_id, expected_status, expected_count));
+        assert!((paid - expected_paid).abs() < 1e-8, "{} paid {}", id, paid);
+    }
+}
+
+#[tokio::test]
+async fn only_payments_after_full_payment_are_duplicates() {
+    let Some(database) = common::database().await else { return };
+    load(&database).await;
+
+    let kinds: Vec<(String, String, String)> = database.client
+        .query("SELECT invoice_id, txid, kind FROM get_invoice_payments() ORDER BY invoice_id, txid", &[]).await
+        .expect("payments are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+    let expected = [
+        ("DUPLICATE", hash('6'), "paid"),
+        ("DUPLICATE", hash('7'), "duplicate"),
+        ("EXACT", hash('1'), "paid"),
+        ("LATE", hash('8'), "late"),
+        ("OVERPAID", hash('4'), "paid"),
+        ("OVERPAID", hash('5'), "paid"),
+        ("UNDERPAID", hash('2'), "paid"),
+        ("UNDERPAID", hash('3'), "paid"),
+    ];
+    let expected: Vec<(String, String, String)> = expected.into_iter()
+        .map(|(id, txid, kind)| (String::from(id), txid, String::from(kind)))
+        .collect();
+    assert_eq!(kinds, expected);
+}
+
+#[tokio::test]
+async fn report_loads_the_invoices_file() {
+    let Some(database) = common::database().await else { return };
+    let config = load(&database).await;
+
+    // A restart recreates the invoices table
+    common::migrate(&database).await;
+    assert_eq!(stored(&database).await, 0);
+
+    handlers::list_invoices(&config, None, true).await.expect("invoices are listed");
+    assert_eq!(stored(&database).await, 7);
+    assert_eq!(statuses(&database).await.len(), 7);
+}
@@ -0,0 +1,37 @@
+/*
+handlers/tests/ledger.rs
+10/18/26
+
+This file tests that deposits are credited once in the ledger.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+
+// Journal entries by txid and kind, in posting order
+async fn journal(database: &common::TestDatabase) -> Vec<(String, String)> {
+    database.client.query("SELECT txid, kind FROM journal_entries ORDER BY id", &[]).await
+        .expect("the journal is queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn restart_does_not_post_deposits_again() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("ledger.json", vec![
+        receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a'))),
+        receive(&hash('2'), MCCOY, 2.0, 6, Some(&hash('a'))),
+    ], vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+    let posted = journal(&database).await;
+    assert_eq!(posted, vec![(hash('1'), String::from("deposit")), (hash('2'), String::from("deposit"))]);
+
+    // Starting again keeps the journal, and loading the same file again posts nothing
+    common::migrate(&database).await;
+    common::load(&config, true).await;
+    assert_eq!(journal(&database).await, posted);
+}
@@ -0,0 +1,44 @@
+/*
+handlers/tests/lifecycle.rs
+10/18/26
+
+This file tests the transitions recorded as deposits change status.
+*/
+mod common;
+
+use common::{ WESLEY, hash, receive, write_snapshot };
+use config::InputFile;
+
+// Status changes of a deposit, oldest first
+async fn transitions(database: &common::TestDatabase, txid: &str) -> Vec<(Option<String>, String)> {
+    database.client.query("SELECT from_status, to_status FROM get_deposit_transitions($1)", &[&txid]).await
+        .expect("transitions are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn credited_deposit_reorged_back_to_pending_is_reversed_first() {
+    let Some(database) = common::database().await else { return };
+
+    // Credited at height 95, then reorged out and mined again at the tip
+    let credited = write_snapshot("credited.json",
+        vec![receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a')))], vec![], &hash('a'));
+    let remined = write_snapshot("remined-pending.json",
+        vec![receive(&hash('1'), WESLEY, 1.0, 1, Some(&hash('b')))],
+        vec![receive(&hash('1'), WESLEY, 1.0, -1, Some(&hash('a')))], &hash('b'));
+    let config = common::config(&database, vec![
+        InputFile { path: credited, snapshot_tip: Some(100) },
+        InputFile { path: remined, snapshot_tip: Some(101) },
+    ]);
+    common::load(&config, false).await;
+
+    let status = |from: Option<&str>, to: &str| (from.map(String::from), String::from(to));
+    assert_eq!(transitions(&database, &hash('1')).await, vec![
+        status(None, "confirmed"),
+        status(Some("confirmed"), "credited"),
+        status(Some("credited"), "reversed"),
+        status(Some("reversed"), "pending"),
+    ]);
+}
@@ -0,0 +1,35 @@
+/*
+handlers/tests/lookup.rs
+10/18/26
+
+This file tests how the transaction lookups judge a stored transaction.
+*/
+mod common;
+
+use common::{ WESLEY, hash, receive, write_snapshot };
+use serde_json::json;
+
+// Whether the lookup reports the transaction as conflicted
+async fn conflicted(database: &common::TestDatabase, txid: &str) -> bool {
+    database.client
+        .query_one("SELECT is_conflicted FROM find_transactions($1, NULL, 1, 0)", &[&txid]).await
+        .expect("the transaction is found")
+        .get(0)
+}
+
+#[tokio::test]
+async fn only_the_loser_of_a_double_spend_is_conflicted() {
+    let Some(database) = common::database().await else { return };
+
+    // Both spend the same coins and list each other, the winner was mined
+    let mut winner = receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a')));
+    winner["walletconflicts"] = json!([hash('2')]);
+    let mut loser = receive(&hash('2'), WESLEY, 1.0, -6, None);
+    loser["walletconflicts"] = json!([hash('1')]);
+    let path = write_snapshot("double-spend.json", vec![winner, loser], vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    assert!(!conflicted(&database, &hash('1')).await);
+    assert!(conflicted(&database, &hash('2')).await);
+}
@@ -0,0 +1,75 @@
+/*
+handlers/tests/main_chain.rs
+10/18/26
+
+This file tests how the main chain is settled from snapshots.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use config::InputFile;
+
+// Main chain flag of every stored block, by hash
+async fn main_chain(database: &common::TestDatabase) -> Vec<(String, Option<i32>, bool)> {
+    database.client.query("SELECT hash, height, is_main_chain FROM blocks ORDER BY hash", &[]).await
+        .expect("blocks are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect()
+}
+
+#[tokio::test]
+async fn older_snapshot_does_not_repromote_a_replaced_block() {
+    let Some(database) = common::database().await else { return };
+
+    // Taken at height 100, the deposit is in block a at height 100
+    let older = write_snapshot("older.json",
+        vec![receive(&hash('1'), WESLEY, 1.0, 1, Some(&hash('a')))], vec![], &hash('a'));
+    // Taken at height 101, block a was replaced by b and c built on it
+    let newer = write_snapshot("newer.json",
+        vec![
+            receive(&hash('1'), WESLEY, 1.0, 2, Some(&hash('b'))),
+            receive(&hash('2'), MCCOY, 2.0, 1, Some(&hash('c'))),
+        ],
+        vec![receive(&hash('1'), WESLEY, 1.0, -1, Some(&hash('a')))], &hash('c'));
+
+    let config = common::config(&database, vec![
+        InputFile { path: older.clone(), snapshot_tip: Some(100) },
+        InputFile { path: newer, snapshot_tip: Some(101) },
+    ]);
+    common::load(&config, false).await;
+
+    let expected = vec![
+        (hash('a'), Some(100), false),
+        (hash('b'), Some(100), true),
+        (hash('c'), Some(101), true),
+    ];
+    assert_eq!(main_chain(&database).await, expected);
+
+    // Loading the older snapshot again leaves the newer chain in place
+    let config = common::config(&database, vec![InputFile { path: older, snapshot_tip: Some(100) }]);
+    common::load(&config, true).await;
+    assert_eq!(main_chain(&database).await, expected);
+}
+
+#[tokio::test]
+async fn blocks_without_height_take_part_in_reorgs() {
+    let Some(database) = common::database().await else { return };
+
+    // Heights come from the snapshot tip, the rows carry none
+    let older = write_snapshot("older-tipless.json",
+        vec![receive(&hash('3'), WESLEY, 1.0, 3, Some(&hash('d')))], vec![], &hash('d'));
+    let newer = write_snapshot("newer-tipless.json",
+        vec![receive(&hash('4'), MCCOY, 1.0, 4, Some(&hash('e')))], vec![], &hash('e'));
+
+    let config = common::config(&database, vec![
+        InputFile { path: older, snapshot_tip: Some(200) },
+        InputFile { path: newer, snapshot_tip: Some(201) },
+    ]);
+    common::load(&config, false).await;
+
+    assert_eq!(main_chain(&database).await, vec![
+        (hash('d'), Some(198), false),
+        (hash('e'), Some(198), true),
+    ]);
+}
@@ -0,0 +1,73 @@
+/*
+handlers/tests/provisional.rs
+10/18/26
+
+This file tests when provisional deposits are expected to mature,
+and that polled ones mature as the node mines blocks.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::json;
+use sources::mock::MockBitcoind;
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+
+// Block time of the receive entries the test helpers build
+const BLOCKTIME: i64 = 1627607548;
+
+#[tokio::test]
+async fn maturity_is_estimated_from_the_block_time() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("provisional.json", vec![
+        receive(&hash('1'), WESLEY, 1.0, 2, Some(&hash('a'))),
+        receive(&hash('2'), MCCOY, 1.0, 0, None),
+    ], vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    // Seconds from the block time, or from now in the mempool, to the estimated maturity
+    let query = "SELECT txid, EXTRACT(EPOCH FROM estimated_maturity - CASE WHEN txid = $1 \
+        THEN to_timestamp($2::BIGINT) ELSE NOW() END)::BIGINT FROM get_provisional_deposits() ORDER BY txid";
+    let estimates: Vec<(String, i64)> = database.client.query(query, &[&hash('1'), &BLOCKTIME]).await
+        .expect("provisional deposits are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(estimates, vec![(hash('1'), 50 * 60), (hash('2'), 60 * 60)]);
+}
+
+#[tokio::test]
+async fn polled_deposit_matures_without_new_transactions() {
+    let Some(database) = common::database().await else { return };
+
+    // The node lists the deposit with one confirmation, then only mines blocks
+    let mut deposit = receive(&hash('3'), WESLEY, 1.0, 1, Some(&hash('a')));
+    deposit["blockheight"] = json!(200);
+    let response = json!({ "transactions": [deposit], "removed": [], "lastblock": hash('a') });
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("port is free");
+    let url = format!("http://{}", listener.local_addr().expect("listener has an address"));
+    let mock = MockBitcoind::new(vec![response], None);
+    let tip = mock.chain_tip();
+    tokio::spawn(async move {
+        let _ = mock.serve(listener).await;
+    });
+
+    let mut config = common::config(&database, vec![]);
+    config.bitcoind_rpc_url = Some(url);
+    common::load(&config, false).await;
+    let confirmed = "SELECT COALESCE(get_total_confirmed_amount($1), 0)::FLOAT8";
+    let total: f64 = database.client.query_one(confirmed, &[&WESLEY]).await.expect("the total is queried").get(0);
+    assert_eq!(total, 0.0);
+
+    // Five blocks later the poll lists nothing new, yet the deposit has 6 confirmations
+    tip.fetch_add(5, Ordering::SeqCst);
+    common::load(&config, false).await;
+    let total: f64 = database.client.query_one(confirmed, &[&WESLEY]).await.expect("the total is queried").get(0);
+    assert_eq!(total, 1.0);
+    let status: String = database.client
+        .query_one("SELECT status FROM deposit_states WHERE txid = $1", &[&hash('3')]).await
+        .expect("the deposit has a state").get(0);
+    assert_eq!(status, "credited");
+}
@@ -0,0 +1,37 @@
+/*
+handlers/tests/quarantine.rs
+10/18/26
+
+This file tests how entries failing validation are quarantined
+and counted in their batch.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::json;
+
+#[tokio::test]
+async fn forced_reload_does_not_quarantine_entries_twice() {
+    let Some(database) = common::database().await else { return };
+
+    let mut invalid = receive(&hash('2'), MCCOY, 1.0, 3, Some(&hash('b')));
+    invalid["vout"] = json!(-1);
+    let rows = vec![receive(&hash('1'), WESLEY, 1.0, 3, Some(&hash('a'))), invalid];
+    let path = write_snapshot("quarantine.json", rows, vec![], &hash('b'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+    common::load(&config, true).await;
+
+    let quarantined: i64 = database.client.query_one("SELECT COUNT(*) FROM quarantined_transactions", &[]).await
+        .expect("quarantined entries are counted").get(0);
+    assert_eq!(quarantined, 1);
+
+    // Both batches count the row they stored and the one they rejected
+    let counts: Vec<(i32, i32)> = database.client
+        .query("SELECT row_count, quarantined_count FROM ingest_batches ORDER BY id", &[]).await
+        .expect("batches are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(counts, vec![(1, 1), (1, 1)]);
+}
@@ -0,0 +1,122 @@
+/*
+handlers/tests/reconcile.rs
+10/18/26
+
+This file tests the reconciliation against a mock bitcoind
+whose wallet has spent deposits on a send.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::{ Value, json };
+use sources::mock::{ CHANGE_ADDRESS, MockBitcoind };
+use tokio::net::TcpListener;
+
+// Outside address the send pays
+const PAYEE: &str = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+
+// Two deposits and a send spending both, paying 1.5 with a 0.0001 fee
+fn history() -> Vec<Value> {
+    let mut send = receive(&hash('3'), PAYEE, -1.5, 6, Some(&hash('a')));
+    send["category"] = json!("send");
+    send["fee"] = json!(-0.0001);
+    vec![
+        receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a'))),
+        receive(&hash('2'), MCCOY, 2.0, 6, Some(&hash('a'))),
+        send,
+    ]
+}
+
+// Serve the responses on a free port and return its url
+async fn bitcoind(responses: Vec<Value>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("a port is free");
+    let url = format!("http://{}", listener.local_addr().expect("the port is bound"));
+    let mock = MockBitcoind::new(responses, None);
+    tokio::spawn(async move {
+        let _ = mock.serve(listener).await;
+    });
+    url
+}
+
+// Kinds of the discrepancies of the snapshot with their count
+async fn kinds(database: &common::TestDatabase, snapshot_id: i32) -> Vec<(String, i64)> {
+    database.client
+        .query("SELECT kind::TEXT, COUNT(*) FROM get_reconciliation_discrepancies($1) GROUP BY kind ORDER BY kind",
+            &[&snapshot_id]).await
+        .expect("discrepancies are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+#[tokio::test]
+async fn spent_deposits_and_change_reconcile() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("spent.json", history(), vec![], &hash('a'));
+    let mut config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    let response = json!({ "transactions": history(), "removed": [], "lastblock": hash('a') });
+    config.bitcoind_rpc_url = Some(bitcoind(vec![response]).await);
+    handlers::reconcile_wallet(&config, None, None, true).await.expect("the wallet reconciles");
+
+    // The spent deposits are not in the wallet and the rest came back as change
+    assert_eq!(kinds(&database, 1).await,
+        vec![(String::from("change"), 1), (String::from("not_in_wallet"), 2)]);
+    let change = database.client
+        .query_one("SELECT address, wallet_amount::FLOAT8 FROM get_reconciliation_discrepancies(1) WHERE kind = 'change'", &[]).await
+        .expect("the change is queried");
+    let (address, amount): (String, f64) = (change.get(0), change.get(1));
+    assert_eq!(address, CHANGE_ADDRESS);
+    assert!((amount - 1.4999).abs() < 1e-8, "{}", amount);
+}
+
+#[tokio::test]
+async fn output_the_engine_cannot_explain_fails() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("spent.json", history(), vec![], &hash('a'));
+    let mut config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    // The wallet has since received a deposit the engine has not ingested
+    let first = json!({ "transactions": history(), "removed": [], "lastblock": hash('a') });
+    let second = json!({
+        "transactions": [receive(&hash('4'), WESLEY, 0.5, 1, Some(&hash('b')))],
+        "removed": [],
+        "lastblock": hash('b')
+    });
+    config.bitcoind_rpc_url = Some(bitcoind(vec![first, second]).await);
+    let error = handlers::reconcile_wallet(&config, None, None, false).await
+        .expect_err("the wallet does not reconcile");
+    assert!(error.to_string().contains("1 outputs not in the engine"), "{}", error);
+
+    assert_eq!(kinds(&database, 1).await, vec![
+        (String::from("change"), 1),
+        (String::from("not_in_engine"), 1),
+        (String::from("not_in_wallet"), 2),
+    ]);
+}
+
+#[tokio::test]
+async fn immature_coinbase_is_left_out_on_both_sides() {
+    let Some(database) = common::database().await else { return };
+
+    // Mined to the wallet 70 blocks ago, 30 short of maturity
+    let mut coinbase = receive(&hash('5'), WESLEY, 50.0, 70, Some(&hash('b')));
+    coinbase["category"] = json!("immature");
+    coinbase["generated"] = json!(true);
+    let mut entries = history();
+    entries.push(coinbase);
+
+    let path = write_snapshot("immature.json", entries.clone(), vec![], &hash('b'));
+    let mut config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    let response = json!({ "transactions": entries, "removed": [], "lastblock": hash('b') });
+    config.bitcoind_rpc_url = Some(bitcoind(vec![response]).await);
+    handlers::reconcile_wallet(&config, None, None, false).await.expect("the wallet reconciles");
+    assert_eq!(kinds(&database, 1).await,
+        vec![(String::from("change"), 1), (String::from("not_in_wallet"), 2)]);
+}
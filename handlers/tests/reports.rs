@@ -0,0 +1,86 @@
+/*
+handlers/tests/reports.rs
+10/18/26
+
+This file tests which deposits the time-windowed reports and
+the statistics scopes count.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use config::InputFile;
+use serde_json::{ Value, json };
+
+// Block time of the receive entries the test helpers build
+const BLOCKTIME: i64 = 1627607548;
+
+fn mined(txid: char, address: &str, confirmations: i32, blockhash: char, minutes: i64) -> Value {
+    let mut entry = receive(&hash(txid), address, 1.0, confirmations, Some(&hash(blockhash)));
+    entry["blocktime"] = json!(BLOCKTIME + minutes * 60);
+    entry
+}
+
+// Deposits to the address counted by the report as of the time, in minutes after BLOCKTIME
+async fn counted_as_of(database: &common::TestDatabase, address: &str, minutes: Option<i64>) -> i32 {
+    let to = minutes.map(|minutes| BLOCKTIME + minutes * 60);
+    database.client
+        .query("SELECT transaction_count FROM get_confirmed_deposits_by_bucket($1, NULL, to_timestamp($2::BIGINT), NULL)",
+            &[&address, &to]).await
+        .expect("the report is queried")
+        .iter()
+        .map(|row| row.get::<_, i32>(0))
+        .sum()
+}
+
+#[tokio::test]
+async fn deposits_count_as_of_when_they_matured() {
+    let Some(database) = common::database().await else { return };
+
+    // Wesley is paid at height 100, the block 5 above is mined 50 minutes later
+    let path = write_snapshot("as-of.json", vec![
+        mined('1', WESLEY, 11, 'a', 0),
+        mined('2', MCCOY, 6, 'b', 50),
+        mined('3', MCCOY, 1, 'c', 100),
+    ], vec![], &hash('c'));
+    let config = common::config(&database, vec![InputFile { path, snapshot_tip: Some(110) }]);
+    common::load(&config, false).await;
+
+    // Mined in the window but not yet matured by its end
+    assert_eq!(counted_as_of(&database, WESLEY, Some(40)).await, 0);
+    assert_eq!(counted_as_of(&database, WESLEY, Some(50)).await, 0);
+    assert_eq!(counted_as_of(&database, WESLEY, Some(51)).await, 1);
+    assert_eq!(counted_as_of(&database, WESLEY, None).await, 1);
+}
+
+// Count and sum of the deposits in a statistics scope
+async fn statistics(database: &common::TestDatabase, address: Option<&str>, label: Option<&str>, unreferenced: bool)
+    -> (i32, Option<f64>) {
+    let row = database.client
+        .query_one("SELECT transaction_count, total_amount FROM get_deposit_statistics($1, $2, $3, NULL, NULL)",
+            &[&address, &label, &unreferenced]).await
+        .expect("statistics are queried");
+    (row.get(0), row.get(1))
+}
+
+#[tokio::test]
+async fn statistics_scopes_add_up_to_all_deposits() {
+    let Some(database) = common::database().await else { return };
+
+    // A testnet address of no known customer, paid under two labels
+    let stranger = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+    let mut kirk = receive(&hash('2'), stranger, 2.0, 6, Some(&hash('a')));
+    kirk["label"] = json!("kirk-7");
+    let mut other = receive(&hash('3'), stranger, 4.0, 6, Some(&hash('a')));
+    other["label"] = json!("other");
+    let path = write_snapshot("scopes.json",
+        vec![receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a'))), kirk, other], vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+    database.client.execute("INSERT INTO label_rules (position, name, pattern) VALUES (0, 'James T. Kirk', 'kirk-%')", &[])
+        .await.expect("the label rule is stored");
+
+    assert_eq!(statistics(&database, None, None, false).await, (3, Some(7.0)));
+    assert_eq!(statistics(&database, Some(WESLEY), None, false).await, (1, Some(1.0)));
+    assert_eq!(statistics(&database, None, Some("James T. Kirk"), false).await, (1, Some(2.0)));
+    assert_eq!(statistics(&database, None, None, true).await, (1, Some(4.0)));
+}
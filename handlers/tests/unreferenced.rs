@@ -0,0 +1,93 @@
+/*
+handlers/tests/unreferenced.rs
+10/18/26
+
+This file tests how unreferenced deposits are filtered.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use config::InputFile;
+use serde_json::json;
+
+// A testnet address of no known customer
+const STRANGER: &str = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+
+// Labels of the unreferenced deposits whose label contains the text
+async fn labels_containing(database: &common::TestDatabase, label: &str) -> Vec<String> {
+    database.client
+        .query("SELECT label FROM get_unreferenced_deposits(NULL, $1, NULL, NULL, NULL, NULL) ORDER BY label",
+            &[&label]).await
+        .expect("unreferenced deposits are queried")
+        .iter()
+        .map(|row| row.get(0))
+        .collect()
+}
+
+#[tokio::test]
+async fn label_filter_takes_like_wildcards_literally() {
+    let Some(database) = common::database().await else { return };
+
+    let labels = ["100% paid", "1000 paid", "order_7", "order 7", "c:\\stale", "c:stale"];
+    let rows = labels.iter().zip('1'..).map(|(label, txid)| {
+        let mut entry = receive(&hash(txid), STRANGER, 1.0, 6, Some(&hash('a')));
+        entry["label"] = json!(label);
+        entry
+    }).collect();
+    let path = write_snapshot("labels.json", rows, vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    assert_eq!(labels_containing(&database, "100%").await, vec!["100% paid"]);
+    assert_eq!(labels_containing(&database, "ORDER_").await, vec!["order_7"]);
+    assert_eq!(labels_containing(&database, "c:\\").await, vec!["c:\\stale"]);
+    assert_eq!(labels_containing(&database, "paid").await, vec!["100% paid", "1000 paid"]);
+}
+
+#[tokio::test]
+async fn known_customers_survive_a_restart() {
+    let Some(database) = common::database().await else { return };
+
+    let path = write_snapshot("restart-customers.json", vec![
+        receive(&hash('1'), WESLEY, 1.0, 6, Some(&hash('a'))),
+        receive(&hash('2'), STRANGER, 2.0, 6, Some(&hash('a'))),
+    ], vec![], &hash('a'));
+    let config = common::config(&database, vec![common::input(&path)]);
+    common::load(&config, false).await;
+
+    // Starting again runs the migrations, and reports run without loading
+    common::migrate(&database).await;
+    let addresses: Vec<String> = database.client
+        .query("SELECT address FROM get_unreferenced_deposits(NULL, NULL, NULL, NULL, NULL, NULL)", &[]).await
+        .expect("unreferenced deposits are queried")
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    assert_eq!(addresses, vec![STRANGER]);
+}
+
+// Unreferenced deposits counted as of the epoch time
+async fn counted_as_of(database: &common::TestDatabase, to: i64) -> usize {
+    database.client
+        .query("SELECT txid FROM get_unreferenced_deposits(NULL, NULL, NULL, NULL, NULL, to_timestamp($1::BIGINT))", &[&to]).await
+        .expect("unreferenced deposits are queried")
+        .len()
+}
+
+#[tokio::test]
+async fn deposits_count_as_of_when_they_matured() {
+    let Some(database) = common::database().await else { return };
+
+    // The stranger is paid at height 100, the block 5 above is mined 50 minutes later
+    let blocktime: i64 = 1627607548;
+    let paid = receive(&hash('1'), STRANGER, 1.0, 11, Some(&hash('a')));
+    let mut later = receive(&hash('2'), MCCOY, 1.0, 6, Some(&hash('b')));
+    later["blocktime"] = json!(blocktime + 50 * 60);
+    let path = write_snapshot("unreferenced-as-of.json", vec![paid, later], vec![], &hash('b'));
+    let config = common::config(&database, vec![InputFile { path, snapshot_tip: Some(110) }]);
+    common::load(&config, false).await;
+
+    assert_eq!(counted_as_of(&database, blocktime + 40 * 60).await, 0);
+    assert_eq!(counted_as_of(&database, blocktime + 50 * 60).await, 0);
+    assert_eq!(counted_as_of(&database, blocktime + 51 * 60).await, 1);
+}
@@ -0,0 +1,114 @@
+/*
+handlers/tests/withdrawals.rs
+10/18/26
+
+This file tests which entries count as deposits and
+who withdrawals are attributed to.
+*/
+mod common;
+
+use common::{ MCCOY, WESLEY, hash, receive, write_snapshot };
+use serde_json::{ Value, json };
+
+const SPOCK: &str = "mvcyJMiAcSXKAEsQxbW9TYZ369rsMG6rVV";
+
+// Coinbase output to Spock with 70 of the 100 confirmations it needs to be spent
+const IMMATURE_TXID: &str = "1f4732db92c9f62474e2b3bdea11420374c0677146e3f7abc16cd60cea25481c";
+
+// A send of the amount to the address, with its label
+fn send(txid: char, address: &str, amount: f64, label: &str) -> Value {
+    let mut entry = receive(&hash(txid), address, -amount, 6, Some(&hash('a')));
+    entry["category"] = json!("send");
+    entry["label"] = json!(label);
+    entry["fee"] = json!(-0.0001);
+    entry
+}
+
+#[tokio::test]
+async fn immature_coinbase_is_not_a_deposit() {
+    let Some(database) = common::database().await else { return };
+
+    let config = common::config(&database, vec![common::input(&common::repo_file("transactions-2.json"))]);
+    common::load(&config, false).await;
+
+    let immature: i64 = database.client
+        .query_one("SELECT COUNT(*) FROM main_chain_deposits WHERE txid = $1", &[&IMMATURE_TXID]).await
+        .expect("deposits are queried").get(0);
+    assert_eq!(immature, 0);
+
+    // The matured coinbase to Spock still counts, the 25 immature ones do not
+    let generated: i64 = database.client
+        .query_one("SELECT COUNT(*) FROM main_chain_deposits WHERE address = $1 AND category = 'generate'", &[&SPOCK]).await
+        .expect("deposits are queried").get(0);
+    assert_eq!(generated, 1);
+    let total: f64 = database.client
+        .query_one("SELECT total_amount::FLOAT8 FROM get_confirmed_deposits_by_bucket($1, NULL, NULL, NULL)", &[&SPOCK]).await
+        .expect("the report is queried").get(0);
+    assert!((total - 225.26432102).abs() < 1e-8, "{}", total);
+}
+
+#[tokio::test]
+async fn withdrawals_are_attributed_by_payout_address_or_send_label() {
+    let Some(database) = common::database().await else { return };
+
+    // Wesley is paid out to McCoy's deposit address, Kirk by the label of the send
+    let known_customers = common::scratch_file("payout-customers.json");
+    std::fs::write(&known_customers, serde_json::to_vec(&json!({
+        "known_customers": [
+            { "name": "Wesley Crusher", "address": WESLEY, "payout_addresses": [MCCOY] },
+            { "name": "Spock", "address": SPOCK }
+        ],
+        "label_rules": [{ "name": "Spock", "pattern": "spock-*" }],
+        "payout_label_rules": [{ "name": "James T. Kirk", "pattern": "kirk-payout-*" }]
+    })).expect("customers serialize")).expect("customers are written");
+
+    let path = write_snapshot("sends.json", vec![
+        send('1', MCCOY, 1.0, ""),
+        send('2', "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc", 2.0, "kirk-payout-1"),
+        // Paying a deposit address or a deposit label attributes nothing
+        send('3', SPOCK, 3.0, "spock-7"),
+    ], vec![], &hash('a'));
+    let mut config = common::config(&database, vec![common::input(&path)]);
+    config.known_customers = known_customers;
+    common::load(&config, false).await;
+
+    let customers: Vec<(String, Option<String>)> = database.client
+        .query("SELECT txid, customer FROM get_withdrawals() ORDER BY txid", &[]).await
+        .expect("withdrawals are queried")
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(customers, vec![
+        (hash('1'), Some(String::from("Wesley Crusher"))),
+        (hash('2'), Some(String::from("James T. Kirk"))),
+        (hash('3'), None),
+    ]);
+}
+
+#[tokio::test]
+async fn net_balances_survive_a_restart() {
+    let Some(database) = common::database().await else { return };
+
+    let known_customers = common::scratch_file("restart-payouts.json");
+    std::fs::write(&known_customers, serde_json::to_vec(&json!({
+        "known_customers": [{ "name": "Wesley Crusher", "address": WESLEY, "payout_addresses": [MCCOY] }]
+    })).expect("customers serialize")).expect("customers are written");
+    let path = write_snapshot("restart-balances.json", vec![
+        receive(&hash('1'), WESLEY, 5.0, 6, Some(&hash('a'))),
+        send('2', MCCOY, 1.0, ""),
+    ], vec![], &hash('a'));
+    let mut config = common::config(&database, vec![common::input(&path)]);
+    config.known_customers = known_customers;
+    common::load(&config, false).await;
+
+    // Starting again runs the migrations, and the report runs without loading
+    common::migrate(&database).await;
+    handlers::net_balances(&config).await.expect("net balances are reported");
+
+    let row = database.client
+        .query_one("SELECT deposit_count, withdrawal_count, net_balance::FLOAT8 FROM get_customer_net_balances() WHERE customer = 'Wesley Crusher'", &[]).await
+        .expect("Wesley has a balance");
+    assert_eq!((row.get::<_, i32>(0), row.get::<_, i32>(1)), (1, 1));
+    let net: f64 = row.get(2);
+    assert!((net - 3.9999).abs() < 1e-8, "{}", net);
+}
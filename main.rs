@@ -7,13 +7,24 @@ It calls each handler and will log the execution time on completion.
 If any errors occur at lower levels in the callstack, they are
 propigated and logged by the main function.
 The Tokio runtime is also managed at this level.
+In daemon mode the handlers run on an interval until SIGINT or
+SIGTERM, and the batch in flight is always finished first.
+Subcommands such as quarantine run on their own and exit.
 */
-use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use clap::Parser;
 use logger::{Logger, LogLevel, log};
-use config::Config;
+use config::{Cli, Command, Config, LedgerAction, QuarantineAction};
+use models::UnreferencedFilter;
+use sources::DepositSource;
 
 #[tokio::main]
 async fn main() {
+    // Parse command line arguments
+    let cli = Cli::parse();
+
     // Initialize logger
     let logger = Logger::new(LogLevel::Info, "log.txt");
     log!(logger, info, "Starting Service");
@@ -30,9 +41,49 @@ async fn main() {
         }
     };
 
+    // Flip the shutdown flag on SIGINT or SIGTERM
+    let shutdown = Arc::new(AtomicBool::new(false));
+    tokio::spawn(wait_for_signal(Arc::clone(&shutdown)));
+
+    // Run a command instead of ingesting when one is given
+    if let Some(command) = &cli.command {
+        run_command(&config, &cli, command, &shutdown, &logger).await;
+        return;
+    }
+
+    // Build the deposit sources once, so files are only drained on the first poll
+    let mut sources = match sources::from_config(&config) {
+        Ok(sources) => sources,
+        Err(e) => {
+            log!(logger, info, "Failed to configure sources: {}", e);
+            return;
+        }
+    };
+
+    if !cli.daemon {
+        run_once(&config, &cli, &mut sources, &shutdown, &logger).await;
+        return;
+    }
+
+    // Poll until asked to stop, checking the flag between cycles
+    log!(logger, info, "Daemon Mode: polling every {}s", cli.interval);
+    while !shutdown.load(Ordering::SeqCst) {
+        run_once(&config, &cli, &mut sources, &shutdown, &logger).await;
+
+        let sleep_until = Instant::now() + Duration::from_secs(cli.interval);
+        while !shutdown.load(Ordering::SeqCst) && Instant::now() < sleep_until {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+    log!(logger, info, "Shutdown Complete");
+}
+
+// Ingest whatever the sources have and regenerate the reports
+async fn run_once(config: &Config, cli: &Cli, sources: &mut [Box<dyn DepositSource + Send>],
+    shutdown: &AtomicBool, logger: &Logger) {
     // Upload the input data to the db
     let load_time = Instant::now();
-    match handlers::load_data(&config).await {
+    match handlers::load_data(config, sources, shutdown, cli.force, logger).await {
         Ok(_) => {
             let load_time_elapsed = load_time.elapsed();
             log!(logger, info, "Load Data Execution Time: {:?}",
@@ -41,36 +92,88 @@ async fn main() {
         Err(e) => log!(logger, info, "Error load_data: {}", e),
     }
 
-    // Query for known customer deposits
-    let known_customer_time = Instant::now();
-    match handlers::known_customer_deposits(&config).await {
-        Ok(_) => {
-            let known_customer_time_elapsed = known_customer_time.elapsed();
-            log!(logger, info, "Known Customer Deposits Execution Time: {:?}",
-                known_customer_time_elapsed);
-        },
-        Err(e) => log!(logger, info, "Error known_customer_deposits: {}", e),
+    // A shutdown requested while loading skips the reports
+    if shutdown.load(Ordering::SeqCst) {
+        return;
     }
 
-    // Query for unknown customer deposits
-    let unknown_customer_time = Instant::now();
-    match handlers::unknown_customer_deposits(&config).await {
+    // Run every report against one consistent snapshot
+    let reports_time = Instant::now();
+    match handlers::generate_reports(config, &cli.report_window()).await {
         Ok(_) => {
-            let unknown_customer_time_elapsed = unknown_customer_time.elapsed();
-            log!(logger, info, "Unknown Customer Deposits Execution Time: {:?}",
-                unknown_customer_time_elapsed);
+            let reports_time_elapsed = reports_time.elapsed();
+            log!(logger, info, "Generate Reports Execution Time: {:?}",
+                reports_time_elapsed);
         },
-        Err(e) => log!(logger, info, "Error unknown_customer_deposits: {}", e),
+        Err(e) => log!(logger, info, "Error generate_reports: {}", e),
     }
+}
 
-    // Calculate the range of deposits (min & max)
-    let calculate_range_time = Instant::now();
-    match handlers::calculate_range(&config).await {
-        Ok(_) => {
-            let calculate_range_time_elapsed = calculate_range_time.elapsed();
-            log!(logger, info, "Calculate Range Execution Time: {:?}",
-                calculate_range_time_elapsed);
+// Run a single command, reporting failures on stderr as well as the log
+async fn run_command(config: &Config, cli: &Cli, command: &Command, shutdown: &AtomicBool, logger: &Logger) {
+    let result = match command {
+        Command::Stats { bins } => handlers::deposit_statistics(config, &cli.report_window(), *bins).await,
+        Command::Unreferenced { address, label, min_amount, max_amount, sort, desc, deposits } => {
+            let filter = UnreferencedFilter {
+                address: address.clone(),
+                label: label.clone(),
+                min_amount: *min_amount,
+                max_amount: *max_amount,
+                sort: *sort,
+                descending: *desc,
+            };
+            handlers::list_unreferenced(config, &cli.report_window(), &filter, *deposits).await
         },
-        Err(e) => log!(logger, info, "Error calculate_range: {}", e),
+        Command::Pending => handlers::provisional_deposits(config).await,
+        Command::Notices { after, follow } =>
+            handlers::stream_notices(config, *after, *follow, cli.interval, shutdown).await,
+        Command::Withdrawals => handlers::list_withdrawals(config).await,
+        Command::NetBalances => handlers::net_balances(config).await,
+        Command::Reconcile { unspent, balances, all } =>
+            handlers::reconcile_wallet(config, unspent.as_deref(), balances.as_deref(), *all).await,
+        Command::Invoices { status, payments } => handlers::list_invoices(config, *status, *payments).await,
+        Command::Transaction { txid } => handlers::show_transaction(config, txid).await,
+        Command::Address { address, page } => handlers::list_address_transactions(config, address, *page).await,
+        Command::Ledger { action } => match action {
+            LedgerAction::Balances => handlers::ledger_balances(config).await,
+            LedgerAction::Journal { account, txid } =>
+                handlers::ledger_journal(config, account.as_deref(), txid.as_deref()).await,
+        },
+        Command::Quarantine { action } => match action {
+            QuarantineAction::List { all } => handlers::list_quarantined(config, *all).await,
+            QuarantineAction::Fix { id, json } => handlers::fix_quarantined(config, *id, json).await,
+            QuarantineAction::Resubmit { ids } => handlers::resubmit_quarantined(config, ids).await,
+        },
+    };
+
+    if let Err(e) = result {
+        log!(logger, info, "Error {:?}: {}", command, e);
+        eprintln!("Error: {}", e);
     }
 }
+
+// Resolve once SIGINT or SIGTERM arrives and raise the shutdown flag
+async fn wait_for_signal(shutdown: Arc<AtomicBool>) {
+    let ctrl_c = tokio::signal::ctrl_c();
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = ctrl_c => {},
+                    _ = terminate.recv() => {},
+                }
+            },
+            Err(_) => { let _ = ctrl_c.await; },
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = ctrl_c.await;
+    }
+
+    shutdown.store(true, Ordering::SeqCst);
+}
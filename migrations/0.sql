@@ -1,31 +1,347 @@
+-- The first version of this script recreated the transactions table on
+-- every run, so a table of that shape, without the batch its rows came
+-- from, holds nothing worth keeping and is replaced
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1
+        FROM information_schema.columns
+        WHERE table_schema = current_schema()
+        AND table_name = 'transactions'
+        AND column_name = 'batch_id'
+    ) THEN
+        DROP TABLE IF EXISTS transactions;
+    END IF;
+END;
+$$;
+
 -- Known Clients table
-DROP TABLE known_clients;
-CREATE TABLE known_clients (
+-- Replaced by the configured customers whenever data is loaded, and kept
+-- across restarts like the rules below, so every command sees them
+CREATE TABLE IF NOT EXISTS known_clients (
     name VARCHAR(64) NOT NULL UNIQUE,
     address VARCHAR(255) NOT NULL UNIQUE
 );
 
+-- Label Rules table
+-- Attribute deposits to addresses of no known client by their wallet label.
+-- Patterns are LIKE patterns matched ignoring case, the first matching rule wins
+CREATE TABLE IF NOT EXISTS label_rules (
+    position INTEGER PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    pattern VARCHAR(255) NOT NULL
+);
+
+-- Payout Addresses table
+-- Addresses customers are paid out to, which attribute withdrawals
+CREATE TABLE IF NOT EXISTS payout_addresses (
+    address VARCHAR(255) PRIMARY KEY,
+    name VARCHAR(64) NOT NULL
+);
+
+-- Payout Label Rules table
+-- Attribute withdrawals to payout addresses of no customer by the label
+-- of the send, like label_rules do for deposits
+CREATE TABLE IF NOT EXISTS payout_label_rules (
+    position INTEGER PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    pattern VARCHAR(255) NOT NULL
+);
+
+-- Blocks table
+-- One row per block referenced by a transaction. Blocks replaced by a
+-- reorg are kept for history but flagged as no longer on the main chain.
+-- seen_tip is the highest snapshot tip the block was seen at and
+-- disconnected_tip the highest one it was reported removed at, so the
+-- newest snapshot decides the main chain whatever order they arrive in
+CREATE TABLE IF NOT EXISTS blocks (
+    hash VARCHAR(64) PRIMARY KEY,
+    height INTEGER,
+    time TIMESTAMPTZ NOT NULL,
+    parent_hash VARCHAR(64),
+    is_main_chain BOOLEAN NOT NULL DEFAULT TRUE,
+    seen_tip INTEGER,
+    disconnected_tip INTEGER,
+    seen_batch_id INTEGER,
+    seen_confirmations INTEGER
+);
+
+-- Ingest Batches table
+-- One row per file or listsinceblock response loaded, so every stored
+-- transaction can be traced back to the input that produced it
+CREATE TABLE IF NOT EXISTS ingest_batches (
+    id SERIAL PRIMARY KEY,
+    source TEXT NOT NULL,
+    sha256 CHAR(64) NOT NULL,
+    row_count INTEGER NOT NULL DEFAULT 0,
+    quarantined_count INTEGER NOT NULL DEFAULT 0,
+    snapshot_tip INTEGER,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    status VARCHAR(16) NOT NULL DEFAULT 'running'
+        CHECK (status IN ('running', 'completed', 'failed'))
+);
+
 -- Transactions table
-DROP TABLE transactions;
-CREATE TABLE transactions (
+CREATE TABLE IF NOT EXISTS transactions (
     involves_watchonly BOOLEAN NOT NULL,
-    account VARCHAR(255) NOT NULL,
-    address VARCHAR(255) NOT NULL,
+    account VARCHAR(255),
+    address VARCHAR(255),
     category VARCHAR(255) NOT NULL,
     amount NUMERIC(18, 8) NOT NULL,
     label VARCHAR(255),
     confirmations INTEGER NOT NULL,
-    blockhash VARCHAR(64) NOT NULL,
-    blockindex INTEGER NOT NULL,
-    blocktime BIGINT NOT NULL,
+    blockhash VARCHAR(64) REFERENCES blocks (hash),
+    blockindex INTEGER,
     txid VARCHAR(64) NOT NULL UNIQUE,
     vout INTEGER NOT NULL,
     walletconflicts TEXT[],
-    time BIGINT NOT NULL,
-    timereceived BIGINT NOT NULL,
-    bip125_replaceable VARCHAR(255) NOT NULL
+    time TIMESTAMPTZ NOT NULL,
+    timereceived TIMESTAMPTZ NOT NULL,
+    bip125_replaceable VARCHAR(255) NOT NULL,
+    removed BOOLEAN NOT NULL DEFAULT FALSE,
+    generated BOOLEAN,
+    abandoned BOOLEAN,
+    fee NUMERIC(18, 8),
+    comment TEXT,
+    comment_to TEXT,
+    batch_id INTEGER NOT NULL REFERENCES ingest_batches (id),
+    last_seen_batch_id INTEGER REFERENCES ingest_batches (id)
+);
+
+-- Ledger Accounts table
+-- One account per customer, a suspense account for deposits no customer
+-- can be found for, and the wallet account every deposit is received into.
+-- The ledger persists across runs like the deposits it records, so a
+-- deposit credited by an earlier run is never posted again
+CREATE TABLE IF NOT EXISTS ledger_accounts (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('wallet', 'customer', 'suspense')),
+    name VARCHAR(64) NOT NULL,
+    UNIQUE (kind, name)
+);
+
+-- Journal Entries table
+-- Every change to what a deposit credits, numbered per txid. The unique
+-- sequence makes posting idempotent: a second posting of the same change
+-- conflicts instead of crediting twice. The credited account and amount
+-- are what the deposit credits after the entry, NULL once reversed
+CREATE TABLE IF NOT EXISTS journal_entries (
+    id SERIAL PRIMARY KEY,
+    txid VARCHAR(64) NOT NULL,
+    sequence INTEGER NOT NULL,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('deposit', 'reversal', 'reattribution', 'adjustment')),
+    credited_account_id INTEGER REFERENCES ledger_accounts (id),
+    credited_amount NUMERIC(18, 8),
+    batch_id INTEGER REFERENCES ingest_batches (id),
+    posted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (txid, sequence)
+);
+
+-- Journal Lines table
+-- Debits are positive and credits negative, and the lines of an entry sum to zero
+CREATE TABLE IF NOT EXISTS journal_lines (
+    entry_id INTEGER NOT NULL REFERENCES journal_entries (id),
+    account_id INTEGER NOT NULL REFERENCES ledger_accounts (id),
+    amount NUMERIC(18, 8) NOT NULL,
+    PRIMARY KEY (entry_id, account_id)
+);
+
+-- Journal entries and lines are never changed once posted,
+-- a correction is posted as an entry of its own
+CREATE OR REPLACE FUNCTION reject_ledger_change()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RAISE EXCEPTION 'Journal % is append-only', TG_TABLE_NAME;
+END;
+$$;
+
+CREATE OR REPLACE TRIGGER journal_entries_immutable
+BEFORE UPDATE OR DELETE OR TRUNCATE ON journal_entries
+FOR EACH STATEMENT EXECUTE FUNCTION reject_ledger_change();
+
+CREATE OR REPLACE TRIGGER journal_lines_immutable
+BEFORE UPDATE OR DELETE OR TRUNCATE ON journal_lines
+FOR EACH STATEMENT EXECUTE FUNCTION reject_ledger_change();
+
+-- Deposit States table
+-- Where each deposit stands: seen in the mempool, pending confirmations,
+-- confirmed, credited in the ledger, or reversed by a reorg or conflict
+CREATE TABLE IF NOT EXISTS deposit_states (
+    txid VARCHAR(64) PRIMARY KEY,
+    status VARCHAR(16) NOT NULL CHECK (status IN ('seen', 'pending', 'confirmed', 'credited', 'reversed')),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Deposit Transitions table
+-- Every status change of a deposit with the batch that caused it,
+-- NULL for changes found after a chain tip change
+CREATE TABLE IF NOT EXISTS deposit_transitions (
+    id SERIAL PRIMARY KEY,
+    txid VARCHAR(64) NOT NULL,
+    from_status VARCHAR(16),
+    to_status VARCHAR(16) NOT NULL,
+    batch_id INTEGER REFERENCES ingest_batches (id),
+    transitioned_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Deposit Notices table
+-- Event stream of provisional deposits, below 6 confirmations. A notice is
+-- recorded when a deposit is first seen and whenever its confirmations
+-- change, and a last one once it matured or was dropped by a reorg or conflict
+CREATE TABLE IF NOT EXISTS deposit_notices (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('provisional', 'matured', 'dropped')),
+    txid VARCHAR(64) NOT NULL,
+    vout INTEGER NOT NULL,
+    address VARCHAR(255),
+    customer VARCHAR(64),
+    amount NUMERIC(18, 8) NOT NULL,
+    confirmations INTEGER NOT NULL,
+    replaceable VARCHAR(255) NOT NULL,
+    estimated_maturity TIMESTAMPTZ,
+    batch_id INTEGER REFERENCES ingest_batches (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Quarantined Transactions table
+-- Entries that failed validation during ingest, kept as received with the
+-- reason and source batch. Resolved once re-submitted in a later batch.
+-- An entry is quarantined once per source content, keyed on the hash of
+-- the content and of the entry as received, so loading the same input
+-- again does not add it twice
+CREATE TABLE IF NOT EXISTS quarantined_transactions (
+    id SERIAL PRIMARY KEY,
+    batch_id INTEGER NOT NULL REFERENCES ingest_batches (id),
+    raw JSONB NOT NULL,
+    reason TEXT NOT NULL,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_batch_id INTEGER REFERENCES ingest_batches (id),
+    source_sha256 CHAR(64),
+    entry_md5 CHAR(32)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS quarantined_transactions_entry
+    ON quarantined_transactions (source_sha256, entry_md5);
+
+-- Sync Cursors table
+-- The lastblock returned by the latest listsinceblock call, per wallet and source
+CREATE TABLE IF NOT EXISTS sync_cursors (
+    wallet VARCHAR(255) PRIMARY KEY,
+    lastblock VARCHAR(64) NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Chain Tip table
+-- Holds a single row with the highest block height seen so far
+-- and the hash of the main chain block at that height, once known
+CREATE TABLE IF NOT EXISTS chain_tip (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    height INTEGER NOT NULL,
+    hash VARCHAR(64)
+);
+
+-- Invoices table
+-- Payment requests expecting an amount at an address until they expire.
+-- Replaced by the configured invoices on every run, like label rules
+DROP TABLE IF EXISTS invoices;
+CREATE TABLE invoices (
+    id VARCHAR(64) PRIMARY KEY,
+    customer VARCHAR(64),
+    address VARCHAR(255) NOT NULL,
+    amount NUMERIC(18, 8) NOT NULL CHECK (amount > 0),
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL CHECK (expires_at > created_at)
+);
+
+-- Wallet Snapshots table
+-- What the wallet reported holding when a reconciliation was run, from a
+-- listunspent snapshot and, when available, the getbalances total
+CREATE TABLE IF NOT EXISTS wallet_snapshots (
+    id SERIAL PRIMARY KEY,
+    source VARCHAR(255) NOT NULL,
+    balance NUMERIC(18, 8),
+    taken_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Wallet Unspent table
+-- The unspent outputs of a wallet snapshot
+CREATE TABLE IF NOT EXISTS wallet_unspent (
+    snapshot_id INTEGER NOT NULL REFERENCES wallet_snapshots (id),
+    txid VARCHAR(64) NOT NULL,
+    vout INTEGER NOT NULL,
+    address VARCHAR(255),
+    amount NUMERIC(18, 8) NOT NULL,
+    confirmations INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, txid, vout)
 );
 
+-- SELECT current_confirmations(block_height, confirmations);
+-- Compute the confirmations of a transaction against the current chain tip.
+-- Falls back to the stored snapshot value when the block height or the tip is unknown
+CREATE OR REPLACE FUNCTION current_confirmations(p_block_height INTEGER, p_confirmations INTEGER)
+RETURNS INTEGER
+LANGUAGE plpgsql
+STABLE
+AS $$
+DECLARE
+    tip_height INTEGER;
+BEGIN
+    IF p_block_height IS NULL THEN
+        RETURN p_confirmations;
+    END IF;
+
+    SELECT height
+    INTO tip_height
+    FROM chain_tip;
+
+    IF tip_height IS NULL THEN
+        RETURN p_confirmations;
+    END IF;
+
+    RETURN tip_height - p_block_height + 1;
+END;
+$$;
+
+-- SELECT matured_before(700000, '2021-08-01');
+-- Whether a deposit mined at the height had its 6th confirmation before
+-- p_to, which is when the block 5 above it was mined. Only blocks holding
+-- wallet transactions are stored, so any main chain block at least 5
+-- above mined before p_to shows it. Always true without a bound
+CREATE OR REPLACE FUNCTION matured_before(p_block_height INTEGER, p_to TIMESTAMPTZ)
+RETURNS BOOLEAN
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT p_to IS NULL OR p_block_height IS NULL OR EXISTS (
+        SELECT 1
+        FROM blocks b
+        WHERE b.is_main_chain
+        AND b.height >= p_block_height + 5
+        AND b.time < p_to
+    );
+$$;
+
+-- Transactions whose block is on the main chain, joined with the block data.
+-- Reorged blocks never count for deposits or withdrawals
+CREATE OR REPLACE VIEW main_chain_transactions AS
+SELECT
+    t.*,
+    b.height AS block_height,
+    b.time AS blocktime
+FROM transactions t
+JOIN blocks b ON b.hash = t.blockhash
+WHERE b.is_main_chain AND NOT t.removed;
+
+-- Main chain transactions that paid into the wallet and can be spent.
+-- Every deposit query reads from here so sends are never counted as
+-- deposits, and neither are coinbase outputs still immature or orphaned
+CREATE OR REPLACE VIEW main_chain_deposits AS
+SELECT *
+FROM main_chain_transactions
+WHERE category IN ('receive', 'generate');
+
 -- SELECT get_total_confirmed_amount('your_wallet_address');
 -- Given a wallet address, return the deposit sum for all transactions
 -- that have at least 6 confirmations
@@ -38,8 +354,8 @@ DECLARE
 BEGIN
     SELECT SUM(amount)
     INTO total_amount
-    FROM transactions
-    WHERE address = wallet_address AND confirmations >= 6;
+    FROM main_chain_deposits
+    WHERE address = wallet_address AND current_confirmations(block_height, confirmations) >= 6;
 
     RETURN total_amount;
 END;
@@ -57,16 +373,47 @@ DECLARE
 BEGIN
     SELECT COUNT(*)
     INTO transaction_count
-    FROM transactions
-    WHERE address = wallet_address AND confirmations >= 6;
+    FROM main_chain_deposits
+    WHERE address = wallet_address AND current_confirmations(block_height, confirmations) >= 6;
 
     RETURN transaction_count;
 END;
 $$;
 
+-- SELECT label_attributed_client('kirk-invoice-17');
+-- Name of the client the first label rule matching the label attributes it to,
+-- NULL when no rule matches or there is no label
+CREATE OR REPLACE FUNCTION label_attributed_client(p_label VARCHAR(255))
+RETURNS VARCHAR(64)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT r.name
+    FROM label_rules r
+    WHERE p_label ILIKE r.pattern
+    ORDER BY r.position
+    LIMIT 1;
+$$;
+
+-- SELECT payout_attributed_client('your_payout_address', 'kirk-payout-3');
+-- Name of the client a withdrawal is attributed to: the one paid out to the
+-- address, else the one the first payout label rule matching the label of
+-- the send attributes it to. NULL when neither does
+CREATE OR REPLACE FUNCTION payout_attributed_client(p_address VARCHAR(255), p_label VARCHAR(255))
+RETURNS VARCHAR(64)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT COALESCE(
+        (SELECT p.name FROM payout_addresses p WHERE p.address = p_address),
+        (SELECT r.name FROM payout_label_rules r WHERE p_label ILIKE r.pattern ORDER BY r.position LIMIT 1)
+    );
+$$;
+
 -- SELECT get_total_confirmed_amount_excluding_known_clients();
 -- Return the deposit sum for all transactions
--- that have at least 6 confirmations and are not from known clients
+-- that have at least 6 confirmations and are not from known clients,
+-- neither by address nor by label
 CREATE OR REPLACE FUNCTION get_total_confirmed_amount_excluding_known_clients()
 RETURNS NUMERIC(18, 8)
 LANGUAGE plpgsql
@@ -76,9 +423,10 @@ DECLARE
 BEGIN
     SELECT SUM(amount)
     INTO total_amount
-    FROM transactions
-    WHERE confirmations >= 6
-    AND address NOT IN (SELECT address FROM known_clients);
+    FROM main_chain_deposits
+    WHERE current_confirmations(block_height, confirmations) >= 6
+    AND address NOT IN (SELECT address FROM known_clients)
+    AND label_attributed_client(label) IS NULL;
 
     RETURN total_amount;
 END;
@@ -96,14 +444,34 @@ DECLARE
 BEGIN
     SELECT COUNT(*)
     INTO transaction_count
-    FROM transactions
-    WHERE confirmations >= 6
-    AND address NOT IN (SELECT address FROM known_clients);
+    FROM main_chain_deposits
+    WHERE current_confirmations(block_height, confirmations) >= 6
+    AND address NOT IN (SELECT address FROM known_clients)
+    AND label_attributed_client(label) IS NULL;
 
     RETURN transaction_count;
 END;
 $$;
 
+-- SELECT get_total_confirmed_amount_all_clients();
+-- Return the deposit sum for all transactions
+-- that have at least 6 confirmations, known and unknown clients alike
+CREATE OR REPLACE FUNCTION get_total_confirmed_amount_all_clients()
+RETURNS NUMERIC(18, 8)
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    total_amount NUMERIC(18, 8) := 0;
+BEGIN
+    SELECT SUM(amount)
+    INTO total_amount
+    FROM main_chain_deposits
+    WHERE current_confirmations(block_height, confirmations) >= 6;
+
+    RETURN total_amount;
+END;
+$$;
+
 -- SELECT get_smallest_confirmed_amount();
 -- Returns the smallest deposit with at least 6 confirmations
 CREATE OR REPLACE FUNCTION get_smallest_confirmed_amount()
@@ -115,8 +483,8 @@ DECLARE
 BEGIN
     SELECT MIN(amount)
     INTO smallest_amount
-    FROM transactions
-    WHERE confirmations >= 6;
+    FROM main_chain_deposits
+    WHERE current_confirmations(block_height, confirmations) >= 6;
 
     RETURN smallest_amount;
 END;
@@ -133,13 +501,1211 @@ DECLARE
 BEGIN
     SELECT MAX(amount)
     INTO max_amount
-    FROM transactions
-    WHERE confirmations >= 6;
+    FROM main_chain_deposits
+    WHERE current_confirmations(block_height, confirmations) >= 6;
 
     RETURN max_amount;
 END;
 $$;
 
+-- SELECT * FROM get_confirmed_deposits_by_bucket('your_wallet_address', '2021-07-01', '2021-08-01', 'day');
+-- Given a wallet address, return the count and sum of deposits with at least
+-- 6 confirmations whose block falls in [p_from, p_to), per day, week or month.
+-- NULL bounds are open and a NULL bucket returns a single row for the window
+CREATE OR REPLACE FUNCTION get_confirmed_deposits_by_bucket(
+    wallet_address VARCHAR(255),
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ,
+    p_bucket VARCHAR(16)
+)
+RETURNS TABLE (bucket_start TIMESTAMPTZ, transaction_count INTEGER, total_amount NUMERIC(18, 8))
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT
+        CASE WHEN p_bucket IS NULL THEN NULL ELSE date_trunc(p_bucket, t.blocktime, 'UTC') END AS bucket,
+        COUNT(*)::INTEGER,
+        SUM(t.amount)::NUMERIC(18, 8)
+    FROM main_chain_deposits t
+    WHERE t.address = wallet_address
+    AND current_confirmations(t.block_height, t.confirmations) >= 6
+    AND (p_from IS NULL OR t.blocktime >= p_from)
+    AND (p_to IS NULL OR t.blocktime < p_to)
+    AND matured_before(t.block_height, p_to)
+    GROUP BY bucket
+    ORDER BY bucket;
+END;
+$$;
+
+-- SELECT * FROM get_unknown_confirmed_deposits_by_bucket('2021-07-01', '2021-08-01', 'day');
+-- The same for every deposit that is not from a known client, by address or label
+CREATE OR REPLACE FUNCTION get_unknown_confirmed_deposits_by_bucket(
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ,
+    p_bucket VARCHAR(16)
+)
+RETURNS TABLE (bucket_start TIMESTAMPTZ, transaction_count INTEGER, total_amount NUMERIC(18, 8))
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT
+        CASE WHEN p_bucket IS NULL THEN NULL ELSE date_trunc(p_bucket, t.blocktime, 'UTC') END AS bucket,
+        COUNT(*)::INTEGER,
+        SUM(t.amount)::NUMERIC(18, 8)
+    FROM main_chain_deposits t
+    WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+    AND t.address NOT IN (SELECT address FROM known_clients)
+    AND label_attributed_client(t.label) IS NULL
+    AND (p_from IS NULL OR t.blocktime >= p_from)
+    AND (p_to IS NULL OR t.blocktime < p_to)
+    AND matured_before(t.block_height, p_to)
+    GROUP BY bucket
+    ORDER BY bucket;
+END;
+$$;
+
+-- SELECT * FROM get_label_attributed_deposits_by_bucket('James T. Kirk', NULL, NULL, NULL);
+-- The same for deposits to addresses of no known client that label rules
+-- attribute to the client. Matching by address takes precedence
+CREATE OR REPLACE FUNCTION get_label_attributed_deposits_by_bucket(
+    p_name VARCHAR(64),
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ,
+    p_bucket VARCHAR(16)
+)
+RETURNS TABLE (bucket_start TIMESTAMPTZ, transaction_count INTEGER, total_amount NUMERIC(18, 8))
+LANGUAGE plpgsql
+STABLE
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT
+        CASE WHEN p_bucket IS NULL THEN NULL ELSE date_trunc(p_bucket, t.blocktime, 'UTC') END AS bucket,
+        COUNT(*)::INTEGER,
+        SUM(t.amount)::NUMERIC(18, 8)
+    FROM main_chain_deposits t
+    WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+    AND t.address NOT IN (SELECT k.address FROM known_clients k)
+    AND label_attributed_client(t.label) = p_name
+    AND (p_from IS NULL OR t.blocktime >= p_from)
+    AND (p_to IS NULL OR t.blocktime < p_to)
+    AND matured_before(t.block_height, p_to)
+    GROUP BY bucket
+    ORDER BY bucket;
+END;
+$$;
+
+-- SELECT * FROM get_confirmed_deposits_in_scope(NULL, NULL, TRUE, NULL, NULL);
+-- Amounts of deposits with at least 6 confirmations whose block falls in
+-- [p_from, p_to), limited to one address when given, to addresses of no
+-- known client that label rules attribute to p_label when given, and to
+-- addresses of no known client and no label rule when p_unreferenced is
+-- set. NULL bounds are open
+CREATE OR REPLACE FUNCTION get_confirmed_deposits_in_scope(
+    p_address VARCHAR(255),
+    p_label VARCHAR(64),
+    p_unreferenced BOOLEAN,
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ
+)
+RETURNS TABLE (amount NUMERIC(18, 8))
+LANGUAGE plpgsql
+STABLE
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT t.amount
+    FROM main_chain_deposits t
+    WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+    AND (p_address IS NULL OR t.address = p_address)
+    AND (p_label IS NULL OR (t.address NOT IN (SELECT k.address FROM known_clients k)
+        AND label_attributed_client(t.label) = p_label))
+    AND (NOT p_unreferenced OR (t.address NOT IN (SELECT k.address FROM known_clients k)
+        AND label_attributed_client(t.label) IS NULL))
+    AND (p_from IS NULL OR t.blocktime >= p_from)
+    AND (p_to IS NULL OR t.blocktime < p_to)
+    AND matured_before(t.block_height, p_to);
+END;
+$$;
+
+-- SELECT * FROM get_deposit_statistics(NULL, NULL, FALSE, NULL, NULL);
+-- Count, sum, mean, median, 90th and 99th percentile, sample standard
+-- deviation and range of the deposits selected as in get_confirmed_deposits_in_scope
+CREATE OR REPLACE FUNCTION get_deposit_statistics(
+    p_address VARCHAR(255),
+    p_label VARCHAR(64),
+    p_unreferenced BOOLEAN,
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ
+)
+RETURNS TABLE (
+    transaction_count INTEGER,
+    total_amount DOUBLE PRECISION,
+    mean_amount DOUBLE PRECISION,
+    median_amount DOUBLE PRECISION,
+    p90_amount DOUBLE PRECISION,
+    p99_amount DOUBLE PRECISION,
+    stddev_amount DOUBLE PRECISION,
+    min_amount DOUBLE PRECISION,
+    max_amount DOUBLE PRECISION
+)
+LANGUAGE plpgsql
+STABLE
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT
+        COUNT(*)::INTEGER,
+        SUM(d.amount)::DOUBLE PRECISION,
+        AVG(d.amount)::DOUBLE PRECISION,
+        percentile_cont(0.5) WITHIN GROUP (ORDER BY d.amount),
+        percentile_cont(0.9) WITHIN GROUP (ORDER BY d.amount),
+        percentile_cont(0.99) WITHIN GROUP (ORDER BY d.amount),
+        stddev_samp(d.amount)::DOUBLE PRECISION,
+        MIN(d.amount)::DOUBLE PRECISION,
+        MAX(d.amount)::DOUBLE PRECISION
+    FROM get_confirmed_deposits_in_scope(p_address, p_label, p_unreferenced, p_from, p_to) d;
+END;
+$$;
+
+-- SELECT * FROM get_deposit_histogram(NULL, NULL, FALSE, NULL, NULL, 10);
+-- Histogram of the deposits selected as in get_confirmed_deposits_in_scope,
+-- in p_bins equal width bins from the smallest to the largest amount.
+-- Every bin but the last excludes its upper bound, empty bins are included
+CREATE OR REPLACE FUNCTION get_deposit_histogram(
+    p_address VARCHAR(255),
+    p_label VARCHAR(64),
+    p_unreferenced BOOLEAN,
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ,
+    p_bins INTEGER
+)
+RETURNS TABLE (
+    bin_number INTEGER,
+    lower_bound DOUBLE PRECISION,
+    upper_bound DOUBLE PRECISION,
+    transaction_count INTEGER
+)
+LANGUAGE plpgsql
+STABLE
+AS $$
+DECLARE
+    low NUMERIC;
+    high NUMERIC;
+    width NUMERIC;
+BEGIN
+    SELECT MIN(d.amount), MAX(d.amount)
+    INTO low, high
+    FROM get_confirmed_deposits_in_scope(p_address, p_label, p_unreferenced, p_from, p_to) d;
+
+    IF low IS NULL THEN
+        RETURN;
+    END IF;
+
+    -- Every deposit has the same amount, so there is nothing to spread out
+    IF low = high THEN
+        RETURN QUERY
+        SELECT 1, low::DOUBLE PRECISION, high::DOUBLE PRECISION, COUNT(*)::INTEGER
+        FROM get_confirmed_deposits_in_scope(p_address, p_label, p_unreferenced, p_from, p_to);
+        RETURN;
+    END IF;
+
+    width := (high - low) / p_bins;
+
+    RETURN QUERY
+    SELECT
+        s.n,
+        (low + (s.n - 1) * width)::DOUBLE PRECISION,
+        (low + s.n * width)::DOUBLE PRECISION,
+        COUNT(d.n)::INTEGER
+    FROM generate_series(1, p_bins) AS s(n)
+    LEFT JOIN (
+        SELECT LEAST(width_bucket(c.amount, low, high, p_bins), p_bins) AS n
+        FROM get_confirmed_deposits_in_scope(p_address, p_label, p_unreferenced, p_from, p_to) c
+    ) d ON d.n = s.n
+    GROUP BY s.n
+    ORDER BY s.n;
+END;
+$$;
+
+-- SELECT * FROM get_unreferenced_deposits(NULL, 'stale', 1, NULL, NULL, NULL);
+-- Deposits with at least 6 confirmations to addresses of no known client
+-- and without a label rule matching them,
+-- optionally limited to one address, to labels containing p_label (any case,
+-- with %, _ and \ taken literally),
+-- to amounts in [p_min_amount, p_max_amount] and to blocks in [p_from, p_to)
+CREATE OR REPLACE FUNCTION get_unreferenced_deposits(
+    p_address VARCHAR(255),
+    p_label VARCHAR(255),
+    p_min_amount NUMERIC(18, 8),
+    p_max_amount NUMERIC(18, 8),
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ
+)
+RETURNS TABLE (
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    amount NUMERIC(18, 8),
+    blocktime TIMESTAMPTZ,
+    label VARCHAR(255)
+)
+LANGUAGE plpgsql
+STABLE
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT t.txid, t.vout, t.address, t.amount, t.blocktime, t.label
+    FROM main_chain_deposits t
+    WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+    AND t.address NOT IN (SELECT k.address FROM known_clients k)
+    AND label_attributed_client(t.label) IS NULL
+    AND (p_address IS NULL OR t.address = p_address)
+    AND (p_label IS NULL OR t.label ILIKE '%' || replace(replace(replace(p_label, '\', '\\'), '%', '\%'), '_', '\_') || '%')
+    AND (p_min_amount IS NULL OR t.amount >= p_min_amount)
+    AND (p_max_amount IS NULL OR t.amount <= p_max_amount)
+    AND (p_from IS NULL OR t.blocktime >= p_from)
+    AND (p_to IS NULL OR t.blocktime < p_to)
+    AND matured_before(t.block_height, p_to)
+    ORDER BY t.address, t.blocktime, t.txid;
+END;
+$$;
+
+-- SELECT * FROM get_unreferenced_deposits_by_address(NULL, NULL, NULL, NULL, NULL, NULL, 'amount', TRUE);
+-- The deposits selected as in get_unreferenced_deposits grouped per address,
+-- with when the address was first and last paid and the labels it was paid
+-- under. Sorted by address, amount, count, first or last, ties by address
+CREATE OR REPLACE FUNCTION get_unreferenced_deposits_by_address(
+    p_address VARCHAR(255),
+    p_label VARCHAR(255),
+    p_min_amount NUMERIC(18, 8),
+    p_max_amount NUMERIC(18, 8),
+    p_from TIMESTAMPTZ,
+    p_to TIMESTAMPTZ,
+    p_sort VARCHAR(16),
+    p_descending BOOLEAN
+)
+RETURNS TABLE (
+    address VARCHAR(255),
+    transaction_count INTEGER,
+    total_amount NUMERIC(18, 8),
+    first_deposit TIMESTAMPTZ,
+    last_deposit TIMESTAMPTZ,
+    labels TEXT[]
+)
+LANGUAGE plpgsql
+STABLE
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT
+        g.address,
+        g.transaction_count,
+        g.total_amount,
+        g.first_deposit,
+        g.last_deposit,
+        g.labels
+    FROM (
+        SELECT
+            d.address,
+            COUNT(*)::INTEGER AS transaction_count,
+            SUM(d.amount)::NUMERIC(18, 8) AS total_amount,
+            MIN(d.blocktime) AS first_deposit,
+            MAX(d.blocktime) AS last_deposit,
+            COALESCE(
+                array_agg(DISTINCT d.label::TEXT) FILTER (WHERE d.label IS NOT NULL AND d.label <> ''),
+                '{}'
+            ) AS labels
+        FROM get_unreferenced_deposits(p_address, p_label, p_min_amount, p_max_amount, p_from, p_to) d
+        GROUP BY d.address
+    ) g,
+    LATERAL (
+        SELECT CASE p_sort
+            WHEN 'amount' THEN g.total_amount
+            WHEN 'count' THEN g.transaction_count
+            WHEN 'first' THEN EXTRACT(EPOCH FROM g.first_deposit)
+            WHEN 'last' THEN EXTRACT(EPOCH FROM g.last_deposit)
+        END AS sort_key
+    ) s
+    ORDER BY
+        CASE WHEN p_descending THEN -s.sort_key ELSE s.sort_key END,
+        CASE WHEN p_descending AND p_sort = 'address' THEN g.address END DESC,
+        g.address;
+END;
+$$;
+
+-- SELECT * FROM get_withdrawals();
+-- Every send out of the wallet that still stands, with the customer it is
+-- attributed to by the payout address it paid or its label, never by the
+-- deposit address or label rules of a customer. Unlike deposits a
+-- withdrawal counts from the moment it is broadcast, as the coins are gone
+-- unless it is removed, orphaned, conflicted or abandoned. Amounts and fees
+-- are positive
+CREATE OR REPLACE FUNCTION get_withdrawals()
+RETURNS TABLE (
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    customer VARCHAR(64),
+    amount NUMERIC(18, 8),
+    fee NUMERIC(18, 8),
+    confirmations INTEGER,
+    sent_at TIMESTAMPTZ
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT
+        t.txid,
+        t.vout,
+        t.address,
+        payout_attributed_client(t.address, t.label),
+        ABS(t.amount),
+        ABS(COALESCE(t.fee, 0)),
+        current_confirmations(b.height, t.confirmations),
+        t."time"
+    FROM transactions t
+    LEFT JOIN blocks b ON b.hash = t.blockhash
+    WHERE t.category = 'send'
+    AND NOT t.removed
+    AND NOT COALESCE(t.abandoned, FALSE)
+    AND (t.blockhash IS NULL OR b.is_main_chain)
+    AND t.confirmations >= 0
+    ORDER BY 4 NULLS LAST, t."time", t.txid;
+$$;
+
+-- SELECT * FROM get_customer_net_balances();
+-- Valid deposits less withdrawals and their fees per customer, with a
+-- NULL customer for everything attributed to no customer
+CREATE OR REPLACE FUNCTION get_customer_net_balances()
+RETURNS TABLE (
+    customer VARCHAR(64),
+    deposit_count INTEGER,
+    deposit_amount NUMERIC(18, 8),
+    withdrawal_count INTEGER,
+    withdrawal_amount NUMERIC(18, 8),
+    fee_amount NUMERIC(18, 8),
+    net_balance NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH movements AS (
+        SELECT
+            COALESCE(k.name, label_attributed_client(t.label)) AS customer,
+            t.amount AS deposited,
+            0 AS withdrawn,
+            0 AS fee
+        FROM main_chain_deposits t
+        LEFT JOIN known_clients k ON k.address = t.address
+        WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+        UNION ALL
+        SELECT w.customer, NULL, w.amount, w.fee
+        FROM get_withdrawals() w
+    )
+    SELECT
+        m.customer,
+        COUNT(m.deposited)::INTEGER,
+        COALESCE(SUM(m.deposited), 0),
+        (COUNT(*) - COUNT(m.deposited))::INTEGER,
+        SUM(m.withdrawn),
+        SUM(m.fee),
+        COALESCE(SUM(m.deposited), 0) - SUM(m.withdrawn) - SUM(m.fee)
+    FROM movements m
+    GROUP BY m.customer
+    ORDER BY m.customer NULLS LAST;
+$$;
+
+-- SELECT record_wallet_snapshot('listunspent.json', NULL, ARRAY['your_txid'], ARRAY[0], ARRAY['your_wallet_address'], ARRAY[1.5], ARRAY[6]);
+-- Store a wallet snapshot with its unspent outputs and return its id
+CREATE OR REPLACE FUNCTION record_wallet_snapshot(
+    p_source VARCHAR(255),
+    p_balance NUMERIC(18, 8),
+    p_txids VARCHAR(64)[],
+    p_vouts INTEGER[],
+    p_addresses VARCHAR(255)[],
+    p_amounts NUMERIC(18, 8)[],
+    p_confirmations INTEGER[]
+)
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    snapshot_id INTEGER;
+BEGIN
+    INSERT INTO wallet_snapshots (source, balance)
+    VALUES (p_source, p_balance)
+    RETURNING id INTO snapshot_id;
+
+    INSERT INTO wallet_unspent (snapshot_id, txid, vout, address, amount, confirmations)
+    SELECT snapshot_id, u.txid, u.vout, u.address, u.amount, u.confirmations
+    FROM unnest(p_txids, p_vouts, p_addresses, p_amounts, p_confirmations)
+        AS u(txid, vout, address, amount, confirmations);
+
+    RETURN snapshot_id;
+END;
+$$;
+
+-- SELECT * FROM get_standing_deposits();
+-- Every deposit the engine stored that still stands, whatever its
+-- confirmations: not removed, orphaned or conflicted. These are the
+-- outputs the wallet should report as unspent unless they were spent
+CREATE OR REPLACE FUNCTION get_standing_deposits()
+RETURNS TABLE (
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    amount NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT t.txid, t.vout, t.address, t.amount
+    FROM transactions t
+    LEFT JOIN blocks b ON b.hash = t.blockhash
+    WHERE t.category IN ('receive', 'generate')
+    AND NOT t.removed
+    AND (t.blockhash IS NULL OR b.is_main_chain)
+    AND t.confirmations >= 0;
+$$;
+
+-- SELECT * FROM get_reconciliation_totals(1);
+-- What the engine holds, standing deposits less withdrawals and fees,
+-- against what the snapshot holds, its unspent outputs and its balance
+CREATE OR REPLACE FUNCTION get_reconciliation_totals(p_snapshot_id INTEGER)
+RETURNS TABLE (
+    deposit_amount NUMERIC(18, 8),
+    withdrawal_amount NUMERIC(18, 8),
+    fee_amount NUMERIC(18, 8),
+    engine_balance NUMERIC(18, 8),
+    unspent_amount NUMERIC(18, 8),
+    wallet_balance NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH deposits AS (
+        SELECT COALESCE(SUM(d.amount), 0) AS amount
+        FROM get_standing_deposits() d
+    ), withdrawals AS (
+        SELECT COALESCE(SUM(w.amount), 0) AS amount, COALESCE(SUM(w.fee), 0) AS fee
+        FROM get_withdrawals() w
+    ), unspent AS (
+        SELECT COALESCE(SUM(u.amount), 0) AS amount
+        FROM wallet_unspent u
+        WHERE u.snapshot_id = p_snapshot_id
+    )
+    SELECT
+        d.amount,
+        w.amount,
+        w.fee,
+        d.amount - w.amount - w.fee,
+        u.amount,
+        COALESCE(s.balance, u.amount)
+    FROM wallet_snapshots s
+    CROSS JOIN deposits d
+    CROSS JOIN withdrawals w
+    CROSS JOIN unspent u
+    WHERE s.id = p_snapshot_id;
+$$;
+
+-- SELECT * FROM get_reconciliation_by_address(1);
+-- Standing deposits against unspent outputs of the snapshot per address,
+-- with the customer the address belongs to. Addresses only one side
+-- knows of get a zero count on the other
+CREATE OR REPLACE FUNCTION get_reconciliation_by_address(p_snapshot_id INTEGER)
+RETURNS TABLE (
+    address VARCHAR(255),
+    customer VARCHAR(64),
+    engine_count INTEGER,
+    engine_amount NUMERIC(18, 8),
+    wallet_count INTEGER,
+    wallet_amount NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH outputs AS (
+        SELECT d.address, d.amount, TRUE AS engine
+        FROM get_standing_deposits() d
+        UNION ALL
+        SELECT u.address, u.amount, FALSE
+        FROM wallet_unspent u
+        WHERE u.snapshot_id = p_snapshot_id
+    )
+    SELECT
+        o.address,
+        MIN(k.name),
+        COUNT(*) FILTER (WHERE o.engine)::INTEGER,
+        COALESCE(SUM(o.amount) FILTER (WHERE o.engine), 0),
+        COUNT(*) FILTER (WHERE NOT o.engine)::INTEGER,
+        COALESCE(SUM(o.amount) FILTER (WHERE NOT o.engine), 0)
+    FROM outputs o
+    LEFT JOIN known_clients k ON k.address = o.address
+    GROUP BY o.address
+    ORDER BY o.address NULLS LAST;
+$$;
+
+-- SELECT * FROM get_reconciliation_discrepancies(1);
+-- The outputs responsible for a difference: standing deposits the wallet
+-- does not hold unspent, unspent outputs the engine has no deposit for,
+-- and outputs both know of for different amounts or addresses. Unspent
+-- outputs of a stored send are the change it returned to the wallet
+CREATE OR REPLACE FUNCTION get_reconciliation_discrepancies(p_snapshot_id INTEGER)
+RETURNS TABLE (
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    kind VARCHAR(16),
+    engine_amount NUMERIC(18, 8),
+    wallet_amount NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH wallet AS (
+        SELECT u.txid, u.vout, u.address, u.amount
+        FROM wallet_unspent u
+        WHERE u.snapshot_id = p_snapshot_id
+    )
+    SELECT
+        COALESCE(d.txid, w.txid),
+        COALESCE(d.vout, w.vout),
+        COALESCE(d.address, w.address),
+        (CASE
+            WHEN w.txid IS NULL THEN 'not_in_wallet'
+            WHEN d.txid IS NULL AND EXISTS (
+                SELECT 1
+                FROM transactions t
+                WHERE t.txid = w.txid
+                AND t.category = 'send'
+                AND NOT t.removed
+            ) THEN 'change'
+            WHEN d.txid IS NULL THEN 'not_in_engine'
+            ELSE 'mismatch'
+        END)::VARCHAR(16),
+        d.amount,
+        w.amount
+    FROM get_standing_deposits() d
+    FULL JOIN wallet w ON w.txid = d.txid AND w.vout = d.vout
+    WHERE d.txid IS NULL OR w.txid IS NULL
+    OR d.amount <> w.amount OR d.address IS DISTINCT FROM w.address
+    ORDER BY 3 NULLS LAST, 1, 2;
+$$;
+
+-- SELECT * FROM get_invoice_payments();
+-- Every valid deposit to an invoice address, matched to the latest invoice
+-- on the address created before the wallet first saw the deposit. Deposits
+-- seen after the invoice expired are late. The others are paid towards the
+-- invoice in time order, and duplicates once the invoice was fully paid
+CREATE OR REPLACE FUNCTION get_invoice_payments()
+RETURNS TABLE (
+    invoice_id VARCHAR(64),
+    txid VARCHAR(64),
+    vout INTEGER,
+    amount NUMERIC(18, 8),
+    paid_at TIMESTAMPTZ,
+    kind VARCHAR(16)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH matched AS (
+        SELECT
+            i.id,
+            i.amount AS expected,
+            t.txid,
+            t.vout,
+            t.amount,
+            t."time" AS paid_at,
+            t."time" > i.expires_at AS late
+        FROM main_chain_deposits t
+        CROSS JOIN LATERAL (
+            SELECT i.id, i.amount, i.expires_at
+            FROM invoices i
+            WHERE i.address = t.address
+            AND i.created_at <= t."time"
+            ORDER BY i.created_at DESC, i.id
+            LIMIT 1
+        ) i
+        WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+    ), running AS (
+        SELECT m.*, COALESCE(SUM(m.amount) OVER (
+            PARTITION BY m.id, m.late
+            ORDER BY m.paid_at, m.txid, m.vout
+            ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+        ), 0) AS paid_before
+        FROM matched m
+    )
+    SELECT
+        r.id,
+        r.txid,
+        r.vout,
+        r.amount,
+        r.paid_at,
+        (CASE
+            WHEN r.late THEN 'late'
+            WHEN r.paid_before >= r.expected THEN 'duplicate'
+            ELSE 'paid'
+        END)::VARCHAR(16)
+    FROM running r
+    ORDER BY r.id, r.paid_at, r.txid, r.vout;
+$$;
+
+-- SELECT * FROM get_invoice_statuses();
+-- Every invoice with its status and the payments matched to it. The paid
+-- amount, the payments before expiry up to the one that paid it in full,
+-- is compared with the expected one. An invoice only paid after it expired
+-- is late, and an unpaid one is open until it expires
+CREATE OR REPLACE FUNCTION get_invoice_statuses()
+RETURNS TABLE (
+    id VARCHAR(64),
+    customer VARCHAR(64),
+    address VARCHAR(255),
+    amount NUMERIC(18, 8),
+    created_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    status VARCHAR(16),
+    paid_amount NUMERIC(18, 8),
+    payment_count INTEGER
+)
+LANGUAGE sql
+STABLE
+AS $$
+    WITH payments AS (
+        SELECT
+            p.invoice_id,
+            SUM(p.amount) FILTER (WHERE p.kind = 'paid') AS paid_amount,
+            COUNT(*)::INTEGER AS count,
+            BOOL_OR(p.kind = 'late') AS late
+        FROM get_invoice_payments() p
+        GROUP BY p.invoice_id
+    )
+    SELECT
+        i.id,
+        i.customer,
+        i.address,
+        i.amount,
+        i.created_at,
+        i.expires_at,
+        (CASE
+            WHEN p.paid_amount = i.amount THEN 'exact'
+            WHEN p.paid_amount < i.amount THEN 'underpaid'
+            WHEN p.paid_amount > i.amount THEN 'overpaid'
+            WHEN p.late THEN 'late'
+            WHEN NOW() >= i.expires_at THEN 'expired'
+            ELSE 'open'
+        END)::VARCHAR(16),
+        COALESCE(p.paid_amount, 0),
+        COALESCE(p.count, 0)
+    FROM invoices i
+    LEFT JOIN payments p ON p.invoice_id = i.id
+    ORDER BY i.created_at, i.id;
+$$;
+
+-- SELECT * FROM find_transactions('your_txid', NULL, 1, 0);
+-- SELECT * FROM find_transactions(NULL, 'your_wallet_address', 25, 0);
+-- Stored transactions by txid or by address, oldest first, a page at a time,
+-- with their block, their current standing, their recorded lifecycle status
+-- and the batch they came from.
+-- A transaction is confirmed with 6 confirmations on the main chain, and
+-- conflicted when removed, orphaned by a reorg, or with negative confirmations
+-- once a transaction it conflicts with was mined. Listing conflicts is not
+-- enough, the confirmed winner of a double spend lists the loser too
+CREATE OR REPLACE FUNCTION find_transactions(
+    p_txid VARCHAR(64),
+    p_address VARCHAR(255),
+    p_limit INTEGER,
+    p_offset INTEGER
+)
+RETURNS TABLE (
+    involves_watchonly BOOLEAN,
+    account VARCHAR(255),
+    address VARCHAR(255),
+    category VARCHAR(255),
+    amount NUMERIC(18, 8),
+    label VARCHAR(255),
+    confirmations INTEGER,
+    generated BOOLEAN,
+    blockhash VARCHAR(64),
+    blockindex INTEGER,
+    blocktime TIMESTAMPTZ,
+    txid VARCHAR(64),
+    vout INTEGER,
+    walletconflicts TEXT[],
+    "time" TIMESTAMPTZ,
+    timereceived TIMESTAMPTZ,
+    bip125_replaceable VARCHAR(255),
+    blockheight INTEGER,
+    fee NUMERIC(18, 8),
+    abandoned BOOLEAN,
+    comment TEXT,
+    comment_to TEXT,
+    removed BOOLEAN,
+    is_main_chain BOOLEAN,
+    current_confirmations INTEGER,
+    is_confirmed BOOLEAN,
+    is_conflicted BOOLEAN,
+    customer VARCHAR(64),
+    attribution VARCHAR(16),
+    batch_id INTEGER,
+    batch_source TEXT,
+    ingested_at TIMESTAMPTZ,
+    lifecycle_status VARCHAR(16),
+    lifecycle_updated_at TIMESTAMPTZ
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT
+        t.involves_watchonly,
+        t.account,
+        t.address,
+        t.category,
+        t.amount,
+        t.label,
+        t.confirmations,
+        t.generated,
+        t.blockhash,
+        t.blockindex,
+        b.time,
+        t.txid,
+        t.vout,
+        t.walletconflicts,
+        t.time,
+        t.timereceived,
+        t.bip125_replaceable,
+        b.height,
+        t.fee,
+        t.abandoned,
+        t.comment,
+        t.comment_to,
+        t.removed,
+        b.is_main_chain,
+        c.confirmations,
+        NOT t.removed AND COALESCE(b.is_main_chain, FALSE) AND c.confirmations >= 6,
+        t.removed OR NOT COALESCE(b.is_main_chain, TRUE) OR t.confirmations < 0,
+        COALESCE(k.name, a.name),
+        CASE WHEN k.name IS NOT NULL THEN 'address' WHEN a.name IS NOT NULL THEN 'label' END,
+        t.batch_id,
+        i.source,
+        i.started_at,
+        s.status,
+        s.updated_at
+    FROM transactions t
+    LEFT JOIN blocks b ON b.hash = t.blockhash
+    LEFT JOIN known_clients k ON k.address = t.address
+    LEFT JOIN deposit_states s ON s.txid = t.txid
+    JOIN ingest_batches i ON i.id = t.batch_id
+    CROSS JOIN LATERAL (SELECT current_confirmations(b.height, t.confirmations) AS confirmations) c
+    CROSS JOIN LATERAL (SELECT label_attributed_client(t.label) AS name) a
+    WHERE (p_txid IS NULL OR t.txid = p_txid)
+    AND (p_address IS NULL OR t.address = p_address)
+    ORDER BY t.time, t.txid
+    LIMIT p_limit OFFSET p_offset;
+$$;
+
+-- SELECT ledger_account('customer', 'James T. Kirk');
+-- Id of a ledger account, opening it on first use
+CREATE OR REPLACE FUNCTION ledger_account(p_kind VARCHAR(16), p_name VARCHAR(64))
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    account_id INTEGER;
+BEGIN
+    INSERT INTO ledger_accounts (kind, name) VALUES (p_kind, p_name)
+    ON CONFLICT (kind, name) DO NOTHING;
+
+    SELECT a.id
+    INTO account_id
+    FROM ledger_accounts a
+    WHERE a.kind = p_kind AND a.name = p_name;
+
+    RETURN account_id;
+END;
+$$;
+
+-- SELECT post_ledger_entries(NULL);
+-- Bring the ledger in line with the valid deposits and return the number of
+-- entries posted. A deposit that became valid is debited to the wallet and
+-- credited to its customer, found by address or label, or to suspense.
+-- A credited deposit that is no longer valid is reversed, and one whose
+-- customer or amount changed is moved over. Nothing is posted for deposits
+-- the ledger already agrees with, so posting again changes nothing
+CREATE OR REPLACE FUNCTION post_ledger_entries(p_batch_id INTEGER)
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    wallet_account INTEGER;
+    target_account INTEGER;
+    entry_id INTEGER;
+    entry_kind VARCHAR(16);
+    posted INTEGER := 0;
+    deposit RECORD;
+BEGIN
+    -- Concurrent postings would compute the same entries, let them take turns
+    PERFORM pg_advisory_xact_lock(hashtext('post_ledger_entries'));
+
+    wallet_account := ledger_account('wallet', 'wallet');
+
+    FOR deposit IN
+        WITH valid AS (
+            SELECT t.txid, t.amount, COALESCE(k.name, label_attributed_client(t.label)) AS customer
+            FROM main_chain_deposits t
+            LEFT JOIN known_clients k ON k.address = t.address
+            WHERE current_confirmations(t.block_height, t.confirmations) >= 6
+        ),
+        credited AS (
+            SELECT DISTINCT ON (e.txid) e.txid, e.sequence, e.credited_account_id, e.credited_amount
+            FROM journal_entries e
+            ORDER BY e.txid, e.sequence DESC
+        )
+        SELECT
+            COALESCE(v.txid, c.txid) AS txid,
+            v.amount,
+            v.customer,
+            COALESCE(c.sequence, 0) AS sequence,
+            c.credited_account_id,
+            c.credited_amount
+        FROM valid v
+        FULL JOIN credited c ON c.txid = v.txid
+        ORDER BY 1
+    LOOP
+        IF deposit.amount IS NULL THEN
+            target_account := NULL;
+        ELSIF deposit.customer IS NULL THEN
+            target_account := ledger_account('suspense', 'suspense');
+        ELSE
+            target_account := ledger_account('customer', deposit.customer);
+        END IF;
+
+        CONTINUE WHEN target_account IS NOT DISTINCT FROM deposit.credited_account_id
+            AND deposit.amount IS NOT DISTINCT FROM deposit.credited_amount;
+
+        entry_kind := CASE
+            WHEN deposit.credited_account_id IS NULL THEN 'deposit'
+            WHEN target_account IS NULL THEN 'reversal'
+            WHEN target_account <> deposit.credited_account_id THEN 'reattribution'
+            ELSE 'adjustment'
+        END;
+
+        INSERT INTO journal_entries (txid, sequence, kind, credited_account_id, credited_amount, batch_id)
+        VALUES (deposit.txid, deposit.sequence + 1, entry_kind, target_account, deposit.amount, p_batch_id)
+        RETURNING id INTO entry_id;
+
+        -- Undo the previous credit and make the new one,
+        -- netting the lines of accounts on both sides
+        INSERT INTO journal_lines (entry_id, account_id, amount)
+        SELECT entry_id, l.account_id, SUM(l.amount)
+        FROM (VALUES
+            (deposit.credited_account_id, deposit.credited_amount),
+            (CASE WHEN deposit.credited_account_id IS NOT NULL THEN wallet_account END, -deposit.credited_amount),
+            (CASE WHEN target_account IS NOT NULL THEN wallet_account END, deposit.amount),
+            (target_account, -deposit.amount)
+        ) AS l(account_id, amount)
+        WHERE l.account_id IS NOT NULL
+        GROUP BY l.account_id
+        HAVING SUM(l.amount) <> 0;
+
+        posted := posted + 1;
+    END LOOP;
+
+    RETURN posted;
+END;
+$$;
+
+-- SELECT * FROM get_ledger_balances();
+-- Balance of every ledger account as derived from its journal lines. Customer
+-- and suspense balances are what was credited, the wallet balance what was
+-- received, so the wallet balance equals the sum of all others
+CREATE OR REPLACE FUNCTION get_ledger_balances()
+RETURNS TABLE (kind VARCHAR(16), name VARCHAR(64), balance NUMERIC(18, 8), entry_count INTEGER)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT
+        a.kind,
+        a.name,
+        COALESCE(CASE WHEN a.kind = 'wallet' THEN SUM(l.amount) ELSE -SUM(l.amount) END, 0)::NUMERIC(18, 8),
+        COUNT(l.entry_id)::INTEGER
+    FROM ledger_accounts a
+    LEFT JOIN journal_lines l ON l.account_id = a.id
+    GROUP BY a.id, a.kind, a.name
+    ORDER BY CASE a.kind WHEN 'wallet' THEN 0 WHEN 'customer' THEN 1 ELSE 2 END, a.name;
+$$;
+
+-- SELECT * FROM get_journal('James T. Kirk', NULL);
+-- Journal lines in posting order, limited to the entries touching the named
+-- account and to one txid when given. Debits are positive, credits negative
+CREATE OR REPLACE FUNCTION get_journal(p_account VARCHAR(64), p_txid VARCHAR(64))
+RETURNS TABLE (
+    entry_id INTEGER,
+    txid VARCHAR(64),
+    sequence INTEGER,
+    kind VARCHAR(16),
+    batch_id INTEGER,
+    posted_at TIMESTAMPTZ,
+    account_kind VARCHAR(16),
+    account_name VARCHAR(64),
+    amount NUMERIC(18, 8)
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT e.id, e.txid, e.sequence, e.kind, e.batch_id, e.posted_at, a.kind, a.name, l.amount
+    FROM journal_entries e
+    JOIN journal_lines l ON l.entry_id = e.id
+    JOIN ledger_accounts a ON a.id = l.account_id
+    WHERE (p_txid IS NULL OR e.txid = p_txid)
+    AND (p_account IS NULL OR e.id IN (
+        SELECT l2.entry_id
+        FROM journal_lines l2
+        JOIN ledger_accounts a2 ON a2.id = l2.account_id
+        WHERE a2.name = p_account
+    ))
+    ORDER BY e.id, l.amount DESC;
+$$;
+
+-- SELECT update_deposit_states(NULL);
+-- Move every deposit to the status it has now and record each change,
+-- returning the number of transitions recorded. Runs after the ledger
+-- was posted, so a deposit credited in the same batch it confirmed in
+-- records both steps. Credited follows the ledger, and a deposit the
+-- ledger does not credit is reversed once removed, orphaned or conflicted.
+-- A credited deposit the ledger stops crediting is always reversed first,
+-- even when it is already back in a block, so no credit goes unrecorded
+CREATE OR REPLACE FUNCTION update_deposit_states(p_batch_id INTEGER)
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    previous VARCHAR(16);
+    recorded INTEGER := 0;
+    deposit RECORD;
+BEGIN
+    PERFORM pg_advisory_xact_lock(hashtext('update_deposit_states'));
+
+    FOR deposit IN
+        SELECT
+            t.txid,
+            s.status AS previous_status,
+            CASE
+                WHEN COALESCE(c.credited, FALSE) THEN 'credited'
+                WHEN t.removed OR NOT COALESCE(b.is_main_chain, TRUE) OR t.confirmations < 0 THEN 'reversed'
+                WHEN t.blockhash IS NULL THEN 'seen'
+                WHEN current_confirmations(b.height, t.confirmations) >= 6 THEN 'confirmed'
+                ELSE 'pending'
+            END AS status
+        FROM transactions t
+        LEFT JOIN blocks b ON b.hash = t.blockhash
+        LEFT JOIN deposit_states s ON s.txid = t.txid
+        LEFT JOIN LATERAL (
+            SELECT e.credited_account_id IS NOT NULL AS credited
+            FROM journal_entries e
+            WHERE e.txid = t.txid
+            ORDER BY e.sequence DESC
+            LIMIT 1
+        ) c ON TRUE
+        WHERE t.category IN ('receive', 'generate')
+        ORDER BY t.txid
+    LOOP
+        previous := deposit.previous_status;
+        CONTINUE WHEN previous IS NOT DISTINCT FROM deposit.status;
+
+        -- No longer credited, so the credit was reversed before it moved on
+        IF previous = 'credited' AND deposit.status NOT IN ('credited', 'reversed') THEN
+            INSERT INTO deposit_transitions (txid, from_status, to_status, batch_id)
+            VALUES (deposit.txid, previous, 'reversed', p_batch_id);
+            previous := 'reversed';
+            recorded := recorded + 1;
+        END IF;
+
+        -- Credited in the batch it confirmed in, so it went through confirmed too
+        IF deposit.status = 'credited' AND previous IS DISTINCT FROM 'confirmed' THEN
+            INSERT INTO deposit_transitions (txid, from_status, to_status, batch_id)
+            VALUES (deposit.txid, previous, 'confirmed', p_batch_id);
+            previous := 'confirmed';
+            recorded := recorded + 1;
+        END IF;
+
+        INSERT INTO deposit_transitions (txid, from_status, to_status, batch_id)
+        VALUES (deposit.txid, previous, deposit.status, p_batch_id);
+        recorded := recorded + 1;
+
+        INSERT INTO deposit_states (txid, status, updated_at)
+        VALUES (deposit.txid, deposit.status, NOW())
+        ON CONFLICT (txid) DO UPDATE SET status = EXCLUDED.status, updated_at = EXCLUDED.updated_at;
+    END LOOP;
+
+    RETURN recorded;
+END;
+$$;
+
+-- SELECT * FROM get_deposit_transitions('your_txid');
+-- Status changes of a deposit, oldest first
+CREATE OR REPLACE FUNCTION get_deposit_transitions(p_txid VARCHAR(64))
+RETURNS TABLE (
+    from_status VARCHAR(16),
+    to_status VARCHAR(16),
+    batch_id INTEGER,
+    transitioned_at TIMESTAMPTZ
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT d.from_status, d.to_status, d.batch_id, d.transitioned_at
+    FROM deposit_transitions d
+    WHERE d.txid = p_txid
+    ORDER BY d.id;
+$$;
+
+-- SELECT * FROM get_provisional_deposits();
+-- Deposits still below 6 confirmations, in the mempool or in a main chain
+-- block, with the customer they would be credited to, whether they can still
+-- be replaced (BIP 125) and when they should mature at one block every
+-- 10 minutes: 5 blocks after their own block, or 6 from now in the
+-- mempool. Deposits removed, orphaned or conflicted are left out
+CREATE OR REPLACE FUNCTION get_provisional_deposits()
+RETURNS TABLE (
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    customer VARCHAR(64),
+    amount NUMERIC(18, 8),
+    confirmations INTEGER,
+    replaceable VARCHAR(255),
+    first_seen TIMESTAMPTZ,
+    estimated_maturity TIMESTAMPTZ
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT
+        t.txid,
+        t.vout,
+        t.address,
+        COALESCE(k.name, label_attributed_client(t.label)),
+        t.amount,
+        c.confirmations,
+        t.bip125_replaceable,
+        t.timereceived,
+        CASE
+            WHEN t.blockhash IS NULL THEN NOW() + 6 * INTERVAL '10 minutes'
+            ELSE b.time + (6 - 1) * INTERVAL '10 minutes'
+        END
+    FROM transactions t
+    LEFT JOIN blocks b ON b.hash = t.blockhash
+    LEFT JOIN known_clients k ON k.address = t.address
+    CROSS JOIN LATERAL (SELECT current_confirmations(b.height, t.confirmations) AS confirmations) c
+    WHERE NOT t.removed
+    AND t.category IN ('receive', 'generate')
+    AND (t.blockhash IS NULL OR b.is_main_chain)
+    AND t.confirmations >= 0
+    AND c.confirmations BETWEEN 0 AND 5
+    ORDER BY 4 NULLS LAST, t.time, t.txid;
+$$;
+
+-- SELECT record_deposit_notices(NULL);
+-- Record a notice for every provisional deposit that is new or whose
+-- confirmations changed since its last notice, and a closing one for every
+-- deposit that stopped being provisional. Returns the number recorded
+CREATE OR REPLACE FUNCTION record_deposit_notices(p_batch_id INTEGER)
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    recorded INTEGER;
+    closed INTEGER;
+BEGIN
+    PERFORM pg_advisory_xact_lock(hashtext('record_deposit_notices'));
+
+    WITH latest AS (
+        SELECT DISTINCT ON (n.txid) n.txid, n.kind, n.confirmations
+        FROM deposit_notices n
+        ORDER BY n.txid, n.id DESC
+    )
+    INSERT INTO deposit_notices (kind, txid, vout, address, customer, amount, confirmations,
+        replaceable, estimated_maturity, batch_id)
+    SELECT 'provisional', p.txid, p.vout, p.address, p.customer, p.amount, p.confirmations,
+        p.replaceable, p.estimated_maturity, p_batch_id
+    FROM get_provisional_deposits() p
+    LEFT JOIN latest l ON l.txid = p.txid
+    WHERE l.txid IS NULL OR l.kind <> 'provisional' OR l.confirmations <> p.confirmations
+    ORDER BY p.txid;
+    GET DIAGNOSTICS recorded = ROW_COUNT;
+
+    -- Close out the deposits that matured or were dropped since their last notice
+    WITH latest AS (
+        SELECT DISTINCT ON (n.txid) n.txid, n.kind
+        FROM deposit_notices n
+        ORDER BY n.txid, n.id DESC
+    )
+    INSERT INTO deposit_notices (kind, txid, vout, address, customer, amount, confirmations,
+        replaceable, estimated_maturity, batch_id)
+    SELECT
+        CASE WHEN m.txid IS NOT NULL THEN 'matured' ELSE 'dropped' END,
+        t.txid, t.vout, t.address, COALESCE(k.name, label_attributed_client(t.label)), t.amount,
+        COALESCE(m.confirmations, t.confirmations), t.bip125_replaceable, NULL, p_batch_id
+    FROM latest l
+    JOIN transactions t ON t.txid = l.txid
+    LEFT JOIN known_clients k ON k.address = t.address
+    LEFT JOIN LATERAL (
+        SELECT current_confirmations(v.block_height, v.confirmations) AS confirmations, v.txid
+        FROM main_chain_deposits v
+        WHERE v.txid = t.txid
+        AND current_confirmations(v.block_height, v.confirmations) >= 6
+    ) m ON TRUE
+    WHERE l.kind = 'provisional'
+    AND l.txid NOT IN (SELECT p.txid FROM get_provisional_deposits() p)
+    ORDER BY t.txid;
+    GET DIAGNOSTICS closed = ROW_COUNT;
+
+    RETURN recorded + closed;
+END;
+$$;
+
+-- SELECT * FROM get_deposit_notices(0, 100);
+-- Notices recorded after the given id, oldest first
+CREATE OR REPLACE FUNCTION get_deposit_notices(p_after_id INTEGER, p_limit INTEGER)
+RETURNS TABLE (
+    id INTEGER,
+    kind VARCHAR(16),
+    txid VARCHAR(64),
+    vout INTEGER,
+    address VARCHAR(255),
+    customer VARCHAR(64),
+    amount NUMERIC(18, 8),
+    confirmations INTEGER,
+    replaceable VARCHAR(255),
+    estimated_maturity TIMESTAMPTZ,
+    batch_id INTEGER,
+    created_at TIMESTAMPTZ
+)
+LANGUAGE sql
+STABLE
+AS $$
+    SELECT n.id, n.kind, n.txid, n.vout, n.address, n.customer, n.amount, n.confirmations,
+        n.replaceable, n.estimated_maturity, n.batch_id, n.created_at
+    FROM deposit_notices n
+    WHERE n.id > p_after_id
+    ORDER BY n.id
+    LIMIT p_limit;
+$$;
+
+-- Procedure for removing every known client, before the configured
+-- ones are inserted again
+CREATE OR REPLACE PROCEDURE clear_known_clients()
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    DELETE FROM known_clients;
+END;
+$$;
+
 -- Procedure for creating a known client entry
 CREATE OR REPLACE PROCEDURE insert_known_client(
     p_name VARCHAR(64),
@@ -152,6 +1718,384 @@ BEGIN
 END;
 $$;
 
+-- CALL set_label_rules(ARRAY['James T. Kirk'], ARRAY['kirk-%']);
+-- Replace every label rule, in order of precedence
+CREATE OR REPLACE PROCEDURE set_label_rules(
+    p_names VARCHAR(64)[],
+    p_patterns VARCHAR(255)[]
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    DELETE FROM label_rules;
+    INSERT INTO label_rules (position, name, pattern)
+    SELECT r.position, r.name, r.pattern
+    FROM unnest(p_names, p_patterns) WITH ORDINALITY AS r(name, pattern, position);
+END;
+$$;
+
+-- CALL set_payout_addresses(ARRAY['James T. Kirk'], ARRAY['your_payout_address']);
+-- Replace every payout address
+CREATE OR REPLACE PROCEDURE set_payout_addresses(
+    p_names VARCHAR(64)[],
+    p_addresses VARCHAR(255)[]
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    DELETE FROM payout_addresses;
+    INSERT INTO payout_addresses (address, name)
+    SELECT p.address, p.name
+    FROM unnest(p_names, p_addresses) AS p(name, address);
+END;
+$$;
+
+-- CALL set_payout_label_rules(ARRAY['James T. Kirk'], ARRAY['kirk-payout-%']);
+-- Replace every payout label rule, in order of precedence
+CREATE OR REPLACE PROCEDURE set_payout_label_rules(
+    p_names VARCHAR(64)[],
+    p_patterns VARCHAR(255)[]
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    DELETE FROM payout_label_rules;
+    INSERT INTO payout_label_rules (position, name, pattern)
+    SELECT r.position, r.name, r.pattern
+    FROM unnest(p_names, p_patterns) WITH ORDINALITY AS r(name, pattern, position);
+END;
+$$;
+
+-- CALL set_invoices(ARRAY['INV-1'], ARRAY['James T. Kirk'], ARRAY['your_wallet_address'], ARRAY[1.5], ARRAY[NOW()], ARRAY[NOW() + INTERVAL '1 hour']);
+-- Replace every invoice
+CREATE OR REPLACE PROCEDURE set_invoices(
+    p_ids VARCHAR(64)[],
+    p_customers VARCHAR(64)[],
+    p_addresses VARCHAR(255)[],
+    p_amounts NUMERIC(18, 8)[],
+    p_created TIMESTAMPTZ[],
+    p_expires TIMESTAMPTZ[]
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    DELETE FROM invoices;
+    INSERT INTO invoices (id, customer, address, amount, created_at, expires_at)
+    SELECT i.id, i.customer, i.address, i.amount, i.created_at, i.expires_at
+    FROM unnest(p_ids, p_customers, p_addresses, p_amounts, p_created, p_expires)
+        AS i(id, customer, address, amount, created_at, expires_at);
+END;
+$$;
+
+-- Procedure for advancing the chain tip, it never moves backwards
+CREATE OR REPLACE PROCEDURE set_chain_tip(
+    p_height INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    INSERT INTO chain_tip (id, height) VALUES (TRUE, p_height)
+    ON CONFLICT (id) DO UPDATE SET height = GREATEST(chain_tip.height, EXCLUDED.height);
+
+    UPDATE chain_tip
+    SET hash = (
+        SELECT hash
+        FROM blocks
+        WHERE height = chain_tip.height AND is_main_chain
+    );
+END;
+$$;
+
+-- Procedure for creating a block entry, or recording that the
+-- current batch saw it again with the given confirmations
+CREATE OR REPLACE PROCEDURE insert_block(
+    p_hash VARCHAR(64),
+    p_height INTEGER,
+    p_time TIMESTAMPTZ,
+    p_confirmations INTEGER,
+    p_batch_id INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    -- Which block is on the main chain is only decided once the
+    -- whole batch is in, see resolve_main_chain
+    INSERT INTO blocks (hash, height, time, seen_batch_id, seen_confirmations)
+    VALUES (p_hash, p_height, p_time, p_batch_id, p_confirmations)
+    ON CONFLICT (hash) DO UPDATE SET
+        height = COALESCE(blocks.height, EXCLUDED.height),
+        seen_batch_id = EXCLUDED.seen_batch_id,
+        seen_confirmations = EXCLUDED.seen_confirmations;
+END;
+$$;
+
+-- Procedure for settling the main chain once a batch is stored.
+-- Blocks the batch saw without a height get one from the snapshot tip.
+-- At every height the block seen by the snapshot with the highest tip
+-- wins, the latest batch breaking ties, unless a snapshot at least as
+-- new reported it removed. Blocks without a height can only be removed
+CREATE OR REPLACE PROCEDURE resolve_main_chain(
+    p_batch_id INTEGER,
+    p_tip INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF p_tip IS NOT NULL THEN
+        UPDATE blocks
+        SET height = p_tip - seen_confirmations + 1
+        WHERE seen_batch_id = p_batch_id
+        AND height IS NULL
+        AND seen_confirmations > 0;
+
+        UPDATE blocks
+        SET seen_tip = GREATEST(seen_tip, p_tip)
+        WHERE seen_batch_id = p_batch_id;
+    END IF;
+
+    WITH ranked AS (
+        SELECT
+            hash,
+            height,
+            connected,
+            ROW_NUMBER() OVER (
+                PARTITION BY height
+                ORDER BY connected DESC, COALESCE(seen_tip, -1) DESC, seen_batch_id DESC NULLS LAST
+            ) AS rank
+        FROM (
+            SELECT
+                *,
+                disconnected_tip IS NULL OR COALESCE(seen_tip, -1) > disconnected_tip AS connected
+            FROM blocks
+        ) AS b
+    )
+    UPDATE blocks
+    SET is_main_chain = r.connected AND (r.height IS NULL OR r.rank = 1)
+    FROM ranked AS r
+    WHERE blocks.hash = r.hash
+    AND blocks.is_main_chain IS DISTINCT FROM (r.connected AND (r.height IS NULL OR r.rank = 1));
+
+    -- Link main chain blocks to their parent once both are known
+    UPDATE blocks AS child
+    SET parent_hash = parent.hash
+    FROM blocks AS parent
+    WHERE parent.height = child.height - 1
+    AND parent.is_main_chain
+    AND child.is_main_chain
+    AND child.parent_hash IS DISTINCT FROM parent.hash;
+END;
+$$;
+
+-- SELECT get_last_block('your_wallet');
+-- Returns the listsinceblock cursor stored for a wallet
+CREATE OR REPLACE FUNCTION get_last_block(p_wallet VARCHAR(255))
+RETURNS VARCHAR(64)
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    last_block VARCHAR(64);
+BEGIN
+    SELECT lastblock
+    INTO last_block
+    FROM sync_cursors
+    WHERE wallet = p_wallet;
+
+    RETURN last_block;
+END;
+$$;
+
+-- Procedure for storing the listsinceblock cursor of a wallet
+CREATE OR REPLACE PROCEDURE set_last_block(
+    p_wallet VARCHAR(255),
+    p_lastblock VARCHAR(64)
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    INSERT INTO sync_cursors (wallet, lastblock) VALUES (p_wallet, p_lastblock)
+    ON CONFLICT (wallet) DO UPDATE SET lastblock = EXCLUDED.lastblock, updated_at = NOW();
+END;
+$$;
+
+-- SELECT start_ingest_batch('transactions-1.json', 'sha256 of the content');
+-- Records the start of an ingest and returns the batch id stamped on its rows
+CREATE OR REPLACE FUNCTION start_ingest_batch(p_source TEXT, p_sha256 CHAR(64))
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    batch_id INTEGER;
+BEGIN
+    INSERT INTO ingest_batches (source, sha256)
+    VALUES (p_source, p_sha256)
+    RETURNING id INTO batch_id;
+
+    RETURN batch_id;
+END;
+$$;
+
+-- SELECT find_ingested_batch('sha256 of the content');
+-- Returns the latest completed batch with the given content hash, if any
+CREATE OR REPLACE FUNCTION find_ingested_batch(p_sha256 CHAR(64))
+RETURNS INTEGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    batch_id INTEGER;
+BEGIN
+    SELECT id
+    INTO batch_id
+    FROM ingest_batches
+    WHERE sha256 = p_sha256
+    AND status = 'completed'
+    ORDER BY id DESC
+    LIMIT 1;
+
+    RETURN batch_id;
+END;
+$$;
+
+-- Procedure for closing an ingest batch as completed or failed,
+-- with the number of rows it stored and quarantined
+CREATE OR REPLACE PROCEDURE finish_ingest_batch(
+    p_id INTEGER,
+    p_row_count INTEGER,
+    p_quarantined_count INTEGER,
+    p_status VARCHAR(16)
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE ingest_batches
+    SET row_count = p_row_count,
+        quarantined_count = p_quarantined_count,
+        finished_at = NOW(),
+        status = p_status
+    WHERE id = p_id;
+END;
+$$;
+
+-- Procedure for recording the chain tip height a batch was taken at
+CREATE OR REPLACE PROCEDURE set_ingest_batch_tip(
+    p_id INTEGER,
+    p_height INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE ingest_batches
+    SET snapshot_tip = p_height
+    WHERE id = p_id;
+END;
+$$;
+
+-- SELECT get_ingest_batch_tip(1);
+-- Returns the chain tip height a batch was taken at, if known
+CREATE OR REPLACE FUNCTION get_ingest_batch_tip(p_id INTEGER)
+RETURNS INTEGER
+LANGUAGE sql
+AS $$
+    SELECT snapshot_tip FROM ingest_batches WHERE id = p_id;
+$$;
+
+-- Procedure for quarantining an entry that failed validation,
+-- unless the same entry of the same source content already was
+CREATE OR REPLACE PROCEDURE quarantine_transaction(
+    p_batch_id INTEGER,
+    p_raw JSONB,
+    p_reason TEXT
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    INSERT INTO quarantined_transactions (batch_id, raw, reason, source_sha256, entry_md5)
+    SELECT p_batch_id, p_raw, p_reason, b.sha256, MD5(p_raw::TEXT)
+    FROM ingest_batches b
+    WHERE b.id = p_batch_id
+    ON CONFLICT (source_sha256, entry_md5) DO NOTHING;
+END;
+$$;
+
+-- SELECT * FROM get_quarantined_transactions(FALSE);
+-- Returns quarantined entries, oldest first. Resolved ones only when asked for
+CREATE OR REPLACE FUNCTION get_quarantined_transactions(p_include_resolved BOOLEAN)
+RETURNS TABLE (
+    id INTEGER,
+    batch_id INTEGER,
+    raw JSONB,
+    reason TEXT,
+    resolved_batch_id INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    RETURN QUERY
+    SELECT q.id, q.batch_id, q.raw, q.reason, q.resolved_batch_id
+    FROM quarantined_transactions q
+    WHERE p_include_resolved OR q.resolved_batch_id IS NULL
+    ORDER BY q.id;
+END;
+$$;
+
+-- SELECT fix_quarantined_transaction(1, '{"txid": "..."}');
+-- Replaces the entry of a pending quarantined row.
+-- Returns false when there is no such row or it was already resolved
+CREATE OR REPLACE FUNCTION fix_quarantined_transaction(p_id INTEGER, p_raw JSONB)
+RETURNS BOOLEAN
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE quarantined_transactions
+    SET raw = p_raw
+    WHERE id = p_id
+    AND resolved_batch_id IS NULL;
+
+    RETURN FOUND;
+END;
+$$;
+
+-- Procedure for recording the outcome of re-submitting a quarantined row.
+-- A batch id resolves it, otherwise the new validation failure is kept
+CREATE OR REPLACE PROCEDURE resubmit_quarantined_transaction(
+    p_id INTEGER,
+    p_resolved_batch_id INTEGER,
+    p_reason TEXT
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE quarantined_transactions
+    SET resolved_batch_id = p_resolved_batch_id,
+        reason = COALESCE(p_reason, reason)
+    WHERE id = p_id;
+END;
+$$;
+
+-- Procedure for reversing a transaction listed in the removed array of
+-- listsinceblock. Its block was disconnected by a reorg and leaves the main chain
+CREATE OR REPLACE PROCEDURE remove_transaction(
+    p_txid VARCHAR(64),
+    p_blockhash VARCHAR(64),
+    p_tip INTEGER
+)
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    -- Only while still stored with the disconnected block, a replacement
+    -- already seen in its new block must stay credited
+    UPDATE transactions
+    SET removed = TRUE
+    WHERE txid = p_txid AND blockhash IS NOT DISTINCT FROM p_blockhash;
+
+    -- Without a snapshot tip the block is only known to be gone as of
+    -- the newest snapshot that had seen it
+    UPDATE blocks
+    SET is_main_chain = FALSE,
+        disconnected_tip = GREATEST(disconnected_tip, COALESCE(p_tip, seen_tip, 0))
+    WHERE hash = p_blockhash;
+END;
+$$;
+
 -- Procedure for creating a transaction entry
 CREATE OR REPLACE PROCEDURE insert_transaction(
     p_involves_watchonly BOOLEAN,
@@ -163,13 +2107,18 @@ CREATE OR REPLACE PROCEDURE insert_transaction(
     p_confirmations INTEGER,
     p_blockhash VARCHAR(64),
     p_blockindex INTEGER,
-    p_blocktime BIGINT,
     p_txid VARCHAR(64),
     p_vout INTEGER,
     p_walletconflicts TEXT[],
-    p_time BIGINT,
-    p_timereceived BIGINT,
-    p_bip125_replaceable VARCHAR(255)
+    p_time TIMESTAMPTZ,
+    p_timereceived TIMESTAMPTZ,
+    p_bip125_replaceable VARCHAR(255),
+    p_generated BOOLEAN,
+    p_abandoned BOOLEAN,
+    p_fee NUMERIC(20, 10),
+    p_comment TEXT,
+    p_comment_to TEXT,
+    p_batch_id INTEGER
 )
 LANGUAGE plpgsql
 AS $$
@@ -185,13 +2134,18 @@ BEGIN
             confirmations, 
             blockhash, 
             blockindex, 
-            blocktime, 
             txid, 
             vout, 
             walletconflicts, 
             time, 
             timereceived, 
-            bip125_replaceable
+            bip125_replaceable,
+            generated,
+            abandoned,
+            fee,
+            comment,
+            comment_to,
+            batch_id
         ) VALUES (
             p_involves_watchonly, 
             p_account, 
@@ -202,16 +2156,46 @@ BEGIN
             p_confirmations, 
             p_blockhash, 
             p_blockindex, 
-            p_blocktime, 
             p_txid, 
             p_vout, 
             p_walletconflicts, 
             p_time, 
             p_timereceived, 
-            p_bip125_replaceable
+            p_bip125_replaceable,
+            p_generated,
+            p_abandoned,
+            p_fee,
+            p_comment,
+            p_comment_to,
+            p_batch_id
         );
     EXCEPTION WHEN unique_violation THEN
-        -- Ignore duplicate key violation and do nothing
+        -- The row keeps the batch that first stored it, and the latest
+        -- batch that saw it again is recorded alongside
+        UPDATE transactions
+        SET last_seen_batch_id = p_batch_id
+        WHERE txid = p_txid AND vout = p_vout;
+
+        -- A transaction seen again in a block it was not stored with was either
+        -- confirmed since, or mined again after a reorg, so it moves to that block
+        UPDATE transactions
+        SET blockhash = p_blockhash,
+            blockindex = p_blockindex,
+            confirmations = p_confirmations,
+            removed = FALSE
+        WHERE txid = p_txid AND vout = p_vout
+        AND p_blockhash IS NOT NULL
+        AND (removed OR blockhash IS DISTINCT FROM p_blockhash);
+
+        -- Seen again in the block it is stored with, it only gained confirmations.
+        -- Snapshots without block heights carry them on the row alone
+        UPDATE transactions
+        SET confirmations = GREATEST(confirmations, p_confirmations)
+        WHERE txid = p_txid AND vout = p_vout
+        AND NOT removed
+        AND blockhash IS NOT DISTINCT FROM p_blockhash;
+
+        -- Ignore any other duplicate key violation and do nothing
         RAISE NOTICE 'Duplicate entry detected for txid: %', p_txid;
     END;
 END;
@@ -0,0 +1,215 @@
+/*
+models/src/address.rs
+10/18/26
+
+This file defines bitcoin address validation.
+Legacy addresses are checked as base58check with the version byte
+of the network, segwit addresses as bech32 (v0) or bech32m (v1+)
+with the human readable part of the network.
+*/
+use bech32::Hrp;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// Version bytes of base58check addresses
+const MAINNET_P2PKH: u8 = 0x00;
+const MAINNET_P2SH: u8 = 0x05;
+const TESTNET_P2PKH: u8 = 0x6f;
+const TESTNET_P2SH: u8 = 0xc4;
+
+// Version byte followed by a 20 byte hash
+const BASE58_PAYLOAD_LEN: usize = 21;
+
+// Bitcoin networks an address can belong to
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    // Base58check version bytes for P2PKH and P2SH.
+    // Signet and regtest reuse the testnet versions
+    fn base58_versions(&self) -> [u8; 2] {
+        match self {
+            Network::Mainnet => [MAINNET_P2PKH, MAINNET_P2SH],
+            _ => [TESTNET_P2PKH, TESTNET_P2SH],
+        }
+    }
+
+    // Human readable part of segwit addresses. Signet shares tb with testnet
+    fn segwit_hrp(&self) -> Hrp {
+        match self {
+            Network::Mainnet => bech32::hrp::BC,
+            Network::Testnet | Network::Signet => bech32::hrp::TB,
+            Network::Regtest => bech32::hrp::BCRT,
+        }
+    }
+
+    // The network every address belongs to. Signet addresses cannot be
+    // told from testnet ones, and legacy regtest addresses share the
+    // testnet versions, so testnet is preferred when both fit
+    pub fn infer<'a>(addresses: impl IntoIterator<Item = &'a str>) -> Result<Network, String> {
+        let mut candidates = vec![Network::Mainnet, Network::Testnet, Network::Regtest];
+        let mut any = false;
+        for address in addresses {
+            any = true;
+            candidates.retain(|network| validate_address(address, *network).is_ok());
+            if candidates.is_empty() {
+                return Err(format!("no network fits address {}", address));
+            }
+        }
+        if !any {
+            return Err(String::from("no addresses to infer the network from"));
+        }
+        Ok(candidates[0])
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    // Accepts the names bitcoind uses for -chain as well
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mainnet" | "main" | "bitcoin" => Ok(Network::Mainnet),
+            "testnet" | "test" | "testnet3" | "testnet4" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("Unknown bitcoin network: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Check that an address is well formed and belongs to the network,
+// returning why it is not
+pub fn validate_address(address: &str, network: Network) -> Result<(), String> {
+    // Segwit addresses start with their hrp and a 1 separator
+    if let Some((hrp, _)) = address.rsplit_once('1') {
+        if ["bc", "tb", "bcrt"].contains(&hrp.to_ascii_lowercase().as_str()) {
+            return validate_segwit(address, network);
+        }
+    }
+    validate_base58(address, network)
+}
+
+// Base58check: valid checksum, 21 byte payload and the version of the network
+fn validate_base58(address: &str, network: Network) -> Result<(), String> {
+    let payload = bs58::decode(address).with_check(None).into_vec()
+        .map_err(|e| format!("invalid base58check address: {}", e))?;
+
+    if payload.len() != BASE58_PAYLOAD_LEN {
+        return Err(format!("invalid base58check payload length: {}", payload.len()));
+    }
+    if !network.base58_versions().contains(&payload[0]) {
+        return Err(format!("address version 0x{:02x} is not a {} address", payload[0], network));
+    }
+    Ok(())
+}
+
+// Bech32 for witness version 0 and bech32m above, with the hrp of the network
+fn validate_segwit(address: &str, network: Network) -> Result<(), String> {
+    let (hrp, _version, _program) = bech32::segwit::decode(address)
+        .map_err(|e| format!("invalid segwit address: {}", e))?;
+
+    if hrp != network.segwit_hrp() {
+        return Err(format!("prefix {} is not a {} address", hrp, network));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAINNET_P2PKH_ADDRESS: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+    const MAINNET_P2SH_ADDRESS: &str = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+    const TESTNET_P2PKH_ADDRESS: &str = "mvcyJMiAcSXKAEsQxbW9TYZ369rsMG6rVV";
+    const TESTNET_P2SH_ADDRESS: &str = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+    const MAINNET_V0: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const TESTNET_V0: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const REGTEST_V0: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+    const TESTNET_V1: &str = "tb1pqqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0slua5fd";
+
+    #[test]
+    fn accepts_base58check_addresses_of_the_network() {
+        assert_eq!(validate_address(MAINNET_P2PKH_ADDRESS, Network::Mainnet), Ok(()));
+        assert_eq!(validate_address(MAINNET_P2SH_ADDRESS, Network::Mainnet), Ok(()));
+        assert_eq!(validate_address(TESTNET_P2PKH_ADDRESS, Network::Testnet), Ok(()));
+        assert_eq!(validate_address(TESTNET_P2SH_ADDRESS, Network::Testnet), Ok(()));
+        assert_eq!(validate_address(TESTNET_P2PKH_ADDRESS, Network::Signet), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_base58check_checksums() {
+        let typo = TESTNET_P2PKH_ADDRESS.replace('V', "W");
+        assert!(validate_address(&typo, Network::Testnet).unwrap_err().starts_with("invalid base58check address"));
+        assert!(validate_address("mvcyJMiAcSXKAEsQxbW0", Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn rejects_base58check_payloads_of_the_wrong_length() {
+        let short = bs58::encode([TESTNET_P2PKH; 11]).with_check().into_string();
+        assert!(validate_address(&short, Network::Testnet).unwrap_err().starts_with("invalid base58check payload length"));
+    }
+
+    #[test]
+    fn accepts_bech32_and_bech32m_addresses_of_the_network() {
+        assert_eq!(validate_address(MAINNET_V0, Network::Mainnet), Ok(()));
+        assert_eq!(validate_address(TESTNET_V0, Network::Testnet), Ok(()));
+        assert_eq!(validate_address(&TESTNET_V0.to_uppercase(), Network::Testnet), Ok(()));
+        assert_eq!(validate_address(REGTEST_V0, Network::Regtest), Ok(()));
+        assert_eq!(validate_address(TESTNET_V1, Network::Testnet), Ok(()));
+    }
+
+    #[test]
+    fn rejects_segwit_addresses_with_the_wrong_checksum_variant() {
+        // Witness version 1 checksummed as bech32, version 0 as bech32m
+        let v1_bech32 = "tb1pqqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0s2qdcv0";
+        let v0_bech32m = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7knazw4y";
+        assert!(validate_address(v1_bech32, Network::Testnet).unwrap_err().starts_with("invalid segwit address"));
+        assert!(validate_address(v0_bech32m, Network::Testnet).unwrap_err().starts_with("invalid segwit address"));
+        assert!(validate_address(&TESTNET_V0.replace('w', "x"), Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn rejects_addresses_of_another_network() {
+        assert!(validate_address(TESTNET_P2PKH_ADDRESS, Network::Mainnet).unwrap_err().contains("not a mainnet address"));
+        assert!(validate_address(MAINNET_P2SH_ADDRESS, Network::Testnet).unwrap_err().contains("not a testnet address"));
+        assert!(validate_address(TESTNET_V0, Network::Mainnet).unwrap_err().contains("not a mainnet address"));
+        assert!(validate_address(MAINNET_V0, Network::Regtest).unwrap_err().contains("not a regtest address"));
+        assert!(validate_address(REGTEST_V0, Network::Testnet).unwrap_err().contains("not a testnet address"));
+    }
+
+    #[test]
+    fn infers_the_network_of_the_addresses() {
+        assert_eq!(Network::infer([MAINNET_P2PKH_ADDRESS, MAINNET_V0]), Ok(Network::Mainnet));
+        assert_eq!(Network::infer([TESTNET_P2PKH_ADDRESS, TESTNET_V1]), Ok(Network::Testnet));
+        assert_eq!(Network::infer([TESTNET_P2SH_ADDRESS, REGTEST_V0]), Ok(Network::Regtest));
+        assert!(Network::infer([TESTNET_P2PKH_ADDRESS, MAINNET_V0]).is_err());
+        assert!(Network::infer([]).is_err());
+    }
+
+    #[test]
+    fn parses_network_names() {
+        assert_eq!("testnet3".parse::<Network>(), Ok(Network::Testnet));
+        assert_eq!("MAIN".parse::<Network>(), Ok(Network::Mainnet));
+        assert!("litecoin".parse::<Network>().is_err());
+    }
+}
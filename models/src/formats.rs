@@ -0,0 +1,403 @@
+/*
+models/src/formats.rs
+10/18/26
+
+This file detects the format of an input file and streams its
+transactions, whatever the format. Supported are a listsinceblock
+JSON document, newline-delimited JSON with one transaction per line
+and CSV with a header row named after the listsinceblock fields.
+Each may be compressed with gzip or zstd.
+*/
+use flate2::read::MultiGzDecoder;
+use serde::de::DeserializeOwned;
+use serde_json::{ Map, Value };
+use std::error::Error;
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader, Cursor, Read };
+use std::path::Path;
+use crate::stream::{ ResponseTrailer, stream_from_reader };
+
+// Magic bytes at the start of compressed files
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// Decompressed bytes read ahead to recognize the format
+const FORMAT_SNIFF_LEN: u64 = 4096;
+
+// Top level keys of a listsinceblock response
+const RESPONSE_KEYS: &[&str] = &["transactions", "removed", "lastblock"];
+
+// CSV columns that hold numbers, booleans or ';' separated lists.
+// Every other column is read as text
+const NUMBER_COLUMNS: &[&str] = &["amount", "fee", "confirmations", "blockindex", "blocktime",
+    "blockheight", "vout", "time", "timereceived"];
+const BOOLEAN_COLUMNS: &[&str] = &["involvesWatchonly", "generated", "abandoned", "trusted"];
+const LIST_COLUMNS: &[&str] = &["walletconflicts"];
+
+// Columns of the transaction export of the Bitcoin Core GUI. It has no
+// vout, confirmation count or block, so it cannot stand in for listsinceblock
+const GUI_EXPORT_COLUMNS: &[&str] = &["Confirmed", "Date", "Type", "Label", "Address", "Amount (BTC)", "ID"];
+
+// Supported input formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+// Supported compression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+// Stream the transactions of an input file in any supported format
+pub fn stream_from_file<T, F>(file_path: &str, on_transaction: F)
+-> Result<ResponseTrailer<T>, Box<dyn Error + Send + Sync>>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    stream_from_source(file_path, File::open(file_path)?, on_transaction)
+}
+
+// Stream the transactions of already opened file content, the path only
+// telling the format by its extension. Callers wrapping the reader see
+// exactly the bytes that were parsed
+pub fn stream_from_source<R, T, F>(file_path: &str, source: R, on_transaction: F)
+-> Result<ResponseTrailer<T>, Box<dyn Error + Send + Sync>>
+where
+    R: Read,
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    let (_, format) = detect_by_extension(file_path);
+
+    // Compression is always sniffed, a .json may well be gzip inside
+    let (head, raw) = peek(source, ZSTD_MAGIC.len() as u64)?;
+    let raw = BufReader::new(raw);
+    let decompressed: Box<dyn Read> = match detect_compression(&head) {
+        Compression::None => Box::new(raw),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(raw)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(raw)?),
+    };
+
+    // Sniff the content for the format when the extension did not say
+    let (head, content) = peek(decompressed, FORMAT_SNIFF_LEN)?;
+    let format = format.unwrap_or_else(|| detect_format(&head));
+    let reader = BufReader::new(content);
+
+    match format {
+        InputFormat::Json => stream_from_reader(reader, on_transaction),
+        InputFormat::Ndjson => stream_ndjson(reader, on_transaction),
+        InputFormat::Csv => stream_csv(reader, on_transaction),
+    }
+}
+
+// Read compression and format from extensions like .ndjson.zst,
+// None where the extension does not say. Only the format is taken
+// from it when streaming, the compression is sniffed from the content
+pub fn detect_by_extension(file_path: &str) -> (Option<Compression>, Option<InputFormat>) {
+    let path = Path::new(file_path);
+    let extension = |path: &Path| path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    let (compression, inner) = match extension(path).as_deref() {
+        Some("gz") | Some("gzip") => (Some(Compression::Gzip), path.with_extension("")),
+        Some("zst") | Some("zstd") => (Some(Compression::Zstd), path.with_extension("")),
+        _ => (None, path.to_path_buf()),
+    };
+
+    let format = match extension(&inner).as_deref() {
+        Some("json") => Some(InputFormat::Json),
+        Some("ndjson") | Some("jsonl") => Some(InputFormat::Ndjson),
+        Some("csv") => Some(InputFormat::Csv),
+        _ => None,
+    };
+
+    // A bare compression extension says nothing about what is inside
+    (compression.or(format.map(|_| Compression::None)), format)
+}
+
+// Recognize compression from the first bytes of a file
+pub fn detect_compression(head: &[u8]) -> Compression {
+    if head.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+// Recognize the format from the first bytes of decompressed content.
+// A document opens with a listsinceblock key, a line of NDJSON with a
+// transaction field, and anything that is not an object is CSV
+pub fn detect_format(head: &[u8]) -> InputFormat {
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+
+    let Some(object) = text.strip_prefix('{') else {
+        return InputFormat::Csv;
+    };
+    let first_key = object.trim_start()
+        .strip_prefix('"')
+        .and_then(|key| key.split('"').next());
+
+    match first_key {
+        Some(key) if RESPONSE_KEYS.contains(&key) => InputFormat::Json,
+        Some(_) => InputFormat::Ndjson,
+        None => InputFormat::Json,
+    }
+}
+
+// A reader with bytes read ahead put back in front
+type Peeked<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+// Read up to len bytes ahead, as many reads as it takes or until the end,
+// and return them together with a reader that still yields them first
+fn peek<R: Read>(mut reader: R, len: u64) -> io::Result<(Vec<u8>, Peeked<R>)> {
+    let mut head = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut head)?;
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+// One transaction per line, blank lines are skipped.
+// NDJSON carries no removed entries or lastblock
+fn stream_ndjson<R, T, F>(reader: R, mut on_transaction: F)
+-> Result<ResponseTrailer<T>, Box<dyn Error + Send + Sync>>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let transaction = serde_json::from_str::<T>(&line)
+            .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        on_transaction(transaction)?;
+    }
+    Ok(ResponseTrailer { removed: Vec::new(), lastblock: None })
+}
+
+// One transaction per record, columns named after the listsinceblock fields.
+// Empty cells are treated as absent fields
+fn stream_csv<R, T, F>(reader: R, mut on_transaction: F)
+-> Result<ResponseTrailer<T>, Box<dyn Error + Send + Sync>>
+where
+    R: Read,
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let headers = csv_reader.headers()?.clone();
+    check_csv_headers(&headers)?;
+
+    for (number, record) in csv_reader.records().enumerate() {
+        let record = record?;
+        let mut fields = Map::new();
+        for (column, cell) in headers.iter().zip(record.iter()) {
+            if !cell.is_empty() {
+                fields.insert(column.to_string(), csv_value(column, cell));
+            }
+        }
+        let transaction = serde_json::from_value::<T>(Value::Object(fields))
+            .map_err(|e| format!("record {}: {}", number + 1, e))?;
+        on_transaction(transaction)?;
+    }
+    Ok(ResponseTrailer { removed: Vec::new(), lastblock: None })
+}
+
+// Reject up front a header row that is not named after the listsinceblock
+// fields, rather than quarantining every record for the fields it lacks
+fn check_csv_headers(headers: &csv::StringRecord) -> Result<(), String> {
+    if GUI_EXPORT_COLUMNS.iter().all(|column| headers.iter().any(|header| header == *column)) {
+        return Err(String::from("CSV is a Bitcoin Core GUI transaction export, which has no vout, \
+            confirmations or block columns. Export the listsinceblock fields instead"));
+    }
+    if !headers.iter().any(|header| header == "txid") {
+        return Err(format!("CSV header has no txid column, columns must be named after the listsinceblock fields: {}",
+            headers.iter().collect::<Vec<&str>>().join(",")));
+    }
+    Ok(())
+}
+
+// Convert a CSV cell into the JSON value bitcoind would have emitted.
+// Cells that do not parse are kept as text and left to validation
+fn csv_value(column: &str, cell: &str) -> Value {
+    if NUMBER_COLUMNS.contains(&column) {
+        match cell.trim().parse::<serde_json::Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::String(cell.to_string()),
+        }
+    } else if BOOLEAN_COLUMNS.contains(&column) {
+        match cell.trim().to_ascii_lowercase().as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(cell.to_string()),
+        }
+    } else if LIST_COLUMNS.contains(&column) {
+        Value::Array(cell.split(';')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| Value::String(item.to_string()))
+            .collect())
+    } else {
+        Value::String(cell.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression as Level;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    // A reader handing out one byte per read, like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buffer.first_mut()) {
+                (Some((byte, rest)), Some(slot)) => {
+                    *slot = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0),
+            }
+        }
+    }
+
+    // Write content to a scratch file and return its path
+    fn scratch(name: &str, content: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("kobayashi-maru-formats-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // Count the entries streamed from a file and return its lastblock
+    fn stream(path: &str) -> (usize, Option<String>) {
+        let mut count = 0;
+        let trailer = stream_from_file(path, |_: Value| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+        (count, trailer.lastblock)
+    }
+
+    const RESPONSE: &[u8] = br#"{"transactions": [{"txid": "a"}, {"txid": "b"}], "removed": [], "lastblock": "00ff"}"#;
+    const NDJSON: &[u8] = b"{\"txid\": \"a\"}\n\n{\"txid\": \"b\"}\n{\"txid\": \"c\"}\n";
+
+    #[test]
+    fn detects_by_extension() {
+        assert_eq!(detect_by_extension("a.json"), (Some(Compression::None), Some(InputFormat::Json)));
+        assert_eq!(detect_by_extension("a.csv"), (Some(Compression::None), Some(InputFormat::Csv)));
+        assert_eq!(detect_by_extension("a.ndjson.zst"), (Some(Compression::Zstd), Some(InputFormat::Ndjson)));
+        assert_eq!(detect_by_extension("dir.v2/a.JSONL.GZ"), (Some(Compression::Gzip), Some(InputFormat::Ndjson)));
+        assert_eq!(detect_by_extension("a.gz"), (Some(Compression::Gzip), None));
+        assert_eq!(detect_by_extension("a.txt"), (None, None));
+        assert_eq!(detect_by_extension("deposits"), (None, None));
+    }
+
+    #[test]
+    fn detects_compression_from_magic_bytes() {
+        assert_eq!(detect_compression(&gzip(b"{}")), Compression::Gzip);
+        assert_eq!(detect_compression(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]), Compression::Zstd);
+        assert_eq!(detect_compression(b"{}"), Compression::None);
+        assert_eq!(detect_compression(&[0x1f]), Compression::None);
+        assert_eq!(detect_compression(b""), Compression::None);
+    }
+
+    #[test]
+    fn detects_format_from_content() {
+        assert_eq!(detect_format(b"{\"transactions\": ["), InputFormat::Json);
+        assert_eq!(detect_format("\u{feff} \n {\"lastblock\": \"00\"".as_bytes()), InputFormat::Json);
+        assert_eq!(detect_format(b"{ \"txid\": \"a\" }\n"), InputFormat::Ndjson);
+        assert_eq!(detect_format(b"{"), InputFormat::Json);
+        assert_eq!(detect_format(b"txid,amount\n"), InputFormat::Csv);
+        assert_eq!(detect_format(b""), InputFormat::Csv);
+    }
+
+    #[test]
+    fn converts_csv_cells() {
+        assert_eq!(csv_value("amount", " 1.5 "), serde_json::json!(1.5));
+        assert_eq!(csv_value("confirmations", "-1"), serde_json::json!(-1));
+        assert_eq!(csv_value("amount", "1,5"), serde_json::json!("1,5"));
+        assert_eq!(csv_value("abandoned", "TRUE"), serde_json::json!(true));
+        assert_eq!(csv_value("generated", "false"), serde_json::json!(false));
+        assert_eq!(csv_value("generated", "yes"), serde_json::json!("yes"));
+        assert_eq!(csv_value("walletconflicts", "a; ;b"), serde_json::json!(["a", "b"]));
+        assert_eq!(csv_value("walletconflicts", ""), serde_json::json!([]));
+        assert_eq!(csv_value("label", "42"), serde_json::json!("42"));
+    }
+
+    #[test]
+    fn peek_reads_until_enough_bytes() {
+        let (head, mut reader) = peek(Trickle(b"{\"transactions\": []}"), 8).unwrap();
+        assert_eq!(head, b"{\"transa");
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "{\"transactions\": []}");
+    }
+
+    #[test]
+    fn peek_stops_at_the_end() {
+        let (head, mut reader) = peek(Trickle(b"{}"), 8).unwrap();
+        assert_eq!(head, b"{}");
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"{}");
+    }
+
+    #[test]
+    fn streams_gzip_named_json() {
+        let path = scratch("response.json", &gzip(RESPONSE));
+        assert_eq!(stream(&path), (2, Some(String::from("00ff"))));
+    }
+
+    #[test]
+    fn streams_ndjson_without_extension() {
+        let path = scratch("deposits", &gzip(NDJSON));
+        assert_eq!(stream(&path), (3, None));
+    }
+
+    #[test]
+    fn streams_csv() {
+        let path = scratch("deposits.csv", b"txid,amount,walletconflicts\na,1.5,\nb,2,x;y\n");
+        let mut rows = Vec::new();
+        stream_from_file(&path, |row: Value| {
+            rows.push(row);
+            Ok(())
+        }).unwrap();
+        assert_eq!(rows, vec![
+            serde_json::json!({ "txid": "a", "amount": 1.5 }),
+            serde_json::json!({ "txid": "b", "amount": 2, "walletconflicts": ["x", "y"] }),
+        ]);
+    }
+
+    #[test]
+    fn rejects_csv_not_named_after_listsinceblock_fields() {
+        let reject = |name: &str, content: &[u8]| stream_from_file(&scratch(name, content), |_: Value| Ok(()))
+            .expect_err("the file is rejected").to_string();
+
+        let export = b"\"Confirmed\",\"Date\",\"Type\",\"Label\",\"Address\",\"Amount (BTC)\",\"ID\"\n\
+            \"true\",\"2021-07-30T01:12:28.000\",\"Received with\",\"\",\"mvd6qFeVkqH6MNAS2Y2cLifbdaX5XUkbZJ\",\"1.50000000\",\"a\"\n";
+        assert!(reject("export.csv", export).starts_with("CSV is a Bitcoin Core GUI transaction export"));
+        assert!(reject("columns.csv", b"hash,value\na,1.5\n").starts_with("CSV header has no txid column"));
+    }
+}
@@ -0,0 +1,229 @@
+/*
+models/src/invoice.rs
+10/18/26
+
+This file defines payment requests and how deposits match them.
+An invoice expects an amount at an address until it expires. Every
+valid deposit to the address is matched to the latest invoice created
+before the wallet first saw it, and the deposits paid before it expired
+settle the invoice up to the one paying it in full.
+*/
+use chrono::{ DateTime, Utc };
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use crate::{ Network, timestamp, validate_address };
+
+// Invoice structure. Times are epoch seconds like bitcoind's
+#[derive(Debug, Clone, Deserialize)]
+pub struct Invoice {
+    pub id: String,
+    #[serde(default)]
+    pub customer: Option<String>,
+    pub address: String,
+    pub amount: f64,
+    #[serde(with = "timestamp")]
+    pub created: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub expires: DateTime<Utc>,
+}
+
+// Invoices Array structure
+#[derive(Debug, Deserialize)]
+pub struct InvoicesArray {
+    pub invoices: Vec<Invoice>,
+}
+
+impl InvoicesArray {
+    // Reject invoices that no deposit could ever match
+    pub fn validate(&self, network: Network) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        for invoice in &self.invoices {
+            if !ids.insert(invoice.id.as_str()) {
+                return Err(format!("Invoice {} is listed twice", invoice.id));
+            }
+            validate_address(&invoice.address, network).map_err(|e|
+                format!("Invoice {0} has an invalid address {1}: {2}", invoice.id, invoice.address, e))?;
+            if invoice.amount <= 0.0 {
+                return Err(format!("Invoice {0} has a non-positive amount {1}", invoice.id, invoice.amount));
+            }
+            if invoice.expires <= invoice.created {
+                return Err(format!("Invoice {} expires before it is created", invoice.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+// How a deposit matches its invoice. Deposits before expiry are paid
+// towards it until it is paid in full, and duplicates after that
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaymentMatch {
+    Paid,
+    Late,
+    Duplicate,
+}
+
+impl PaymentMatch {
+    // Match name as returned by get_invoice_payments
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentMatch::Paid => "paid",
+            PaymentMatch::Late => "late",
+            PaymentMatch::Duplicate => "duplicate",
+        }
+    }
+}
+
+impl FromStr for PaymentMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "paid" => Ok(PaymentMatch::Paid),
+            "late" => Ok(PaymentMatch::Late),
+            "duplicate" => Ok(PaymentMatch::Duplicate),
+            _ => Err(format!("Unknown payment match: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PaymentMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Where an invoice stands. The amount paid before expiry is exact, short
+// or over the expected one, late when every payment came after expiry.
+// Unpaid ones are open until they expire
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvoiceStatus {
+    Open,
+    Expired,
+    Exact,
+    Underpaid,
+    Overpaid,
+    Late,
+}
+
+impl InvoiceStatus {
+    // Every status, in the order the report counts them
+    pub const ALL: [InvoiceStatus; 6] = [InvoiceStatus::Open, InvoiceStatus::Expired, InvoiceStatus::Exact,
+        InvoiceStatus::Underpaid, InvoiceStatus::Overpaid, InvoiceStatus::Late];
+
+    // Status name as returned by get_invoice_statuses
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceStatus::Open => "open",
+            InvoiceStatus::Expired => "expired",
+            InvoiceStatus::Exact => "exact",
+            InvoiceStatus::Underpaid => "underpaid",
+            InvoiceStatus::Overpaid => "overpaid",
+            InvoiceStatus::Late => "late",
+        }
+    }
+}
+
+impl FromStr for InvoiceStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "open" => Ok(InvoiceStatus::Open),
+            "expired" => Ok(InvoiceStatus::Expired),
+            "exact" => Ok(InvoiceStatus::Exact),
+            "underpaid" => Ok(InvoiceStatus::Underpaid),
+            "overpaid" => Ok(InvoiceStatus::Overpaid),
+            "late" => Ok(InvoiceStatus::Late),
+            _ => Err(format!("Unknown invoice status: {}, expected open, expired, exact, underpaid, overpaid or late", s)),
+        }
+    }
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// An invoice with where it stands, the amount paid towards it and
+// the count of every payment matched to it, late ones and duplicates included
+#[derive(Debug, Clone)]
+pub struct InvoiceSummary {
+    pub id: String,
+    pub customer: Option<String>,
+    pub address: String,
+    pub amount: f64,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub status: InvoiceStatus,
+    pub paid: f64,
+    pub payments: i32,
+}
+
+// A valid deposit matched to an invoice, paid when the wallet first saw it
+#[derive(Debug, Clone)]
+pub struct InvoicePayment {
+    pub invoice_id: String,
+    pub txid: String,
+    pub vout: i32,
+    pub amount: f64,
+    pub paid_at: DateTime<Utc>,
+    pub kind: PaymentMatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "mvcyJMiAcSXKAEsQxbW9TYZ369rsMG6rVV";
+
+    fn invoices(json: serde_json::Value) -> InvoicesArray {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn invoice(id: &str, address: &str, amount: f64, created: i64, expires: i64) -> serde_json::Value {
+        serde_json::json!({ "id": id, "address": address, "amount": amount, "created": created, "expires": expires })
+    }
+
+    #[test]
+    fn accepts_valid_invoices() {
+        let array = invoices(serde_json::json!({ "invoices": [
+            invoice("INV-1", ADDRESS, 1.5, 1627599000, 1627602600),
+            invoice("INV-2", ADDRESS, 0.1, 1627602600, 1627606200),
+        ] }));
+        assert_eq!(array.validate(Network::Testnet), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invoices_no_deposit_could_match() {
+        let listed_twice = invoices(serde_json::json!({ "invoices": [
+            invoice("INV-1", ADDRESS, 1.5, 1627599000, 1627602600),
+            invoice("INV-1", ADDRESS, 1.5, 1627599000, 1627602600),
+        ] }));
+        assert_eq!(listed_twice.validate(Network::Testnet), Err(String::from("Invoice INV-1 is listed twice")));
+
+        let wrong_network = invoices(serde_json::json!({ "invoices": [invoice("INV-1", ADDRESS, 1.5, 1627599000, 1627602600)] }));
+        assert!(wrong_network.validate(Network::Mainnet).unwrap_err().starts_with("Invoice INV-1 has an invalid address"));
+
+        let nothing_due = invoices(serde_json::json!({ "invoices": [invoice("INV-1", ADDRESS, 0.0, 1627599000, 1627602600)] }));
+        assert!(nothing_due.validate(Network::Testnet).unwrap_err().contains("non-positive amount"));
+
+        let expired = invoices(serde_json::json!({ "invoices": [invoice("INV-1", ADDRESS, 1.5, 1627602600, 1627602600)] }));
+        assert_eq!(expired.validate(Network::Testnet), Err(String::from("Invoice INV-1 expires before it is created")));
+    }
+
+    #[test]
+    fn parses_match_and_status_names() {
+        for kind in [PaymentMatch::Paid, PaymentMatch::Late, PaymentMatch::Duplicate] {
+            assert_eq!(kind.as_str().parse::<PaymentMatch>(), Ok(kind));
+        }
+        for status in InvoiceStatus::ALL {
+            assert_eq!(status.as_str().to_uppercase().parse::<InvoiceStatus>(), Ok(status));
+        }
+        assert!("exact".parse::<PaymentMatch>().is_err());
+        assert!("paid".parse::<InvoiceStatus>().is_err());
+    }
+}
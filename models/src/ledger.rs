@@ -0,0 +1,34 @@
+/*
+models/src/ledger.rs
+10/18/26
+
+This file defines the rows of the customer ledger.
+Every valid deposit is debited to the wallet account and credited
+to the account of its customer, or to suspense when there is none.
+*/
+use chrono::{ DateTime, Utc };
+
+// Balance of a ledger account. Customer and suspense balances are
+// what was credited, the wallet balance what was received
+#[derive(Debug, Clone)]
+pub struct LedgerBalance {
+    pub kind: String,
+    pub name: String,
+    pub balance: f64,
+    pub entries: i32,
+}
+
+// One line of a journal entry. Debits are positive, credits negative.
+// The batch is None for entries posted after a chain tip change
+#[derive(Debug, Clone)]
+pub struct JournalLine {
+    pub entry_id: i32,
+    pub txid: String,
+    pub sequence: i32,
+    pub kind: String,
+    pub batch_id: Option<i32>,
+    pub posted_at: DateTime<Utc>,
+    pub account_kind: String,
+    pub account: String,
+    pub amount: f64,
+}
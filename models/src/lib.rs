@@ -4,54 +4,294 @@ models/src/lib.rs
 
 This file defines common data structures used in the codebase.
 Additionally, there is a generic parser for
-loading a json file into a custom struct
+loading a json file into a custom struct, and a streaming
+reader for deposit dumps too large to load at once, as
+listsinceblock JSON, NDJSON or CSV, optionally gzip or zstd compressed
 */
+use chrono::{ DateTime, Utc };
 use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
 use std::fs::File;
 use std::io::BufReader;
 use std::error::Error;
 
+mod address;
+mod formats;
+mod invoice;
+mod ledger;
+mod lifecycle;
+mod provisional;
+mod reconcile;
+mod report;
+mod stream;
+pub mod timestamp;
+mod withdrawal;
+
+pub use address::{ Network, validate_address };
+pub use formats::{ Compression, InputFormat, stream_from_file, stream_from_source };
+pub use invoice::{ Invoice, InvoicePayment, InvoiceStatus, InvoiceSummary, InvoicesArray, PaymentMatch };
+pub use ledger::{ JournalLine, LedgerBalance };
+pub use lifecycle::{ DepositStatus, DepositTransition };
+pub use provisional::{ DepositNotice, ProvisionalDeposit };
+pub use reconcile::{ AddressReconciliation, BalanceGroup, OutputDiscrepancy, ReconciliationTotals, UnspentOutput,
+    WalletBalances, WalletSnapshot };
+pub use report::{ Bucket, DepositBucket, DepositScope, DepositStatistics, HistogramBin, ReportWindow,
+    UnreferencedAddress, UnreferencedDeposit, UnreferencedFilter, UnreferencedSort };
+pub use stream::{ ResponseTrailer, stream_from_reader };
+pub use withdrawal::{ NetBalance, Withdrawal };
+
 // Transaction structure
+// Fields bitcoind only emits for some entries are optional: unconfirmed
+// transactions have no block, newer versions drop account, and fee,
+// abandoned, generated, comment and to depend on the kind of transaction.
+// Timestamps are accepted in seconds or milliseconds
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Transaction {
-    #[serde(rename = "involvesWatchonly")]
+    #[serde(rename = "involvesWatchonly", default)]
     pub involves_watchonly: bool,
-    pub account: String,
-    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
     pub category: String,
     pub amount: f64,
-    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     pub confirmations: i32,
-    pub blockhash: String,
-    pub blockindex: i32,
-    pub blocktime: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockhash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockindex: Option<i32>,
+    #[serde(default, with = "timestamp::option", skip_serializing_if = "Option::is_none")]
+    pub blocktime: Option<DateTime<Utc>>,
     pub txid: String,
     pub vout: i32,
+    #[serde(default)]
     pub walletconflicts: Vec<String>,
-    pub time: i64,
-    pub timereceived: i64,
+    #[serde(with = "timestamp")]
+    pub time: DateTime<Utc>,
+    #[serde(with = "timestamp")]
+    pub timereceived: DateTime<Utc>,
     #[serde(rename = "bip125-replaceable")]
     pub bip125_replaceable: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockheight: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abandoned: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
 }
 
-// Vector of Transactions
+// A listsinceblock response: the transactions since the requested block,
+// the transactions dropped by a reorg (only with include_removed)
+// and the block to pass as the cursor of the next call.
+// Entries can be held as raw JSON until they have been validated
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Transactions {
-    pub transactions: Vec<Transaction>,
+pub struct Transactions<T = Transaction> {
+    pub transactions: Vec<T>,
+    #[serde(default)]
+    pub removed: Vec<Transaction>,
+    #[serde(default)]
+    pub lastblock: Option<String>,
+}
+
+// Block structure, built from the block fields of a Transaction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub hash: String,
+    pub height: Option<i32>,
+    #[serde(with = "timestamp")]
+    pub time: DateTime<Utc>,
+    pub confirmations: i32,
+}
+
+impl Transaction {
+    // The block this transaction was mined in, None while unconfirmed
+    pub fn block(&self) -> Option<Block> {
+        match (&self.blockhash, self.blocktime) {
+            (Some(hash), Some(time)) => Some(Block {
+                hash: hash.clone(),
+                height: self.blockheight,
+                time,
+                confirmations: self.confirmations,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Stored Transaction structure
+// A transaction as stored, with its current standing: whether it
+// counts as a deposit, whether it is in conflict, which customer it
+// is attributed to and how, its recorded lifecycle status and the
+// ingest batch it came from
+#[derive(Debug)]
+pub struct StoredTransaction {
+    pub transaction: Transaction,
+    pub removed: bool,
+    pub main_chain: Option<bool>,
+    pub confirmations: i32,
+    pub confirmed: bool,
+    pub conflicted: bool,
+    pub customer: Option<String>,
+    pub attribution: Option<String>,
+    pub batch_id: i32,
+    pub batch_source: String,
+    pub ingested_at: DateTime<Utc>,
+    pub lifecycle: Option<(DepositStatus, DateTime<Utc>)>,
+}
+
+impl StoredTransaction {
+    // Why a transaction does or does not count as a deposit
+    pub fn status(&self) -> &'static str {
+        if self.removed {
+            "removed"
+        } else if self.transaction.blockhash.is_none() {
+            "unconfirmed"
+        } else if self.main_chain == Some(false) {
+            "orphaned"
+        } else if self.confirmed {
+            "confirmed"
+        } else if self.conflicted {
+            "conflicted"
+        } else {
+            "pending"
+        }
+    }
+}
+
+// Quarantined Transaction structure
+// An entry rejected by validation, kept as it was received
+// until it is fixed and re-submitted
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QuarantinedTransaction {
+    pub id: i32,
+    pub batch_id: i32,
+    pub raw: serde_json::Value,
+    pub reason: String,
+    pub resolved_batch_id: Option<i32>,
 }
 
 // Known Customer structure
+// Deposits are attributed by the address, withdrawals by the
+// payout addresses the customer is paid out to
 #[derive(Serialize, Deserialize, Debug)]
 pub struct KnownCustomers {
     pub name: String,
     pub address: String,
+    #[serde(default)]
+    pub payout_addresses: Vec<String>,
 }
 
-// Vector of Known Customers
+// Label Rule structure
+// Attributes deposits to addresses of no known customer whose wallet
+// label matches the pattern, where * matches any run of characters
+// and ? a single one. Matching ignores case and covers the whole label
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+impl LabelRule {
+    // The pattern as an SQL LIKE pattern, escaping LIKE wildcards
+    pub fn like_pattern(&self) -> String {
+        let mut like = String::with_capacity(self.pattern.len());
+        for c in self.pattern.chars() {
+            match c {
+                '*' => like.push('%'),
+                '?' => like.push('_'),
+                '%' | '_' | '\\' => {
+                    like.push('\\');
+                    like.push(c);
+                },
+                _ => like.push(c),
+            }
+        }
+        like
+    }
+}
+
+// Vector of Known Customers, with optional label rules
+// that are tried in order after matching by address.
+// Payout label rules do the same for the labels of sends
 #[derive(Serialize, Deserialize, Debug)]
 pub struct KnownCustomersArray {
     pub known_customers: Vec<KnownCustomers>,
+    #[serde(default)]
+    pub label_rules: Vec<LabelRule>,
+    #[serde(default)]
+    pub payout_label_rules: Vec<LabelRule>,
+}
+
+impl KnownCustomersArray {
+    // Check every customer address against the network, naming the
+    // first customer whose address is invalid or already belongs to
+    // another customer, and that every label rule has a pattern.
+    // The database stores an address once, so a duplicate would be
+    // summed twice by the reports. Payout addresses are checked the
+    // same way among themselves
+    pub fn validate(&self, network: Network) -> Result<(), String> {
+        for (position, customer) in self.known_customers.iter().enumerate() {
+            validate_address(&customer.address, network).map_err(|e|
+                format!("Known customer {0} has an invalid address {1}: {2}", customer.name, customer.address, e))?;
+            if let Some(other) = self.known_customers[..position].iter().find(|other| other.address == customer.address) {
+                return Err(format!("Known customer {0} has the address {1} of {2}",
+                    customer.name, customer.address, other.name));
+            }
+        }
+        let mut payouts: Vec<(&str, &str)> = Vec::new();
+        for customer in &self.known_customers {
+            for address in &customer.payout_addresses {
+                validate_address(address, network).map_err(|e|
+                    format!("Known customer {0} has an invalid payout address {1}: {2}", customer.name, address, e))?;
+                if let Some((other, _)) = payouts.iter().find(|(_, payout)| payout == address) {
+                    return Err(format!("Known customer {0} has the payout address {1} of {2}",
+                        customer.name, address, other));
+                }
+                payouts.push((&customer.name, address));
+            }
+        }
+        for rule in self.label_rules.iter().chain(&self.payout_label_rules) {
+            if rule.pattern.trim().is_empty() {
+                return Err(format!("Label rule for {} has an empty pattern", rule.name));
+            }
+        }
+        Ok(())
+    }
+
+    // The network the customer addresses belong to, for when none is configured
+    pub fn infer_network(&self) -> Result<Network, String> {
+        Network::infer(self.known_customers.iter().map(|customer| customer.address.as_str()))
+    }
+
+    // Names payout label rules attribute withdrawals to, in order of first appearance
+    pub fn payout_label_customers(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for rule in &self.payout_label_rules {
+            if !names.contains(&rule.name.as_str()) {
+                names.push(&rule.name);
+            }
+        }
+        names
+    }
+
+    // Names label rules attribute deposits to, in order of first appearance
+    pub fn label_customers(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for rule in &self.label_rules {
+            if !names.contains(&rule.name.as_str()) {
+                names.push(&rule.name);
+            }
+        }
+        names
+    }
 }
 
 // Generic method for parsing a json file into a custom struct
@@ -65,3 +305,151 @@ pub fn from_file<T: DeserializeOwned>(file_path: &str) -> Result<T, Box<dyn Erro
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIRK: &str = "miTHhiX3iFhVnAEecLjybxvV5g8mKYTtnM";
+    const SPOCK: &str = "mvcyJMiAcSXKAEsQxbW9TYZ369rsMG6rVV";
+
+    fn customers(entries: &[(&str, &str)]) -> KnownCustomersArray {
+        KnownCustomersArray {
+            known_customers: entries.iter()
+                .map(|(name, address)| KnownCustomers {
+                    name: name.to_string(),
+                    address: address.to_string(),
+                    payout_addresses: Vec::new(),
+                })
+                .collect(),
+            label_rules: Vec::new(),
+            payout_label_rules: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_distinct_addresses() {
+        let known_customers = customers(&[("James T. Kirk", KIRK), ("Spock", SPOCK)]);
+        assert_eq!(known_customers.validate(Network::Testnet), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_duplicate_address() {
+        let known_customers = customers(&[("James T. Kirk", KIRK), ("Spock", SPOCK), ("Tiberius", KIRK)]);
+        let error = known_customers.validate(Network::Testnet).unwrap_err();
+        assert!(error.contains("Tiberius") && error.contains("James T. Kirk"), "{}", error);
+    }
+
+    #[test]
+    fn validate_checks_the_network() {
+        let known_customers = customers(&[("James T. Kirk", KIRK)]);
+        assert!(known_customers.validate(Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn validate_checks_payout_addresses() {
+        let mut known_customers = customers(&[("James T. Kirk", KIRK), ("Spock", SPOCK)]);
+        known_customers.known_customers[0].payout_addresses = vec![SPOCK.to_string()];
+        assert_eq!(known_customers.validate(Network::Testnet), Ok(()));
+
+        known_customers.known_customers[1].payout_addresses = vec![SPOCK.to_string()];
+        let error = known_customers.validate(Network::Testnet).unwrap_err();
+        assert!(error.contains("payout address") && error.contains("James T. Kirk"), "{}", error);
+
+        known_customers.known_customers[1].payout_addresses = vec![String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")];
+        assert!(known_customers.validate(Network::Testnet).unwrap_err().contains("invalid payout address"));
+    }
+
+    #[test]
+    fn validate_checks_payout_label_rules() {
+        let mut known_customers = customers(&[("James T. Kirk", KIRK)]);
+        let rule = |name: &str, pattern: &str| LabelRule { name: name.to_string(), pattern: pattern.to_string() };
+        known_customers.payout_label_rules = vec![rule("Q", "q-*"), rule("James T. Kirk", "kirk*"), rule("Q", "continuum")];
+        assert_eq!(known_customers.validate(Network::Testnet), Ok(()));
+        assert_eq!(known_customers.payout_label_customers(), vec!["Q", "James T. Kirk"]);
+
+        known_customers.payout_label_rules.push(rule("Q", " "));
+        assert!(known_customers.validate(Network::Testnet).unwrap_err().contains("empty pattern"));
+    }
+
+    #[test]
+    fn shipped_known_customers_are_valid() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../known-customers.json");
+        let known_customers = from_file::<KnownCustomersArray>(path).unwrap();
+        assert_eq!(known_customers.validate(Network::Testnet), Ok(()));
+        assert_eq!(known_customers.infer_network(), Ok(Network::Testnet));
+    }
+
+    fn fixture(name: &str) -> Transactions {
+        let path = format!("{}/../fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        from_file::<Transactions>(&path).unwrap()
+    }
+
+    #[test]
+    fn parses_bitcoind_0_17_fixture() {
+        let response = fixture("bitcoind-0.17-listsinceblock.json");
+        assert_eq!(response.transactions.len(), 3);
+        assert!(response.removed.is_empty());
+        assert!(response.lastblock.is_some());
+
+        let confirmed = &response.transactions[0];
+        assert_eq!(confirmed.account.as_deref(), Some(""));
+        assert!(confirmed.blockhash.is_some() && confirmed.blocktime.is_some());
+        assert_eq!(confirmed.blockheight, None);
+
+        // Unconfirmed entries carry no block fields
+        let unconfirmed = &response.transactions[1];
+        assert_eq!(unconfirmed.confirmations, 0);
+        assert_eq!((&unconfirmed.blockhash, unconfirmed.blockindex, unconfirmed.blocktime), (&None, None, None));
+        assert!(unconfirmed.block().is_none());
+
+        let send = &response.transactions[2];
+        assert_eq!(send.category, "send");
+        assert_eq!(send.fee, Some(-0.0000226));
+        assert_eq!(send.abandoned, Some(true));
+        assert_eq!(send.comment.as_deref(), Some("refund"));
+        assert_eq!(send.to.as_deref(), Some("Jonathan Archer"));
+        assert_eq!(send.blockhash, None);
+    }
+
+    #[test]
+    fn parses_bitcoind_0_21_fixture() {
+        let response = fixture("bitcoind-0.21-listsinceblock.json");
+        assert_eq!(response.transactions.len(), 4);
+
+        // Dropped fields default
+        assert!(response.transactions.iter().all(|transaction| transaction.account.is_none()));
+
+        let receive = &response.transactions[0];
+        assert_eq!(receive.blockheight, Some(2399994));
+        assert_eq!(receive.generated, None);
+
+        let immature = &response.transactions[1];
+        assert_eq!((immature.category.as_str(), immature.generated), ("immature", Some(true)));
+        let generate = &response.transactions[2];
+        assert_eq!((generate.category.as_str(), generate.generated), ("generate", Some(true)));
+
+        let send = &response.transactions[3];
+        assert_eq!(send.fee, Some(-0.0000141));
+        assert_eq!(send.abandoned, Some(false));
+        assert_eq!(send.to, None);
+    }
+
+    #[test]
+    fn parses_bitcoind_24_fixture() {
+        let response = fixture("bitcoind-24.0-listsinceblock.json");
+        assert_eq!(response.transactions.len(), 2);
+        assert_eq!(response.removed.len(), 1);
+
+        let unconfirmed = &response.transactions[1];
+        assert_eq!((unconfirmed.confirmations, &unconfirmed.blockhash), (0, &None));
+
+        // Removed entries keep the block they were disconnected from
+        let removed = &response.removed[0];
+        assert_eq!(removed.confirmations, -1);
+        assert_eq!(removed.blockheight, Some(2399999));
+        assert!(removed.blockhash.is_some());
+        assert!(response.transactions.iter().chain(&response.removed)
+            .all(|transaction| transaction.fee.is_none() && transaction.abandoned.is_none()));
+    }
+}
@@ -0,0 +1,66 @@
+/*
+models/src/lifecycle.rs
+10/18/26
+
+This file defines the lifecycle of a deposit.
+A deposit is seen in the mempool, pending until it has 6 confirmations,
+confirmed, and credited once the ledger posts it. A reorg or conflict
+reverses it, and a later reorg can bring it back.
+*/
+use chrono::{ DateTime, Utc };
+use std::fmt;
+use std::str::FromStr;
+
+// Where a deposit stands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepositStatus {
+    Seen,
+    Pending,
+    Confirmed,
+    Credited,
+    Reversed,
+}
+
+impl DepositStatus {
+    // Status name as stored in deposit_states
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DepositStatus::Seen => "seen",
+            DepositStatus::Pending => "pending",
+            DepositStatus::Confirmed => "confirmed",
+            DepositStatus::Credited => "credited",
+            DepositStatus::Reversed => "reversed",
+        }
+    }
+}
+
+impl FromStr for DepositStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "seen" => Ok(DepositStatus::Seen),
+            "pending" => Ok(DepositStatus::Pending),
+            "confirmed" => Ok(DepositStatus::Confirmed),
+            "credited" => Ok(DepositStatus::Credited),
+            "reversed" => Ok(DepositStatus::Reversed),
+            _ => Err(format!("Unknown deposit status: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DepositStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// A recorded status change. The first one of a deposit comes from None,
+// and the batch is None for changes found after a chain tip change
+#[derive(Debug, Clone)]
+pub struct DepositTransition {
+    pub from: Option<DepositStatus>,
+    pub to: DepositStatus,
+    pub batch_id: Option<i32>,
+    pub at: DateTime<Utc>,
+}
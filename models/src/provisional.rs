@@ -0,0 +1,48 @@
+/*
+models/src/provisional.rs
+10/18/26
+
+This file defines provisional deposits and the notices about them.
+A provisional deposit has fewer than 6 confirmations, so it is not
+creditable yet and can still be replaced or dropped. Notices are the
+event stream customers are told about provisional deposits through.
+*/
+use chrono::{ DateTime, Utc };
+use serde::Serialize;
+use crate::timestamp;
+
+// A deposit below the confirmation threshold
+#[derive(Debug, Clone)]
+pub struct ProvisionalDeposit {
+    pub txid: String,
+    pub vout: i32,
+    pub address: Option<String>,
+    pub customer: Option<String>,
+    pub amount: f64,
+    pub confirmations: i32,
+    pub replaceable: String,
+    pub first_seen: DateTime<Utc>,
+    pub estimated_maturity: DateTime<Utc>,
+}
+
+// One event of the provisional deposit stream: provisional when a deposit
+// is seen or its confirmations change, matured or dropped when it stops
+// being provisional. Timestamps are written as epoch seconds
+#[derive(Debug, Clone, Serialize)]
+pub struct DepositNotice {
+    pub id: i32,
+    pub kind: String,
+    pub txid: String,
+    pub vout: i32,
+    pub address: Option<String>,
+    pub customer: Option<String>,
+    pub amount: f64,
+    pub confirmations: i32,
+    #[serde(rename = "bip125-replaceable")]
+    pub replaceable: String,
+    #[serde(with = "timestamp::option")]
+    pub estimated_maturity: Option<DateTime<Utc>>,
+    pub batch_id: Option<i32>,
+    #[serde(with = "timestamp")]
+    pub created_at: DateTime<Utc>,
+}
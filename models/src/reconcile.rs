@@ -0,0 +1,116 @@
+/*
+models/src/reconcile.rs
+10/18/26
+
+This file defines wallet snapshots and the rows of a reconciliation.
+A snapshot is what bitcoind's listunspent and getbalances report,
+either read from files or fetched over JSON-RPC.
+*/
+use serde::Deserialize;
+
+// One entry of listunspent. Only the fields needed to match
+// an output to a stored deposit are kept
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnspentOutput {
+    pub txid: String,
+    pub vout: i32,
+    pub address: Option<String>,
+    pub amount: f64,
+    pub confirmations: i32,
+}
+
+// One group of balances reported by getbalances
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BalanceGroup {
+    pub trusted: f64,
+    pub untrusted_pending: f64,
+    #[serde(default)]
+    pub immature: f64,
+}
+
+impl BalanceGroup {
+    // What the group holds, whatever its confirmations, except coinbase
+    // outputs still immature, which the engine does not count as deposits
+    pub fn total(&self) -> f64 {
+        self.trusted + self.untrusted_pending
+    }
+}
+
+// Result of getbalances. Watch-only balances are only
+// reported by wallets holding watch-only addresses
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletBalances {
+    pub mine: BalanceGroup,
+    #[serde(default)]
+    pub watchonly: Option<BalanceGroup>,
+}
+
+impl WalletBalances {
+    // What the wallet holds, including watch-only addresses
+    // since those are ingested as deposits too
+    pub fn total(&self) -> f64 {
+        self.mine.total() + self.watchonly.as_ref().map_or(0.0, BalanceGroup::total)
+    }
+}
+
+// What a wallet reported holding and where the report came from
+#[derive(Debug, Clone)]
+pub struct WalletSnapshot {
+    pub source: String,
+    pub unspent: Vec<UnspentOutput>,
+    pub balances: Option<WalletBalances>,
+}
+
+// What the engine and the wallet hold overall. The wallet balance
+// is the unspent sum when the snapshot has no getbalances total
+#[derive(Debug, Clone)]
+pub struct ReconciliationTotals {
+    pub deposited: f64,
+    pub withdrawn: f64,
+    pub fees: f64,
+    pub engine_balance: f64,
+    pub unspent: f64,
+    pub wallet_balance: f64,
+}
+
+// Standing deposits against unspent outputs of one address
+#[derive(Debug, Clone)]
+pub struct AddressReconciliation {
+    pub address: Option<String>,
+    pub customer: Option<String>,
+    pub engine_count: i32,
+    pub engine_amount: f64,
+    pub wallet_count: i32,
+    pub wallet_amount: f64,
+}
+
+// An output responsible for a difference. The kind is not_in_wallet,
+// not_in_engine, change or mismatch, and the side missing it has no amount
+#[derive(Debug, Clone)]
+pub struct OutputDiscrepancy {
+    pub txid: String,
+    pub vout: i32,
+    pub address: Option<String>,
+    pub kind: String,
+    pub engine_amount: Option<f64>,
+    pub wallet_amount: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wallet_total_leaves_immature_coinbase_out() {
+        let balances: WalletBalances = serde_json::from_value(serde_json::json!({
+            "mine": { "trusted": 1.5, "untrusted_pending": 0.25, "immature": 50.0 },
+            "watchonly": { "trusted": 2.0, "untrusted_pending": 0.0, "immature": 12.5 }
+        })).unwrap();
+        assert_eq!(balances.total(), 3.75);
+
+        let without_watchonly: WalletBalances = serde_json::from_value(serde_json::json!({
+            "mine": { "trusted": 1.0, "untrusted_pending": 0.0 }
+        })).unwrap();
+        assert_eq!(without_watchonly.total(), 1.0);
+    }
+}
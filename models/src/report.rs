@@ -0,0 +1,259 @@
+/*
+models/src/report.rs
+10/18/26
+
+This file defines the parameters and rows of time-windowed reports.
+Deposits are placed in time by the block that confirmed them.
+*/
+use chrono::{ DateTime, Utc };
+use std::fmt;
+use std::str::FromStr;
+
+// Granularity of a bucketed report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bucket {
+    Day,
+    Week,
+    Month,
+}
+
+impl Bucket {
+    // Field name understood by postgres date_trunc
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+            Bucket::Month => "month",
+        }
+    }
+
+    // How the start of a bucket is shown in a report
+    pub fn label(&self, start: &DateTime<Utc>) -> String {
+        match self {
+            Bucket::Day => start.format("%Y-%m-%d").to_string(),
+            Bucket::Week => start.format("week of %Y-%m-%d").to_string(),
+            Bucket::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            "month" => Ok(Bucket::Month),
+            _ => Err(format!("Unknown bucket: {}, expected day, week or month", s)),
+        }
+    }
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Time range and bucketing of a report. The range includes from
+// and excludes to, and an open end is unbounded, so a report with
+// only to gives the totals as of that point in time
+#[derive(Debug, Clone, Default)]
+pub struct ReportWindow {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub bucket: Option<Bucket>,
+}
+
+impl ReportWindow {
+    // A window without bounds or buckets covers every deposit ever made
+    pub fn is_lifetime(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.bucket.is_none()
+    }
+}
+
+impl fmt::Display for ReportWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => write!(f, "from {} to {}", from.to_rfc3339(), to.to_rfc3339())?,
+            (Some(from), None) => write!(f, "from {}", from.to_rfc3339())?,
+            (None, Some(to)) => write!(f, "as of {}", to.to_rfc3339())?,
+            (None, None) => write!(f, "all time")?,
+        }
+        if let Some(bucket) = &self.bucket {
+            write!(f, " by {}", bucket)?;
+        }
+        Ok(())
+    }
+}
+
+// Deposits falling into one bucket of a report.
+// The start is None when the report is not bucketed
+#[derive(Debug, Clone)]
+pub struct DepositBucket {
+    pub start: Option<DateTime<Utc>>,
+    pub count: i32,
+    pub amount: f64,
+}
+
+// Which deposits a statistics report covers
+#[derive(Debug, Clone)]
+pub enum DepositScope {
+    // Every confirmed deposit
+    All,
+    // Deposits to one address
+    Address(String),
+    // Deposits to addresses of no known customer that label rules attribute to the name
+    Label(String),
+    // Deposits to addresses of no known customer that no label rule attributes
+    Unreferenced,
+}
+
+// Summary statistics of deposit amounts.
+// Everything but the count is None when there are no deposits,
+// and the standard deviation also needs at least two
+#[derive(Debug, Clone)]
+pub struct DepositStatistics {
+    pub count: i32,
+    pub sum: Option<f64>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+    pub stddev: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// One bin of a deposit size histogram
+#[derive(Debug, Clone)]
+pub struct HistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: i32,
+}
+
+// Order of the unreferenced deposit breakdown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnreferencedSort {
+    Address,
+    Amount,
+    Count,
+    First,
+    Last,
+}
+
+impl UnreferencedSort {
+    // Sort key understood by get_unreferenced_deposits_by_address
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnreferencedSort::Address => "address",
+            UnreferencedSort::Amount => "amount",
+            UnreferencedSort::Count => "count",
+            UnreferencedSort::First => "first",
+            UnreferencedSort::Last => "last",
+        }
+    }
+}
+
+impl FromStr for UnreferencedSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "address" => Ok(UnreferencedSort::Address),
+            "amount" => Ok(UnreferencedSort::Amount),
+            "count" => Ok(UnreferencedSort::Count),
+            "first" => Ok(UnreferencedSort::First),
+            "last" => Ok(UnreferencedSort::Last),
+            _ => Err(format!("Unknown sort: {}, expected address, amount, count, first or last", s)),
+        }
+    }
+}
+
+impl fmt::Display for UnreferencedSort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Which unreferenced deposits to list and in what order.
+// The label matches any label containing it, ignoring case, and
+// the amount bounds apply to single deposits and include the bound
+#[derive(Debug, Clone)]
+pub struct UnreferencedFilter {
+    pub address: Option<String>,
+    pub label: Option<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub sort: UnreferencedSort,
+    pub descending: bool,
+}
+
+// Unreferenced deposits to one address, with when it was first
+// and last paid and every label it was paid under
+#[derive(Debug, Clone)]
+pub struct UnreferencedAddress {
+    pub address: String,
+    pub count: i32,
+    pub amount: f64,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    pub labels: Vec<String>,
+}
+
+// A single deposit to an address of no known customer
+#[derive(Debug, Clone)]
+pub struct UnreferencedDeposit {
+    pub txid: String,
+    pub vout: i32,
+    pub address: String,
+    pub amount: f64,
+    pub blocktime: DateTime<Utc>,
+    pub label: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp;
+
+    #[test]
+    fn parses_buckets_ignoring_case() {
+        assert_eq!("day".parse::<Bucket>(), Ok(Bucket::Day));
+        assert_eq!("Week".parse::<Bucket>(), Ok(Bucket::Week));
+        assert_eq!("MONTH".parse::<Bucket>(), Ok(Bucket::Month));
+        assert!("year".parse::<Bucket>().unwrap_err().contains("expected day, week or month"));
+    }
+
+    #[test]
+    fn buckets_round_trip_through_their_name() {
+        for bucket in [Bucket::Day, Bucket::Week, Bucket::Month] {
+            assert_eq!(bucket.to_string().parse::<Bucket>(), Ok(bucket));
+        }
+    }
+
+    #[test]
+    fn labels_bucket_starts() {
+        let start = timestamp::parse("2021-07-26").unwrap();
+        assert_eq!(Bucket::Day.label(&start), "2021-07-26");
+        assert_eq!(Bucket::Week.label(&start), "week of 2021-07-26");
+        assert_eq!(Bucket::Month.label(&start), "2021-07");
+    }
+
+    #[test]
+    fn describes_windows() {
+        let from = timestamp::parse("2021-07-01").ok();
+        let to = timestamp::parse("2021-08-01").ok();
+        let window = |from, to, bucket| ReportWindow { from, to, bucket };
+
+        assert!(ReportWindow::default().is_lifetime());
+        assert!(!window(None, None, Some(Bucket::Day)).is_lifetime());
+        assert_eq!(ReportWindow::default().to_string(), "all time");
+        assert_eq!(window(None, to, None).to_string(), "as of 2021-08-01T00:00:00+00:00");
+        assert_eq!(window(from, None, Some(Bucket::Week)).to_string(), "from 2021-07-01T00:00:00+00:00 by week");
+        assert_eq!(window(from, to, None).to_string(),
+            "from 2021-07-01T00:00:00+00:00 to 2021-08-01T00:00:00+00:00");
+    }
+}
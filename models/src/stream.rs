@@ -0,0 +1,117 @@
+/*
+models/src/stream.rs
+10/18/26
+
+This file defines a streaming reader for listsinceblock dumps.
+Entries of the transactions array are handed out one at a time
+instead of collecting the whole array, so memory stays flat no
+matter how large the file is.
+*/
+use serde::de::{ self, DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor };
+use serde::Deserializer;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+
+// Everything in a listsinceblock response besides the transactions array
+#[derive(Debug)]
+pub struct ResponseTrailer<T> {
+    pub removed: Vec<T>,
+    pub lastblock: Option<String>,
+}
+
+// Stream the transactions of a listsinceblock response, calling
+// on_transaction for each entry as soon as it has been parsed.
+// An error from on_transaction stops the stream and is returned
+pub fn stream_from_reader<R, T, F>(reader: R, mut on_transaction: F)
+-> Result<ResponseTrailer<T>, Box<dyn Error + Send + Sync>>
+where
+    R: Read,
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let visitor = ResponseVisitor { on_transaction: &mut on_transaction, marker: PhantomData };
+    let trailer = deserializer.deserialize_map(visitor)?;
+    deserializer.end()?;
+    Ok(trailer)
+}
+
+// Walks the top level object of the response
+struct ResponseVisitor<'a, T, F> {
+    on_transaction: &'a mut F,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T, F> Visitor<'de> for ResponseVisitor<'_, T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    type Value = ResponseTrailer<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a listsinceblock response object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut removed = Vec::new();
+        let mut lastblock = None;
+        let mut seen_transactions = false;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "transactions" => {
+                    map.next_value_seed(TransactionsSeed { on_transaction: &mut *self.on_transaction, marker: PhantomData })?;
+                    seen_transactions = true;
+                },
+                "removed" => removed = map.next_value()?,
+                "lastblock" => lastblock = map.next_value()?,
+                _ => { map.next_value::<IgnoredAny>()?; },
+            }
+        }
+
+        if !seen_transactions {
+            return Err(de::Error::missing_field("transactions"));
+        }
+        Ok(ResponseTrailer { removed, lastblock })
+    }
+}
+
+// Walks the transactions array, one element at a time
+struct TransactionsSeed<'a, T, F> {
+    on_transaction: &'a mut F,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T, F> DeserializeSeed<'de> for TransactionsSeed<'_, T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T, F> Visitor<'de> for TransactionsSeed<'_, T, F>
+where
+    T: DeserializeOwned,
+    F: FnMut(T) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of transactions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(transaction) = seq.next_element::<T>()? {
+            (self.on_transaction)(transaction).map_err(de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+/*
+models/src/timestamp.rs
+10/18/26
+
+This file defines how epoch timestamps are read and written.
+bitcoind emits seconds, but some exports carry milliseconds, so
+the unit is detected from the magnitude of the value. Timestamps
+are always written back as seconds.
+*/
+use chrono::{ DateTime, NaiveDate, NaiveTime, Utc };
+use serde::{ Deserialize, Deserializer, Serializer };
+use serde::de::Error;
+
+// Values at or above this are milliseconds. As seconds they would
+// fall after the year 5000, as milliseconds before 1974
+const MILLISECONDS_THRESHOLD: i64 = 100_000_000_000;
+
+// Convert an epoch value in seconds or milliseconds
+pub fn from_epoch(value: i64) -> Option<DateTime<Utc>> {
+    if value.unsigned_abs() >= MILLISECONDS_THRESHOLD.unsigned_abs() {
+        DateTime::from_timestamp_millis(value)
+    } else {
+        DateTime::from_timestamp(value, 0)
+    }
+}
+
+// Parse a point in time given by a user, either an RFC 3339
+// timestamp or a date, which is taken as midnight UTC
+pub fn parse(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| format!("Invalid time {}, expected YYYY-MM-DD or an RFC 3339 timestamp", value))
+}
+
+// Read an epoch timestamp in seconds or milliseconds
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = i64::deserialize(deserializer)?;
+    from_epoch(value).ok_or_else(|| D::Error::custom(format!("timestamp out of range: {}", value)))
+}
+
+// Write a timestamp as epoch seconds, the way bitcoind does
+pub fn serialize<S: Serializer>(timestamp: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(timestamp.timestamp())
+}
+
+// The same for timestamps bitcoind only emits for some entries
+pub mod option {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        match Option::<i64>::deserialize(deserializer)? {
+            Some(value) => from_epoch(value).map(Some)
+                .ok_or_else(|| D::Error::custom(format!("timestamp out of range: {}", value))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn serialize<S: Serializer>(timestamp: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match timestamp {
+            Some(timestamp) => serializer.serialize_some(&timestamp.timestamp()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{ Deserialize, Serialize };
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Times {
+        #[serde(with = "super")]
+        time: DateTime<Utc>,
+        #[serde(with = "super::option")]
+        blocktime: Option<DateTime<Utc>>,
+    }
+
+    fn at(seconds: i64, millis: u32) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, millis * 1_000_000).unwrap()
+    }
+
+    #[test]
+    fn reads_seconds_below_the_threshold() {
+        assert_eq!(from_epoch(0), Some(at(0, 0)));
+        assert_eq!(from_epoch(1627633337), Some(at(1627633337, 0)));
+        assert_eq!(from_epoch(MILLISECONDS_THRESHOLD - 1), Some(at(MILLISECONDS_THRESHOLD - 1, 0)));
+    }
+
+    #[test]
+    fn reads_milliseconds_from_the_threshold() {
+        assert_eq!(from_epoch(MILLISECONDS_THRESHOLD), Some(at(100_000_000, 0)));
+        assert_eq!(from_epoch(1627633337048), Some(at(1627633337, 48)));
+    }
+
+    #[test]
+    fn reads_negative_values_by_magnitude() {
+        assert_eq!(from_epoch(-1), Some(at(-1, 0)));
+        assert_eq!(from_epoch(-MILLISECONDS_THRESHOLD), Some(at(-100_000_000, 0)));
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        assert_eq!(from_epoch(i64::MAX), None);
+        let error = serde_json::from_value::<Times>(json!({ "time": i64::MAX, "blocktime": null })).unwrap_err();
+        assert!(error.to_string().contains("timestamp out of range"), "{}", error);
+        let error = serde_json::from_value::<Times>(json!({ "time": 0, "blocktime": i64::MIN })).unwrap_err();
+        assert!(error.to_string().contains("timestamp out of range"), "{}", error);
+    }
+
+    #[test]
+    fn writes_seconds_and_reads_them_back() {
+        let times: Times = serde_json::from_value(json!({ "time": 1627633337048_i64, "blocktime": 1627633348 })).unwrap();
+        assert_eq!(times, Times { time: at(1627633337, 48), blocktime: Some(at(1627633348, 0)) });
+
+        let written = serde_json::to_value(&times).unwrap();
+        assert_eq!(written, json!({ "time": 1627633337, "blocktime": 1627633348 }));
+        let read: Times = serde_json::from_value(written).unwrap();
+        assert_eq!(read, Times { time: at(1627633337, 0), blocktime: Some(at(1627633348, 0)) });
+    }
+
+    #[test]
+    fn round_trips_missing_optional_timestamps() {
+        let times = Times { time: at(1627633337, 0), blocktime: None };
+        let written = serde_json::to_value(&times).unwrap();
+        assert_eq!(written, json!({ "time": 1627633337, "blocktime": null }));
+        assert_eq!(serde_json::from_value::<Times>(written).unwrap(), times);
+    }
+
+    #[test]
+    fn parses_dates_as_midnight_utc() {
+        assert_eq!(parse("2021-07-30"), Ok(at(1627603200, 0)));
+        assert_eq!(parse("2024-02-29"), Ok(at(1709164800, 0)));
+    }
+
+    #[test]
+    fn parses_rfc3339_timestamps_in_any_offset() {
+        assert_eq!(parse("2021-07-30T12:00:00Z"), Ok(at(1627646400, 0)));
+        assert_eq!(parse("2021-07-30T14:00:00+02:00"), Ok(at(1627646400, 0)));
+        assert_eq!(parse("2021-07-30T12:00:00.048Z"), Ok(at(1627646400, 48)));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        for value in ["2021-02-30", "30/07/2021", "2021-07-30 12:00", "1627646400", ""] {
+            assert!(parse(value).unwrap_err().contains("expected YYYY-MM-DD"), "{}", value);
+        }
+    }
+}
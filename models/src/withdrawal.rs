@@ -0,0 +1,36 @@
+/*
+models/src/withdrawal.rs
+10/18/26
+
+This file defines withdrawals and the net customer balance.
+Sends out of the wallet are withdrawals, attributed to customers
+the same way deposits are. Amounts and fees are positive.
+*/
+use chrono::{ DateTime, Utc };
+
+// A send out of the wallet that still stands.
+// The customer is None when it is attributed to no customer
+#[derive(Debug, Clone)]
+pub struct Withdrawal {
+    pub txid: String,
+    pub vout: i32,
+    pub address: Option<String>,
+    pub customer: Option<String>,
+    pub amount: f64,
+    pub fee: f64,
+    pub confirmations: i32,
+    pub sent_at: DateTime<Utc>,
+}
+
+// Valid deposits less withdrawals and fees of one customer,
+// or of everything attributed to no customer when None
+#[derive(Debug, Clone)]
+pub struct NetBalance {
+    pub customer: Option<String>,
+    pub deposits: i32,
+    pub deposited: f64,
+    pub withdrawals: i32,
+    pub withdrawn: f64,
+    pub fees: f64,
+    pub net: f64,
+}
@@ -0,0 +1,39 @@
+/*
+sources/src/bin/mock_bitcoind.rs
+10/18/26
+
+This file is the entrypoint for the mock bitcoind server.
+It replays the given listsinceblock dumps over JSON-RPC.
+
+Usage: mock-bitcoind <listen address> <file>...
+Set MOCK_RPC_USER and MOCK_RPC_PASSWORD to require basic auth.
+*/
+use serde_json::Value;
+use sources::mock::MockBitcoind;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = env::args().skip(1);
+    let address = args.next().ok_or("Usage: mock-bitcoind <listen address> <file>...")?;
+
+    // Load every dump up front, in chain order
+    let mut responses = Vec::new();
+    for file in args {
+        let reader = BufReader::new(File::open(&file)?);
+        let response: Value = serde_json::from_reader(reader)?;
+        responses.push(response);
+    }
+
+    let credentials = match (env::var("MOCK_RPC_USER"), env::var("MOCK_RPC_PASSWORD")) {
+        (Ok(user), Ok(password)) => Some((user, password)),
+        _ => None,
+    };
+
+    let listener = TcpListener::bind(&address).await?;
+    println!("Mock bitcoind replaying {} responses on {}", responses.len(), address);
+    MockBitcoind::new(responses, credentials).serve(listener).await
+}
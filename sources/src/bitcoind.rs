@@ -0,0 +1,139 @@
+/*
+sources/src/bitcoind.rs
+10/18/26
+
+This file defines a deposit source that polls bitcoind's
+listsinceblock over JSON-RPC, authenticating with either
+the .cookie file or rpcuser/rpcpassword. The same connection
+takes the wallet snapshots reconciliation compares against.
+*/
+use async_trait::async_trait;
+use config::RpcAuth;
+use models::{ Transactions, UnspentOutput, WalletBalances, WalletSnapshot };
+use serde::Deserialize;
+use serde_json::{ json, Value };
+use sha2::{ Digest, Sha256 };
+use std::error::Error;
+use std::fs;
+use crate::{ Batch, DepositSource };
+
+// JSON-RPC response envelope
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+// JSON-RPC error object
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+// This struct defines the bitcoind source
+pub struct BitcoindRpcSource {
+    url: String,
+    credentials: Option<(String, String)>,
+    client: reqwest::Client,
+    polled: bool,
+}
+
+impl BitcoindRpcSource {
+    // Source constructor, the cookie file is read once up front
+    pub fn new(url: &str, auth: Option<&RpcAuth>) -> Result<Self, Box<dyn Error>> {
+        let credentials = match auth {
+            Some(RpcAuth::Cookie(path)) => {
+                let cookie = fs::read_to_string(path)?;
+                let (user, password) = cookie.trim().split_once(':')
+                    .ok_or("Malformed bitcoind cookie file")?;
+                Some((user.to_string(), password.to_string()))
+            },
+            Some(RpcAuth::UserPass(user, password)) => Some((user.clone(), password.clone())),
+            None => None,
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            credentials,
+            client: reqwest::Client::new(),
+            polled: false,
+        })
+    }
+
+    // Issue a single JSON-RPC call and return its result
+    async fn call(&self, method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+        let (result, _) = self.call_hashed(method, params).await?;
+        Ok(result)
+    }
+
+    // Issue a single JSON-RPC call and return its result together with
+    // the hex encoded SHA-256 of the response body as received
+    async fn call_hashed(&self, method: &str, params: Value) -> Result<(Value, String), Box<dyn Error>> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "kobayashi-maru",
+            "method": method,
+            "params": params,
+        });
+
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        // bitcoind reports RPC errors with a non 200 status and a JSON body
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        let sha256 = format!("{:x}", Sha256::digest(&body));
+        let response: RpcResponse = match serde_json::from_slice(&body) {
+            Ok(response) => response,
+            Err(_) => return Err(Box::from(format!("bitcoind returned HTTP {}", status))),
+        };
+
+        if let Some(error) = response.error {
+            return Err(Box::from(format!("bitcoind error {}: {}", error.code, error.message)));
+        }
+        let result = response.result.ok_or("bitcoind returned no result")?;
+        Ok((result, sha256))
+    }
+
+    // Take a snapshot of what the wallet holds, every unspent output
+    // including unconfirmed ones, and the getbalances total
+    pub async fn wallet_snapshot(&self) -> Result<WalletSnapshot, Box<dyn Error>> {
+        let unspent: Vec<UnspentOutput> = serde_json::from_value(self.call("listunspent", json!([0])).await?)?;
+        let balances: WalletBalances = serde_json::from_value(self.call("getbalances", json!([])).await?)?;
+        Ok(WalletSnapshot { source: self.name(), unspent, balances: Some(balances) })
+    }
+}
+
+// Bitcoind implementation of the Deposit Source trait
+#[async_trait]
+impl DepositSource for BitcoindRpcSource {
+    fn name(&self) -> String {
+        format!("bitcoind {}", self.url)
+    }
+
+    // One listsinceblock call per poll. Unconfirmed transactions are returned
+    // on every call, so the source reports itself caught up after each response.
+    // The cursor moves to the tip, so deposits already listed are not listed
+    // again as they confirm. The block count taken on every poll keeps the
+    // chain tip moving, and their confirmations with it, while nothing new arrives
+    async fn next_batch(&mut self, lastblock: Option<&str>) -> Result<Option<Batch>, Box<dyn Error>> {
+        if self.polled {
+            self.polled = false;
+            return Ok(None);
+        }
+
+        let tip: i32 = serde_json::from_value(self.call("getblockcount", json!([])).await?)?;
+
+        // blockhash, target_confirmations, include_watchonly, include_removed
+        let params = json!([lastblock.unwrap_or(""), 1, true, true]);
+        let (result, sha256) = self.call_hashed("listsinceblock", params).await?;
+        let response: Transactions<Value> = serde_json::from_value(result)?;
+
+        self.polled = true;
+        Ok(Some(Batch::Response { response, sha256, tip }))
+    }
+}
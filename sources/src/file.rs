@@ -0,0 +1,38 @@
+/*
+sources/src/file.rs
+10/18/26
+
+This file defines a deposit source backed by listsinceblock
+dumps on disk, one response per file. Files are not read here,
+the ingest pipeline streams them so they never sit in memory.
+*/
+use async_trait::async_trait;
+use config::InputFile;
+use std::collections::VecDeque;
+use std::error::Error;
+use crate::{ Batch, DepositSource };
+
+// This struct defines the file source
+pub struct FileSource {
+    files: VecDeque<InputFile>,
+}
+
+impl FileSource {
+    // Source constructor, files are replayed in the given order
+    pub fn new(files: Vec<InputFile>) -> Self {
+        Self { files: files.into() }
+    }
+}
+
+// File implementation of the Deposit Source trait
+#[async_trait]
+impl DepositSource for FileSource {
+    fn name(&self) -> String {
+        String::from("files")
+    }
+
+    // Files are snapshots taken at known points, so the cursor is not needed
+    async fn next_batch(&mut self, _lastblock: Option<&str>) -> Result<Option<Batch>, Box<dyn Error>> {
+        Ok(self.files.pop_front().map(Batch::File))
+    }
+}
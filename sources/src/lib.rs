@@ -0,0 +1,69 @@
+/*
+sources/src/lib.rs
+10/18/26
+
+This file defines where deposits are ingested from.
+Every source hands back listsinceblock responses, so static
+files and a live bitcoind feed the same ingest path.
+*/
+use async_trait::async_trait;
+use config::{ Config, InputFile };
+use models::Transactions;
+use serde_json::Value;
+use std::error::Error;
+
+mod bitcoind;
+mod file;
+pub mod mock;
+
+pub use bitcoind::BitcoindRpcSource;
+pub use file::FileSource;
+
+// One unit of work handed out by a source
+pub enum Batch {
+    // A listsinceblock response already held in memory, its entries
+    // not yet validated, with the SHA-256 of the body it was read from
+    // and the chain tip height the node reported just before
+    Response { response: Transactions<Value>, sha256: String, tip: i32 },
+    // A listsinceblock dump on disk, streamed by the ingest pipeline
+    File(InputFile),
+}
+
+impl Batch {
+    // Chain tip height the batch was taken at, when known up front
+    pub fn snapshot_tip(&self) -> Option<i32> {
+        match self {
+            Batch::Response { tip, .. } => Some(*tip),
+            Batch::File(file) => file.snapshot_tip,
+        }
+    }
+}
+
+// This trait defines the programmatic interface with a deposit source
+#[async_trait]
+pub trait DepositSource {
+    // Name of the source, used when logging
+    fn name(&self) -> String;
+    // Return the next batch after the given cursor,
+    // or None once the source has nothing more for this poll
+    async fn next_batch(&mut self, lastblock: Option<&str>) -> Result<Option<Batch>, Box<dyn Error>>;
+}
+
+// Build every source enabled in the configuration
+pub fn from_config(config: &Config) -> Result<Vec<Box<dyn DepositSource + Send>>, Box<dyn Error>> {
+    let mut sources: Vec<Box<dyn DepositSource + Send>> = Vec::new();
+
+    if !config.input_data.is_empty() {
+        sources.push(Box::new(FileSource::new(config.input_data.clone())));
+    }
+
+    if let Some(url) = &config.bitcoind_rpc_url {
+        sources.push(Box::new(BitcoindRpcSource::new(url, config.bitcoind_rpc_auth.as_ref())?));
+    }
+
+    if sources.is_empty() {
+        return Err(Box::from("No deposit source configured, set INPUT_DATA or BITCOIND_RPC_URL"));
+    }
+
+    Ok(sources)
+}
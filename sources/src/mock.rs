@@ -0,0 +1,262 @@
+/*
+sources/src/mock.rs
+10/18/26
+
+This file defines a minimal mock of bitcoind's JSON-RPC interface.
+It replays listsinceblock dumps in order, chaining them through
+their lastblock, so the RPC source can be exercised locally.
+getblockcount answers a height tests can advance without new
+transactions. listunspent and getbalances answer with what the replayed
+deposits and sends would leave in the wallet, sends spending
+the oldest deposits and returning the rest as change.
+*/
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{ json, Value };
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicI64, Ordering };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::{ TcpListener, TcpStream };
+
+// JSON-RPC error codes used by bitcoind
+const RPC_METHOD_NOT_FOUND: i64 = -32601;
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+// Address of the wallet the change of every send goes to
+pub const CHANGE_ADDRESS: &str = "mkHS9ne12qx9pS9VojpwU5xtRd4T7X7ZUt";
+
+// This struct defines the replayed node state
+pub struct MockBitcoind {
+    responses: Vec<Value>,
+    authorization: Option<String>,
+    tip: Arc<AtomicI64>,
+}
+
+impl MockBitcoind {
+    // Mock constructor, responses are listsinceblock results in chain order
+    pub fn new(responses: Vec<Value>, credentials: Option<(String, String)>) -> Self {
+        let authorization = credentials.map(|(user, password)|
+            format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password))));
+        let tip = Arc::new(AtomicI64::new(initial_tip(&responses)));
+        Self { responses, authorization, tip }
+    }
+
+    // Handle on the height getblockcount answers, so a test
+    // can mine blocks while no wallet transaction arrives
+    pub fn chain_tip(&self) -> Arc<AtomicI64> {
+        Arc::clone(&self.tip)
+    }
+
+    // Accept connections until the listener fails
+    pub async fn serve(self, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+        let mock = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let mock = Arc::clone(&mock);
+            tokio::spawn(async move {
+                if let Err(e) = mock.handle(stream).await {
+                    eprintln!("Mock bitcoind error: {}", e);
+                }
+            });
+        }
+    }
+
+    // Answer a single HTTP request
+    async fn handle(&self, mut stream: TcpStream) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (headers, body) = read_request(&mut stream).await?;
+
+        if let Some(expected) = &self.authorization {
+            let authorized = headers.lines().any(|line| match line.split_once(':') {
+                Some((name, value)) => name.eq_ignore_ascii_case("authorization")
+                    && value.trim() == expected,
+                None => false,
+            });
+            if !authorized {
+                let response = "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                stream.write_all(response.as_bytes()).await?;
+                return Ok(());
+            }
+        }
+
+        let request: Value = serde_json::from_slice(&body)?;
+        let (status, reply) = match self.dispatch(&request) {
+            Ok(result) => ("200 OK", json!({ "result": result, "error": null, "id": request["id"] })),
+            Err((code, message)) => ("500 Internal Server Error", json!({
+                "result": null,
+                "error": { "code": code, "message": message },
+                "id": request["id"],
+            })),
+        };
+
+        let reply = serde_json::to_vec(&reply)?;
+        let head = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status, reply.len());
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&reply).await?;
+        Ok(())
+    }
+
+    // Route a JSON-RPC request to the matching method
+    fn dispatch(&self, request: &Value) -> Result<Value, (i64, String)> {
+        match request["method"].as_str() {
+            Some("listsinceblock") => self.list_since_block(request["params"][0].as_str().unwrap_or("")),
+            Some("getblockcount") => Ok(json!(self.tip.load(Ordering::SeqCst))),
+            Some("listunspent") => Ok(self.list_unspent()),
+            Some("getbalances") => Ok(self.get_balances()),
+            Some(method) => Err((RPC_METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+            None => Err((RPC_METHOD_NOT_FOUND, String::from("Method not found"))),
+        }
+    }
+
+    // The first response answers an empty cursor, every later one answers
+    // the lastblock of the response before it. Past the end nothing is new
+    fn list_since_block(&self, blockhash: &str) -> Result<Value, (i64, String)> {
+        if blockhash.is_empty() {
+            return self.responses.first().cloned()
+                .ok_or((RPC_INVALID_ADDRESS_OR_KEY, String::from("No responses to replay")));
+        }
+
+        let position = self.responses.iter()
+            .position(|response| response["lastblock"].as_str() == Some(blockhash))
+            .ok_or((RPC_INVALID_ADDRESS_OR_KEY, String::from("Block not found")))?;
+
+        match self.responses.get(position + 1) {
+            Some(response) => Ok(response.clone()),
+            None => Ok(json!({ "transactions": [], "removed": [], "lastblock": blockhash })),
+        }
+    }
+
+    // Every replayed entry that still stands, later responses replacing
+    // earlier ones and entries removed by any response left out
+    fn standing_entries(&self) -> Vec<&Value> {
+        let removed: Vec<&Value> = self.responses.iter()
+            .filter_map(|response| response["removed"].as_array())
+            .flatten()
+            .map(|entry| &entry["txid"])
+            .collect();
+        let mut entries: Vec<&Value> = Vec::new();
+        for entry in self.responses.iter().filter_map(|response| response["transactions"].as_array()).flatten() {
+            entries.retain(|kept| kept["txid"] != entry["txid"] || kept["vout"] != entry["vout"]);
+            entries.push(entry);
+        }
+        entries.retain(|entry| !removed.contains(&&entry["txid"])
+            && entry["confirmations"].as_i64().unwrap_or(0) >= 0
+            && !entry["abandoned"].as_bool().unwrap_or(false));
+        entries
+    }
+
+    // Each send spends the oldest unspent deposits until its amount and fee
+    // are covered, and what is left over comes back as a change output of
+    // the send. Sends are assumed to have a single recipient
+    fn list_unspent(&self) -> Value {
+        let mut unspent: Vec<Value> = Vec::new();
+        for entry in self.standing_entries() {
+            // Coinbase outputs cannot be spent, nor are they listed, until they mature
+            if entry["category"] == "immature" {
+                continue;
+            }
+            if entry["category"] != "send" {
+                unspent.push(unspent_output(entry, &entry["address"], entry["vout"].as_i64().unwrap_or(0),
+                    entry["amount"].as_f64().unwrap_or(0.0)));
+                continue;
+            }
+
+            let spent = -(entry["amount"].as_f64().unwrap_or(0.0) + entry["fee"].as_f64().unwrap_or(0.0));
+            let mut inputs = 0.0;
+            while inputs < spent && !unspent.is_empty() {
+                inputs += unspent.remove(0)["amount"].as_f64().unwrap_or(0.0);
+            }
+            let change = ((inputs - spent) * 1e8).round() / 1e8;
+            if change > 0.0 {
+                let mut output = unspent_output(entry, &json!(CHANGE_ADDRESS),
+                    entry["vout"].as_i64().unwrap_or(0) + 1, change);
+                output["label"] = json!("");
+                unspent.push(output);
+            }
+        }
+        Value::from(unspent)
+    }
+
+    // Sends and their fees are negative, so the sum of all standing
+    // entries is what the wallet holds
+    fn get_balances(&self) -> Value {
+        let (mut trusted, mut untrusted_pending, mut immature) = (0.0, 0.0, 0.0);
+        for entry in self.standing_entries() {
+            let amount = entry["amount"].as_f64().unwrap_or(0.0) + entry["fee"].as_f64().unwrap_or(0.0);
+            match (entry["category"].as_str(), entry["confirmations"].as_i64().unwrap_or(0)) {
+                (Some("immature"), _) => immature += amount,
+                (_, 0) => untrusted_pending += amount,
+                _ => trusted += amount,
+            }
+        }
+        json!({ "mine": { "trusted": trusted, "untrusted_pending": untrusted_pending, "immature": immature } })
+    }
+}
+
+// The tip the replayed entries imply, from the heights they carry. Dumps
+// without heights are taken as a chain whose deepest block is the first
+fn initial_tip(responses: &[Value]) -> i64 {
+    let entries: Vec<&Value> = responses.iter()
+        .filter_map(|response| response["transactions"].as_array())
+        .flatten()
+        .collect();
+    let implied = entries.iter()
+        .filter_map(|entry| Some(entry["blockheight"].as_i64()? + entry["confirmations"].as_i64()? - 1))
+        .max();
+    implied.unwrap_or_else(|| entries.iter()
+        .filter_map(|entry| entry["confirmations"].as_i64())
+        .max()
+        .unwrap_or(0))
+}
+
+// An output of the entry's transaction as listunspent reports it
+fn unspent_output(entry: &Value, address: &Value, vout: i64, amount: f64) -> Value {
+    json!({
+        "txid": entry["txid"],
+        "vout": vout,
+        "address": address,
+        "label": entry["label"],
+        "amount": amount,
+        "confirmations": entry["confirmations"],
+        "spendable": true,
+        "solvable": true,
+        "safe": entry["confirmations"].as_i64().unwrap_or(0) > 0,
+    })
+}
+
+// Read the request headers and a Content-Length delimited body
+async fn read_request(stream: &mut TcpStream) -> Result<(String, Vec<u8>), Box<dyn Error + Send + Sync>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(Box::from("Connection closed before the request was complete"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let content_length = headers.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()?
+        .unwrap_or(0);
+
+    let mut body = buffer.split_off(header_end);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(Box::from("Connection closed before the body was complete"));
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+
+    Ok((headers, body))
+}
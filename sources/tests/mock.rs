@@ -0,0 +1,115 @@
+/*
+sources/tests/mock.rs
+10/18/26
+
+This file tests the bitcoind source against the mock server,
+replaying two dumps the way successive polls would see them.
+*/
+use serde_json::{ Value, json };
+use sha2::{ Digest, Sha256 };
+use sources::mock::MockBitcoind;
+use sources::{ Batch, BitcoindRpcSource, DepositSource };
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::atomic::Ordering;
+use tokio::net::TcpListener;
+
+// Parse a listsinceblock dump from the repository
+fn dump(path: &str) -> Value {
+    let path = format!("{}/../{}", env!("CARGO_MANIFEST_DIR"), path);
+    serde_json::from_reader(BufReader::new(File::open(path).unwrap())).unwrap()
+}
+
+// Serve the dumps on a free port and return a source polling it
+async fn source(responses: Vec<Value>) -> BitcoindRpcSource {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mock = MockBitcoind::new(responses, None);
+    tokio::spawn(async move {
+        let _ = mock.serve(listener).await;
+    });
+    BitcoindRpcSource::new(&url, None).unwrap()
+}
+
+// Poll once, expecting a response followed by the end of the poll
+async fn poll(source: &mut BitcoindRpcSource, lastblock: Option<&str>) -> models::Transactions<Value> {
+    let response = match source.next_batch(lastblock).await.unwrap() {
+        Some(Batch::Response { response, .. }) => response,
+        _ => panic!("expected a listsinceblock response"),
+    };
+    assert!(source.next_batch(lastblock).await.unwrap().is_none());
+    response
+}
+
+#[tokio::test]
+async fn replays_dumps_chained_by_lastblock() {
+    let first = dump("transactions-1.json");
+    let second = dump("fixtures/bitcoind-24.0-listsinceblock.json");
+    let mut source = source(vec![first.clone(), second.clone()]).await;
+
+    // Without a cursor the first dump is returned
+    let response = poll(&mut source, None).await;
+    assert_eq!(response.transactions.len(), first["transactions"].as_array().unwrap().len());
+    assert_eq!(response.lastblock.as_deref(), first["lastblock"].as_str());
+
+    // Its lastblock leads to the second, removed entries included
+    let response = poll(&mut source, first["lastblock"].as_str()).await;
+    assert_eq!(response.transactions.len(), 2);
+    assert_eq!(response.removed.len(), 1);
+    assert_eq!(response.removed[0].txid, second["removed"][0]["txid"].as_str().unwrap());
+    assert_eq!(response.removed[0].confirmations, -1);
+    assert_eq!(response.lastblock.as_deref(), second["lastblock"].as_str());
+
+    // Past the last dump nothing is new and the cursor stays put
+    let response = poll(&mut source, second["lastblock"].as_str()).await;
+    assert!(response.transactions.is_empty() && response.removed.is_empty());
+    assert_eq!(response.lastblock.as_deref(), second["lastblock"].as_str());
+}
+
+#[tokio::test]
+async fn unknown_cursor_is_an_error() {
+    let mut source = source(vec![dump("transactions-1.json")]).await;
+    let error = source.next_batch(Some("00")).await.err().expect("an unknown block is rejected");
+    assert!(error.to_string().contains("Block not found"), "{}", error);
+}
+
+#[tokio::test]
+async fn response_hash_covers_the_body_received() {
+    let first = dump("transactions-1.json");
+    let mut source = source(vec![first.clone()]).await;
+    let sha256 = match source.next_batch(None).await.unwrap() {
+        Some(Batch::Response { sha256, .. }) => sha256,
+        _ => panic!("expected a listsinceblock response"),
+    };
+
+    // The mock answers with this envelope, serialized as is
+    let body = serde_json::to_vec(&json!({ "result": first, "error": null, "id": "kobayashi-maru" })).unwrap();
+    assert_eq!(sha256, format!("{:x}", Sha256::digest(body)));
+}
+
+#[tokio::test]
+async fn every_poll_reports_the_node_tip() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let second = dump("fixtures/bitcoind-24.0-listsinceblock.json");
+    let mock = MockBitcoind::new(vec![second.clone()], None);
+    let tip = mock.chain_tip();
+    tokio::spawn(async move {
+        let _ = mock.serve(listener).await;
+    });
+    let mut source = BitcoindRpcSource::new(&url, None).unwrap();
+
+    // The tip its rows imply, block 2399992 with 9 confirmations
+    let batch = source.next_batch(None).await.unwrap().expect("a response");
+    assert_eq!(batch.snapshot_tip(), Some(2400000));
+    assert!(source.next_batch(None).await.unwrap().is_none());
+
+    // Blocks mined since, with nothing new for the wallet
+    tip.fetch_add(3, Ordering::SeqCst);
+    let batch = source.next_batch(second["lastblock"].as_str()).await.unwrap().expect("a response");
+    assert_eq!(batch.snapshot_tip(), Some(2400003));
+    match batch {
+        Batch::Response { response, .. } => assert!(response.transactions.is_empty()),
+        _ => panic!("expected a listsinceblock response"),
+    }
+}